@@ -0,0 +1,94 @@
+use std::{collections::BTreeSet, fmt::Write};
+
+use crate::{
+    data::write_data, print::Print, symbols::Named, utils::render, write_listing, Analysis,
+    Microcode, PrintOpts, RspDisasmError, Syntax,
+};
+
+/// Where armips should write the assembled microcode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArmipsFile {
+    /// binary written by armips
+    pub output: String,
+    /// existing file (e.g. a ROM) to patch into `output`;
+    /// if `None`, `output` is created from scratch
+    pub input: Option<String>,
+    /// offset of the microcode in the output file
+    pub file_offset: u32,
+    /// binary written by armips for the DMEM, if it was disassembled
+    pub data_output: String,
+}
+
+impl ArmipsFile {
+    pub fn create(output: impl Into<String>) -> Self {
+        Self {
+            output: output.into(),
+            input: None,
+            file_offset: 0,
+            data_output: String::from("rsp_data.bin"),
+        }
+    }
+}
+
+/// Disassemble `ucode` into a complete armips source file that rebuilds it.
+///
+/// DMEM, if any, is written to a second binary as data, labeled where the code
+/// loads or stores it.
+pub fn disassemble_armips(
+    ucode: &Microcode,
+    opts: PrintOpts,
+    file: &ArmipsFile,
+) -> Result<String, RspDisasmError> {
+    let opts = PrintOpts {
+        syntax: Syntax::Armips,
+        ..opts
+    };
+    let analysis = Analysis::new(ucode)?;
+    let f = Named::new(&opts, ucode.symbols);
+    let range = ucode.vaddr..ucode.vaddr.wrapping_add(ucode.imem.len() as u32);
+
+    let mut s = String::with_capacity(analysis.instrs.len() * 32 + 256);
+    writeln!(&mut s, ".rsp").unwrap();
+    match &file.input {
+        Some(input) => writeln!(&mut s, ".open \"{}\", \"{}\", 0", input, file.output),
+        None => writeln!(&mut s, ".create \"{}\", 0", file.output),
+    }
+    .unwrap();
+    writeln!(&mut s).unwrap();
+
+    // branch and jump targets that are not part of this file
+    let external = analysis
+        .syms
+        .iter()
+        .filter(|sym| !range.contains(&sym.value()))
+        .map(|sym| (sym.value(), render(|s| sym.print(&f, s))))
+        .collect::<BTreeSet<_>>();
+    for (addr, name) in &external {
+        writeln!(&mut s, ".definelabel {}, {:#010X}", name, addr).unwrap();
+    }
+    if !external.is_empty() {
+        writeln!(&mut s).unwrap();
+    }
+
+    writeln!(
+        &mut s,
+        ".headersize {:#010X}",
+        ucode.vaddr.wrapping_sub(file.file_offset)
+    )
+    .unwrap();
+    writeln!(&mut s, ".org {:#010X}", ucode.vaddr).unwrap();
+    write_listing(&mut s, &analysis, &f).unwrap();
+    writeln!(&mut s).unwrap();
+    writeln!(&mut s, ".close").unwrap();
+
+    if let Some(dmem) = ucode.dmem {
+        writeln!(&mut s).unwrap();
+        writeln!(&mut s, ".create \"{}\", 0", file.data_output).unwrap();
+        writeln!(&mut s, ".headersize 0").unwrap();
+        writeln!(&mut s, ".org 0").unwrap();
+        write_data(&mut s, dmem, &analysis.data, &f).unwrap();
+        writeln!(&mut s, ".close").unwrap();
+    }
+
+    Ok(s)
+}
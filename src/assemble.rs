@@ -0,0 +1,861 @@
+//! A small assembler for the syntax printed by the disassembler.
+//!
+//! This is not a general purpose assembler: it understands exactly what
+//! `disassemble_bytes` emits (labels, `/* addr word */` comments, and the
+//! instruction syntax of the default [`PrintOpts`](crate::PrintOpts)),
+//! so that a disassembly can be checked against the original binary.
+//! Single instructions can also be read in bass syntax.
+
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    regs::{cop0::Cop0Reg, su::GpReg, vu::VUCtrlReg},
+    Syntax,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    /// one-based source line of the error
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmErrorKind {
+    UnknownMnemonic(String),
+    OperandCount { expected: usize, found: usize },
+    BadOperand(String),
+    UndefinedLabel(String),
+    DuplicateLabel(String),
+    OutOfRange(i64),
+    UnalignedTarget(u32),
+    NoInstruction,
+}
+
+impl fmt::Display for AsmErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownMnemonic(m) => write!(f, "unknown mnemonic `{}`", m),
+            Self::OperandCount { expected, found } => {
+                write!(f, "expected {} operands, found {}", expected, found)
+            }
+            Self::BadOperand(o) => write!(f, "could not parse operand `{}`", o),
+            Self::UndefinedLabel(l) => write!(f, "undefined label `{}`", l),
+            Self::DuplicateLabel(l) => write!(f, "label `{}` defined more than once", l),
+            Self::OutOfRange(v) => write!(f, "value {:#x} does not fit in its field", v),
+            Self::UnalignedTarget(t) => write!(f, "target {:#010X} is not word aligned", t),
+            Self::NoInstruction => write!(f, "line does not contain an instruction"),
+        }
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Assemble `src` into instruction words, with the first instruction at `vaddr`.
+///
+/// Of the armips directives written by
+/// [`disassemble_armips`](crate::disassemble_armips), `.definelabel`, `.org`,
+/// `.func` and `.word` are understood, `.db`, `.dh` and `.dw` only move the
+/// address of later labels, and the others are ignored.
+pub fn assemble(src: &str, vaddr: u32) -> Result<Vec<u32>, AsmError> {
+    let at_line = |i: usize| move |kind| AsmError { line: i + 1, kind };
+
+    // first pass: find the address of every label
+    let mut labels = HashMap::new();
+    let mut pc = vaddr;
+    for (i, line) in src.lines().enumerate() {
+        let (label, instr) = split_line(line);
+        if let Some(label) = label {
+            if labels.insert(label, pc).is_some() {
+                return Err(at_line(i)(AsmErrorKind::DuplicateLabel(label.to_string())));
+            }
+        }
+        match instr.map(directive) {
+            Some(Some((".definelabel", args))) => {
+                let (name, value) = args
+                    .split_once(',')
+                    .and_then(|(name, v)| Some((name.trim(), parse_int(v.trim())?)))
+                    .ok_or_else(|| at_line(i)(bad(args)))?;
+                labels.insert(name, value as u32);
+            }
+            Some(Some((".org", args))) => {
+                pc = parse_int(args).ok_or_else(|| at_line(i)(bad(args)))? as u32;
+            }
+            // `.func name` defines `name`
+            Some(Some((".func", name))) => {
+                if labels.insert(name.trim(), pc).is_some() {
+                    return Err(at_line(i)(AsmErrorKind::DuplicateLabel(
+                        name.trim().to_string(),
+                    )));
+                }
+            }
+            Some(Some((".word", _))) | Some(None) => pc = pc.wrapping_add(4),
+            Some(Some((name @ (".db" | ".dh" | ".dw"), args))) => {
+                pc = pc.wrapping_add(data_size(name, args));
+            }
+            Some(Some(_)) | None => (),
+        }
+    }
+
+    // second pass: encode
+    let mut words = Vec::new();
+    let mut pc = vaddr;
+    for (i, line) in src.lines().enumerate() {
+        let instr = match split_line(line) {
+            (_, Some(instr)) => instr,
+            (_, None) => continue,
+        };
+        match directive(instr) {
+            Some((".org", args)) => {
+                pc = parse_int(args).ok_or_else(|| at_line(i)(bad(args)))? as u32;
+            }
+            Some((".word", _)) | None => {
+                let word = encode_line(instr, pc, &labels, Syntax::Armips);
+                words.push(word.map_err(at_line(i))?);
+                pc = pc.wrapping_add(4);
+            }
+            Some((name @ (".db" | ".dh" | ".dw"), args)) => {
+                pc = pc.wrapping_add(data_size(name, args));
+            }
+            Some(_) => (),
+        }
+    }
+
+    Ok(words)
+}
+
+/// bytes taken up by a `.db`, `.dh` or `.dw` directive, which are not assembled
+fn data_size(name: &str, args: &str) -> u32 {
+    let size = match name {
+        ".db" => 1,
+        ".dh" => 2,
+        _ => 4,
+    };
+    args.split(',').count() as u32 * size
+}
+
+/// split an assembler directive into its name and arguments
+fn directive(instr: &str) -> Option<(&str, &str)> {
+    if !instr.starts_with('.') {
+        return None;
+    }
+    let (name, args) = instr.split_once(char::is_whitespace).unwrap_or((instr, ""));
+
+    Some((name, args.trim()))
+}
+
+/// Assemble a single instruction at `pc`.
+///
+/// Branch and jump targets can only refer to the generated `subr_`/`handler_`/`@L` names
+/// or to numeric addresses.
+pub fn assemble_instruction(src: &str, pc: u32) -> Result<u32, AsmErrorKind> {
+    assemble_instruction_as(src, pc, Syntax::Armips)
+}
+
+/// Assemble a single instruction at `pc`, written in armips or bass `syntax`.
+///
+/// GNU syntax is read as armips, which it only shares for the scalar ops.
+pub fn assemble_instruction_as(src: &str, pc: u32, syntax: Syntax) -> Result<u32, AsmErrorKind> {
+    match split_line(src) {
+        (_, Some(instr)) => encode_line(instr, pc, &HashMap::new(), syntax),
+        (_, None) => Err(AsmErrorKind::NoInstruction),
+    }
+}
+
+/// split a line into an optional label and optional instruction text,
+/// dropping comments
+fn split_line(line: &str) -> (Option<&str>, Option<&str>) {
+    let line = strip_comments(line);
+    let is_label = |s: &str| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '@' | '.'))
+    };
+    let (label, rest) = match line.split_once(':') {
+        Some((label, rest)) if is_label(label.trim()) => (Some(label.trim()), rest),
+        _ => (None, line),
+    };
+    let rest = rest.trim();
+
+    (label, if rest.is_empty() { None } else { Some(rest) })
+}
+
+fn strip_comments(mut line: &str) -> &str {
+    // `/* addr word */` prefix
+    while let Some(start) = line.find("/*") {
+        match line[start..].find("*/") {
+            Some(end) => {
+                line = if start == 0 {
+                    &line[end + 2..]
+                } else {
+                    &line[..start]
+                }
+            }
+            None => line = &line[..start],
+        }
+    }
+    // line comments
+    match line.find(';').or_else(|| line.find("//")) {
+        Some(i) => &line[..i],
+        None => line,
+    }
+}
+
+fn encode_line(
+    src: &str,
+    pc: u32,
+    labels: &HashMap<&str, u32>,
+    syntax: Syntax,
+) -> Result<u32, AsmErrorKind> {
+    let (mnemonic, rest) = src.split_once(char::is_whitespace).unwrap_or((src, ""));
+    let mnemonic = mnemonic.to_ascii_lowercase();
+    let operands = rest
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>();
+
+    let format = lookup(&mnemonic).ok_or(AsmErrorKind::UnknownMnemonic(mnemonic))?;
+    let args = Args {
+        ops: &operands,
+        pc,
+        labels,
+        syntax,
+    };
+
+    format.encode(&args)
+}
+
+struct Args<'a> {
+    ops: &'a [&'a str],
+    pc: u32,
+    labels: &'a HashMap<&'a str, u32>,
+    syntax: Syntax,
+}
+
+impl<'a> Args<'a> {
+    fn expect(&self, n: usize) -> Result<(), AsmErrorKind> {
+        if self.ops.len() == n {
+            Ok(())
+        } else {
+            Err(AsmErrorKind::OperandCount {
+                expected: n,
+                found: self.ops.len(),
+            })
+        }
+    }
+
+    fn gp(&self, i: usize) -> Result<u32, AsmErrorKind> {
+        parse_gp(self.ops[i])
+            .map(|r| r as u32)
+            .ok_or_else(|| bad(self.ops[i]))
+    }
+
+    fn target(&self, i: usize) -> Result<u32, AsmErrorKind> {
+        let s = self.ops[i];
+        let addr = self
+            .labels
+            .get(s)
+            .copied()
+            .or_else(|| generated_label(s))
+            .or_else(|| parse_int(s).map(|v| v as u32))
+            .ok_or_else(|| AsmErrorKind::UndefinedLabel(s.to_string()))?;
+
+        if addr & 3 != 0 {
+            Err(AsmErrorKind::UnalignedTarget(addr))
+        } else {
+            Ok(addr)
+        }
+    }
+
+    fn branch(&self, i: usize) -> Result<u32, AsmErrorKind> {
+        let target = self.target(i)?;
+        let delta = (target.wrapping_sub(self.pc.wrapping_add(4)) as i32) >> 2;
+        signed(delta as i64, 16)
+    }
+
+    fn imm(&self, i: usize) -> Result<i64, AsmErrorKind> {
+        parse_int(self.ops[i]).ok_or_else(|| bad(self.ops[i]))
+    }
+
+    /// `offset(base)`
+    fn mem(&self, i: usize) -> Result<(i64, u32), AsmErrorKind> {
+        let s = self.ops[i];
+        let (offset, base) = s
+            .strip_suffix(')')
+            .and_then(|s| s.rsplit_once('('))
+            .ok_or_else(|| bad(s))?;
+        let offset = if offset.trim().is_empty() {
+            0
+        } else {
+            self.disp(offset.trim())?
+        };
+        let base = parse_gp(base.trim()).ok_or_else(|| bad(s))?;
+
+        Ok((offset, base as u32))
+    }
+
+    /// `offset(base)` of a vector load/store, with the offset in bytes
+    fn vector_mem(&self, i: usize, size: u32) -> Result<(i64, u32), AsmErrorKind> {
+        let (offset, base) = self.mem(i)?;
+        match self.syntax {
+            // bass writes the raw field, counted in items
+            Syntax::Bass => Ok((offset * size as i64, base)),
+            _ => Ok((offset, base)),
+        }
+    }
+
+    /// a load/store offset: a number, or a label plus or minus one,
+    /// optionally as `(disp)/size`
+    fn disp(&self, s: &str) -> Result<i64, AsmErrorKind> {
+        if let Some(v) = parse_int(s) {
+            return Ok(v);
+        }
+        if let Some((d, size)) = s.strip_prefix('(').and_then(|s| s.rsplit_once(")/")) {
+            let size = parse_int(size).filter(|&n| n > 0).ok_or_else(|| bad(s))?;
+            let d = self.disp(d)?;
+            return match d % size {
+                0 => Ok(d / size),
+                _ => Err(AsmErrorKind::OutOfRange(d)),
+            };
+        }
+        let (label, delta) = match s.rfind(['+', '-']).filter(|&i| i > 0) {
+            Some(i) => {
+                let delta = s[i..].strip_prefix('+').unwrap_or(&s[i..]);
+                (&s[..i], parse_int(delta).ok_or_else(|| bad(s))?)
+            }
+            None => (s, 0),
+        };
+        let addr = self
+            .labels
+            .get(label)
+            .copied()
+            .or_else(|| generated_label(label))
+            .ok_or_else(|| AsmErrorKind::UndefinedLabel(label.to_string()))?;
+
+        Ok(addr as i32 as i64 + delta)
+    }
+
+    /// `$vN` (`vN` in bass) with an optional `[suffix]`
+    fn vreg(&self, i: usize) -> Result<(u32, Option<&'a str>), AsmErrorKind> {
+        let s = self.ops[i];
+        let (reg, suffix) = match s.split_once('[') {
+            Some((reg, rest)) => (reg, Some(rest.strip_suffix(']').ok_or_else(|| bad(s))?)),
+            None => (s, None),
+        };
+        let reg = reg.trim();
+        let n = reg
+            .strip_prefix('$')
+            .unwrap_or(reg)
+            .strip_prefix('v')
+            .and_then(|n| n.parse::<u32>().ok())
+            .filter(|&n| n < 32)
+            .ok_or_else(|| bad(s))?;
+
+        Ok((n, suffix.map(str::trim)))
+    }
+
+    /// vector register with a compute element suffix (`[3h]`, or the raw `[e13]`)
+    fn vreg_element(&self, i: usize) -> Result<(u32, u32), AsmErrorKind> {
+        let (reg, suffix) = self.vreg(i)?;
+        let e = match suffix {
+            None => 0,
+            Some(s) if s.starts_with('e') => parse_int(&s[1..])
+                .filter(|v| (0..16).contains(v))
+                .ok_or_else(|| bad(self.ops[i]))?
+                as u32,
+            Some(s) => parse_element(s).ok_or_else(|| bad(self.ops[i]))?,
+        };
+
+        Ok((reg, e))
+    }
+
+    /// vector register with a lane index (`[4]`, or `[e4]`)
+    fn vreg_index(&self, i: usize) -> Result<(u32, u32), AsmErrorKind> {
+        let (reg, suffix) = self.vreg(i)?;
+        let idx = match suffix {
+            None => 0,
+            Some(s) => parse_int(s.strip_prefix('e').unwrap_or(s))
+                .filter(|v| (0..16).contains(v))
+                .ok_or_else(|| bad(self.ops[i]))? as u32,
+        };
+
+        Ok((reg, idx))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    /// `op rd, rs, rt`
+    ThreeReg(u32),
+    /// `op rd, rt, rs`
+    ShiftReg(u32),
+    /// `op rd, rt, sa`
+    ShiftImm(u32),
+    Jr,
+    /// `jalr [rd,] rs`
+    Jalr,
+    Break,
+    /// `op rs, target` with the sub-op in `rt`
+    RegImm(u32),
+    Jump(u32),
+    /// `op rs, rt, target`
+    BrTwoReg(u32),
+    /// `op rs, target`
+    BrOneReg(u32),
+    /// `op rt, rs, imm` with a sign extended immediate
+    ArithImm(u32),
+    /// `op rt, rs, imm` with a zero extended immediate
+    LogicImm(u32),
+    Lui,
+    /// `op rt, offset(base)`
+    LoadStore(u32),
+    /// `op rt, cop0reg` with the direction in `rs`
+    Cop0(u32),
+    /// `op rt, $vN[e]` with the direction in `rs`
+    MoveVU(u32),
+    /// `op rt, $vcX` with the direction in `rs`
+    CtrlVU(u32),
+    /// `op $vN[e], offset(base)` for an addressing mode and item size
+    Cop2LoadStore(u32, u32, u32),
+    /// `op vd, vs, vt[e]`
+    VCompute(u32),
+    /// `op vd[de], vt[e]`
+    VScalar(u32),
+    Nop,
+    VNop,
+    /// `.word value` for data or unsupported instructions
+    Word,
+}
+
+const SPECIAL: u32 = 0x00;
+const REGIMM: u32 = 0x01;
+const COP0: u32 = 0x10;
+const COP2: u32 = 0x12;
+const LWC2: u32 = 0x32;
+const SWC2: u32 = 0x3A;
+
+fn lookup(mnemonic: &str) -> Option<Format> {
+    use Format::*;
+
+    let f = match mnemonic {
+        "nop" => Nop,
+        "sll" => ShiftImm(0x00),
+        "srl" => ShiftImm(0x02),
+        "sra" => ShiftImm(0x03),
+        "sllv" => ShiftReg(0x04),
+        "srlv" => ShiftReg(0x06),
+        "srav" => ShiftReg(0x07),
+        "jr" => Jr,
+        "jalr" => Jalr,
+        "break" => Break,
+        "add" => ThreeReg(0x20),
+        "addu" => ThreeReg(0x21),
+        "sub" => ThreeReg(0x22),
+        "subu" => ThreeReg(0x23),
+        "and" => ThreeReg(0x24),
+        "or" => ThreeReg(0x25),
+        "xor" => ThreeReg(0x26),
+        "nor" => ThreeReg(0x27),
+        "slt" => ThreeReg(0x2A),
+        "sltu" => ThreeReg(0x2B),
+        "bltz" => RegImm(0x00),
+        "bgez" => RegImm(0x01),
+        "bltzal" => RegImm(0x10),
+        "bgezal" => RegImm(0x11),
+        "j" => Jump(0x02),
+        "jal" => Jump(0x03),
+        "beq" => BrTwoReg(0x04),
+        "bne" => BrTwoReg(0x05),
+        "blez" => BrOneReg(0x06),
+        "bgtz" => BrOneReg(0x07),
+        "addi" => ArithImm(0x08),
+        "addiu" => ArithImm(0x09),
+        "slti" => ArithImm(0x0A),
+        "sltiu" => ArithImm(0x0B),
+        "andi" => LogicImm(0x0C),
+        "ori" => LogicImm(0x0D),
+        "xori" => LogicImm(0x0E),
+        "lui" => Lui,
+        "mfc0" => Cop0(0x00),
+        "mtc0" => Cop0(0x04),
+        "mfc2" => MoveVU(0x00),
+        "mtc2" => MoveVU(0x04),
+        "cfc2" => CtrlVU(0x02),
+        "ctc2" => CtrlVU(0x06),
+        "lb" => LoadStore(0x20),
+        "lh" => LoadStore(0x21),
+        "lw" => LoadStore(0x23),
+        "lbu" => LoadStore(0x24),
+        "lhu" => LoadStore(0x25),
+        "lwu" => LoadStore(0x27),
+        "sb" => LoadStore(0x28),
+        "sh" => LoadStore(0x29),
+        "sw" => LoadStore(0x2B),
+        "vnop" => VNop,
+        ".word" | "dd" => Word,
+        m => return cop2_load_store(m).or_else(|| vector_op(m)),
+    };
+
+    Some(f)
+}
+
+fn cop2_load_store(m: &str) -> Option<Format> {
+    let mut chars = m.chars();
+    let op = match chars.next()? {
+        'l' => LWC2,
+        's' => SWC2,
+        _ => return None,
+    };
+    let (mode, size) = match chars.next()? {
+        'b' => (0, 1),
+        's' => (1, 2),
+        'l' => (2, 4),
+        'd' => (3, 8),
+        'q' => (4, 16),
+        'r' => (5, 16),
+        'p' => (6, 8),
+        'u' => (7, 8),
+        'h' => (8, 16),
+        'f' => (9, 16),
+        'w' => (10, 16),
+        't' => (11, 16),
+        _ => return None,
+    };
+    if chars.as_str() != "v" {
+        return None;
+    }
+
+    Some(Format::Cop2LoadStore(op, mode, size))
+}
+
+fn vector_op(m: &str) -> Option<Format> {
+    use Format::*;
+
+    let f = match m {
+        "vmulf" => VCompute(0x00),
+        "vmulu" => VCompute(0x01),
+        "vrndp" => VCompute(0x02),
+        "vmulq" => VCompute(0x03),
+        "vmudl" => VCompute(0x04),
+        "vmudm" => VCompute(0x05),
+        "vmudn" => VCompute(0x06),
+        "vmudh" => VCompute(0x07),
+        "vmacf" => VCompute(0x08),
+        "vmacu" => VCompute(0x09),
+        "vrndn" => VCompute(0x0A),
+        "vmacq" => VCompute(0x0B),
+        "vmadl" => VCompute(0x0C),
+        "vmadm" => VCompute(0x0D),
+        "vmadn" => VCompute(0x0E),
+        "vmadh" => VCompute(0x0F),
+        "vadd" => VCompute(0x10),
+        "vsub" => VCompute(0x11),
+        "vabs" => VCompute(0x13),
+        "vaddc" => VCompute(0x14),
+        "vsubc" => VCompute(0x15),
+        "vsar" => VCompute(0x1D),
+        "vlt" => VCompute(0x20),
+        "veq" => VCompute(0x21),
+        "vne" => VCompute(0x22),
+        "vge" => VCompute(0x23),
+        "vcl" => VCompute(0x24),
+        "vch" => VCompute(0x25),
+        "vcr" => VCompute(0x26),
+        "vmrg" => VCompute(0x27),
+        "vand" => VCompute(0x28),
+        "vnand" => VCompute(0x29),
+        "vor" => VCompute(0x2A),
+        "vnor" => VCompute(0x2B),
+        "vxor" => VCompute(0x2C),
+        "vnxor" => VCompute(0x2D),
+        "vrcp" => VScalar(0x30),
+        "vrcpl" => VScalar(0x31),
+        "vrcph" => VScalar(0x32),
+        "vmov" => VScalar(0x33),
+        "vrsq" => VScalar(0x34),
+        "vrsql" => VScalar(0x35),
+        "vrsqh" => VScalar(0x36),
+        _ => return None,
+    };
+
+    Some(f)
+}
+
+impl Format {
+    fn encode(self, a: &Args) -> Result<u32, AsmErrorKind> {
+        use Format::*;
+
+        let word = match self {
+            Nop => {
+                a.expect(0)?;
+                0
+            }
+            ThreeReg(funct) => {
+                a.expect(3)?;
+                (SPECIAL << 26) | (a.gp(1)? << 21) | (a.gp(2)? << 16) | (a.gp(0)? << 11) | funct
+            }
+            ShiftReg(funct) => {
+                a.expect(3)?;
+                (SPECIAL << 26) | (a.gp(2)? << 21) | (a.gp(1)? << 16) | (a.gp(0)? << 11) | funct
+            }
+            ShiftImm(funct) => {
+                a.expect(3)?;
+                let sa = unsigned(a.imm(2)?, 5)?;
+                (SPECIAL << 26) | (a.gp(1)? << 16) | (a.gp(0)? << 11) | (sa << 6) | funct
+            }
+            Jr => {
+                a.expect(1)?;
+                (SPECIAL << 26) | (a.gp(0)? << 21) | 0x08
+            }
+            Jalr => {
+                let (rd, rs) = match a.ops.len() {
+                    1 => (GpReg::RA as u32, a.gp(0)?),
+                    _ => {
+                        a.expect(2)?;
+                        (a.gp(0)?, a.gp(1)?)
+                    }
+                };
+                (SPECIAL << 26) | (rs << 21) | (rd << 11) | 0x09
+            }
+            Break => {
+                let code = match a.ops.len() {
+                    0 => 0,
+                    _ => {
+                        a.expect(1)?;
+                        unsigned(a.imm(0)?, 20)?
+                    }
+                };
+                (SPECIAL << 26) | (code << 6) | 0x0D
+            }
+            RegImm(sub) => {
+                a.expect(2)?;
+                (REGIMM << 26) | (a.gp(0)? << 21) | (sub << 16) | a.branch(1)?
+            }
+            Jump(op) => {
+                a.expect(1)?;
+                (op << 26) | ((a.target(0)? >> 2) & 0x03FFFFFF)
+            }
+            BrTwoReg(op) => {
+                a.expect(3)?;
+                (op << 26) | (a.gp(0)? << 21) | (a.gp(1)? << 16) | a.branch(2)?
+            }
+            BrOneReg(op) => {
+                a.expect(2)?;
+                (op << 26) | (a.gp(0)? << 21) | a.branch(1)?
+            }
+            ArithImm(op) | LogicImm(op) => {
+                a.expect(3)?;
+                // accept both signed and unsigned spellings of the 16-bit field
+                let imm = signed_or_unsigned(a.imm(2)?, 16)?;
+                (op << 26) | (a.gp(1)? << 21) | (a.gp(0)? << 16) | imm
+            }
+            Lui => {
+                a.expect(2)?;
+                let imm = signed_or_unsigned(a.imm(1)?, 16)?;
+                (0x0F << 26) | (a.gp(0)? << 16) | imm
+            }
+            LoadStore(op) => {
+                a.expect(2)?;
+                let (offset, base) = a.mem(1)?;
+                let offset = signed_or_unsigned(offset, 16)?;
+                (op << 26) | (base << 21) | (a.gp(0)? << 16) | offset
+            }
+            Cop0(dir) => {
+                a.expect(2)?;
+                let rd = parse_cop0(a.ops[1]).ok_or_else(|| bad(a.ops[1]))? as u32;
+                (COP0 << 26) | (dir << 21) | (a.gp(0)? << 16) | (rd << 11)
+            }
+            MoveVU(dir) => {
+                a.expect(2)?;
+                let (vd, e) = a.vreg_index(1)?;
+                (COP2 << 26) | (dir << 21) | (a.gp(0)? << 16) | (vd << 11) | (e << 7)
+            }
+            CtrlVU(dir) => {
+                a.expect(2)?;
+                let vs = parse_vu_ctrl(a.ops[1]).ok_or_else(|| bad(a.ops[1]))? as u32;
+                (COP2 << 26) | (dir << 21) | (a.gp(0)? << 16) | (vs << 11)
+            }
+            Cop2LoadStore(op, mode, size) => {
+                a.expect(2)?;
+                let (vt, e) = a.vreg_index(0)?;
+                let (offset, base) = a.vector_mem(1, size)?;
+                if offset % size as i64 != 0 {
+                    return Err(AsmErrorKind::OutOfRange(offset));
+                }
+                let offset = signed(offset / size as i64, 7)?;
+                (op << 26) | (base << 21) | (vt << 16) | (mode << 11) | (e << 7) | offset
+            }
+            VCompute(funct) => {
+                a.expect(3)?;
+                let (vd, _) = a.vreg(0)?;
+                let (vs, _) = a.vreg(1)?;
+                let (vt, e) = a.vreg_element(2)?;
+                vector(e, vt, vs, vd, funct)
+            }
+            VScalar(funct) => {
+                a.expect(2)?;
+                let (vd, de) = a.vreg_index(0)?;
+                let (vt, e) = a.vreg_element(1)?;
+                vector(e, vt, de, vd, funct)
+            }
+            VNop => {
+                a.expect(0)?;
+                vector(0, 0, 0, 0, 0x37)
+            }
+            Word => {
+                a.expect(1)?;
+                signed_or_unsigned(a.imm(0)?, 32)?
+            }
+        };
+
+        Ok(word)
+    }
+}
+
+fn vector(e: u32, vt: u32, vs: u32, vd: u32, funct: u32) -> u32 {
+    (COP2 << 26) | (1 << 25) | (e << 21) | (vt << 16) | (vs << 11) | (vd << 6) | funct
+}
+
+fn bad(s: &str) -> AsmErrorKind {
+    AsmErrorKind::BadOperand(s.to_string())
+}
+
+fn signed(v: i64, bits: u32) -> Result<u32, AsmErrorKind> {
+    let half = 1i64 << (bits - 1);
+    if (-half..half).contains(&v) {
+        Ok((v as u32) & (u32::MAX >> (32 - bits)))
+    } else {
+        Err(AsmErrorKind::OutOfRange(v))
+    }
+}
+
+fn unsigned(v: i64, bits: u32) -> Result<u32, AsmErrorKind> {
+    if (0..1i64 << bits).contains(&v) {
+        Ok(v as u32)
+    } else {
+        Err(AsmErrorKind::OutOfRange(v))
+    }
+}
+
+fn signed_or_unsigned(v: i64, bits: u32) -> Result<u32, AsmErrorKind> {
+    unsigned(v, bits).or_else(|_| signed(v, bits))
+}
+
+fn parse_int(s: &str) -> Option<i64> {
+    let (neg, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+    let v = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None => s.parse::<i64>().ok()?,
+    };
+
+    Some(if neg { -v } else { v })
+}
+
+/// addresses of the `subr_XXXXXXXX`, `@LXXXXXXXX` (`LXXXXXXXX` in bass) and `data_0xXXXX`
+/// names generated by `Sym`
+fn generated_label(s: &str) -> Option<u32> {
+    let hex = s
+        .strip_prefix("subr_")
+        .or_else(|| s.strip_prefix("handler_"))
+        .or_else(|| s.strip_prefix("data_0x"))
+        .or_else(|| s.strip_prefix("@L"))
+        .or_else(|| s.strip_prefix('L'))?;
+    u32::from_str_radix(hex, 16).ok()
+}
+
+/// a named register, `$N`, or bass `rN`; `r0` is also `zero`, as the GNU
+/// syntax names it
+fn parse_gp(s: &str) -> Option<GpReg> {
+    let s = s.trim();
+    if s == "zero" {
+        return Some(GpReg::R0);
+    }
+    (0..32).filter_map(|i| GpReg::try_from(i).ok()).find(|&r| {
+        s == r.as_mnemonic()
+            || s == r.as_armips_id()
+            || s.strip_prefix('r').and_then(|n| n.parse::<u8>().ok()) == Some(r as u8)
+    })
+}
+
+/// an armips or Nintendo name, or bass `cN`
+fn parse_cop0(s: &str) -> Option<Cop0Reg> {
+    let s = s.trim();
+    (0..16)
+        .filter_map(|i| Cop0Reg::try_from(i).ok())
+        .find(|&r| {
+            s.eq_ignore_ascii_case(r.armips_name())
+                || s == r.nintendo_name()
+                || s.strip_prefix('c').and_then(|n| n.parse::<u8>().ok()) == Some(r as u8)
+        })
+}
+
+fn parse_vu_ctrl(s: &str) -> Option<VUCtrlReg> {
+    let s = s.trim();
+    let s = s.strip_prefix('$').unwrap_or(s);
+    (0..3)
+        .filter_map(|i| VUCtrlReg::try_from(i).ok())
+        .find(|r| s == r.as_mnemonic())
+}
+
+/// element suffix of a vector compute op, without the brackets
+fn parse_element(s: &str) -> Option<u32> {
+    if let Some(q) = s.strip_suffix('q') {
+        parse_int(q)
+            .filter(|v| (0..2).contains(v))
+            .map(|v| 0b0010 | v as u32)
+    } else if let Some(h) = s.strip_suffix('h') {
+        parse_int(h)
+            .filter(|v| (0..4).contains(v))
+            .map(|v| 0b0100 | v as u32)
+    } else {
+        parse_int(s)
+            .filter(|v| (0..8).contains(v))
+            .map(|v| 0b1000 | v as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode_bytes, print::Print, PrintOpts};
+
+    const VADDR: u32 = 0x0400_1000;
+
+    #[test]
+    fn gnu_r0_is_zero() {
+        let opts = PrintOpts {
+            syntax: Syntax::Gnu,
+            ..PrintOpts::default()
+        };
+        for word in [
+            0x24080001u32, // addiu t0, r0, 1
+            0x8C090010,    // lw t1, 0x10(r0)
+            0xAC000004,    // sw r0, 4(r0)
+            0x00004025,    // or t0, r0, r0
+        ] {
+            let instr = decode_bytes(&word.to_be_bytes(), VADDR).unwrap()[0];
+            let mut text = String::new();
+            instr.op.print(&opts, &mut text).unwrap();
+            assert!(text.contains("zero"), "{}", text);
+            assert_eq!(
+                assemble_instruction_as(&text, VADDR, Syntax::Armips),
+                Ok(word),
+                "{}",
+                text
+            );
+        }
+        assert_eq!(parse_gp("zero"), Some(GpReg::R0));
+        assert_eq!(parse_gp("r0"), Some(GpReg::R0));
+        assert_eq!(parse_gp("$0"), Some(GpReg::R0));
+    }
+}
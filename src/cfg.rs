@@ -0,0 +1,437 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    ops::{regimm::RegImm, RspOpcode},
+    regs::su::GpReg,
+    Instruction, Sym,
+};
+
+/// How a basic block hands off control
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Terminator {
+    /// no control flow op; runs into the next block
+    FallThrough,
+    /// conditional branch (`beq`, `bne`, `blez`, `bgtz`, `bltz`, `bgez`)
+    Branch(Sym),
+    /// conditional call (`bltzal`, `bgezal`)
+    BranchLink(Sym),
+    /// `j`, or a branch that is always taken (`beq r0, r0`, `blez r0`, `bgez r0`)
+    Jump(Sym),
+    /// `jal`, or `bgezal r0`
+    Call(Sym),
+    /// `jr`, to a target that is not known statically
+    JumpReg(GpReg),
+    /// `jalr`
+    CallReg { target: GpReg, link: GpReg },
+    /// `break` halts the RSP
+    Break,
+}
+
+impl Terminator {
+    /// The control flow effect of `op`, or `None` if it just falls through
+    pub fn of(op: &RspOpcode) -> Option<Self> {
+        let term = match op {
+            RspOpcode::BEQ(d) if d.rs == d.rt => Self::Jump(d.target),
+            RspOpcode::BEQ(d) | RspOpcode::BNE(d) => Self::Branch(d.target),
+            RspOpcode::BLEZ(d) if d.rs == GpReg::R0 => Self::Jump(d.target),
+            RspOpcode::BLEZ(d) | RspOpcode::BGTZ(d) => Self::Branch(d.target),
+            RspOpcode::RegImm(RegImm::BGEZ(r)) if r.rs == GpReg::R0 => Self::Jump(r.sym),
+            RspOpcode::RegImm(RegImm::BGEZAL(r)) if r.rs == GpReg::R0 => Self::Call(r.sym),
+            RspOpcode::RegImm(RegImm::BLTZ(r) | RegImm::BGEZ(r)) => Self::Branch(r.sym),
+            RspOpcode::RegImm(RegImm::BLTZAL(r) | RegImm::BGEZAL(r)) => Self::BranchLink(r.sym),
+            RspOpcode::J(s) => Self::Jump(*s),
+            RspOpcode::JAL(s) => Self::Call(*s),
+            RspOpcode::Special(sub) => match sub.jump_reg() {
+                Some((rs, None)) => Self::JumpReg(rs),
+                Some((target, Some(link))) => Self::CallReg { target, link },
+                None if sub.is_break() => Self::Break,
+                None => return None,
+            },
+            _ => return None,
+        };
+
+        Some(term)
+    }
+
+    /// whether the instruction after this one executes before control is transferred
+    pub const fn has_delay_slot(&self) -> bool {
+        !matches!(self, Self::FallThrough | Self::Break)
+    }
+
+    /// the static target, if any
+    pub const fn target(&self) -> Option<Sym> {
+        match self {
+            Self::Branch(s) | Self::BranchLink(s) | Self::Jump(s) | Self::Call(s) => Some(*s),
+            _ => None,
+        }
+    }
+
+    /// whether execution can continue after this op (and its delay slot)
+    pub const fn falls_through(&self) -> bool {
+        !matches!(self, Self::Jump(_) | Self::JumpReg(_) | Self::Break)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+    /// a branch or jump to its target
+    Taken,
+    /// on to the next instruction, or back from a call
+    FallThrough,
+    /// a call to its subroutine
+    Call,
+    /// into a delay slot that is the start of another block
+    DelaySlot,
+    /// a `jr` to a target found by [`constprop`](crate::constprop)
+    Indirect,
+}
+
+/// A control flow edge between the start addresses of two blocks.
+///
+/// `to` may lie outside of the decoded instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Edge {
+    pub from: u32,
+    pub to: u32,
+    pub kind: EdgeKind,
+}
+
+/// A straight line run of instructions, ending with its terminator's delay slot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: u32,
+    pub instrs: Vec<Instruction>,
+    pub terminator: Terminator,
+    pub succs: Vec<Edge>,
+    pub preds: Vec<Edge>,
+}
+
+impl BasicBlock {
+    /// address following the last instruction of this block
+    pub fn end(&self) -> u32 {
+        self.start + self.instrs.len() as u32 * 4
+    }
+
+    pub fn contains(&self, addr: u32) -> bool {
+        (self.start..self.end()).contains(&addr)
+    }
+}
+
+/// Control flow graph of a contiguous run of instructions
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cfg {
+    blocks: BTreeMap<u32, BasicBlock>,
+}
+
+impl Cfg {
+    /// Split `instrs`, which must be contiguous and in address order, into basic blocks.
+    ///
+    /// A block runs until a control flow op and its delay slot. If another branch
+    /// targets the delay slot, the slot starts a block of its own, the branch's
+    /// block ends at the branch and a [`EdgeKind::DelaySlot`] edge joins the two.
+    pub fn new(instrs: &[Instruction]) -> Self {
+        Self::with_indirect(instrs, &BTreeMap::new())
+    }
+
+    /// Like [`Cfg::new`], with the known targets of `jr` ops keyed by the `jr`'s address
+    pub fn with_indirect(instrs: &[Instruction], jumps: &BTreeMap<u32, Vec<u32>>) -> Self {
+        let base = match instrs.first() {
+            Some(i) => i.addr,
+            None => return Self::default(),
+        };
+        let index = |addr: u32| {
+            let off = addr.wrapping_sub(base);
+            let idx = (off / 4) as usize;
+            (off % 4 == 0 && idx < instrs.len()).then_some(idx)
+        };
+
+        let mut leaders = BTreeSet::from([0, instrs.len()]);
+        for (i, instr) in instrs.iter().enumerate() {
+            if let Some(term) = Terminator::of(&instr.op) {
+                let next = i + if term.has_delay_slot() { 2 } else { 1 };
+                leaders.insert(next.min(instrs.len()));
+                if let Some(idx) = term.target().and_then(|t| index(t.value())) {
+                    leaders.insert(idx);
+                }
+                for &target in jumps.get(&instr.addr).into_iter().flatten() {
+                    leaders.extend(index(target));
+                }
+            }
+        }
+
+        let leaders = leaders.into_iter().collect::<Vec<_>>();
+        let mut blocks = BTreeMap::new();
+        for bounds in leaders.windows(2) {
+            let body = &instrs[bounds[0]..bounds[1]];
+            let start = body[0].addr;
+            let control = body
+                .iter()
+                .find_map(|i| Terminator::of(&i.op).map(|t| (i.addr, t)));
+            let (terminator, succs) = match control {
+                Some((addr, term)) => {
+                    let mut succs = edges(start, addr, term);
+                    succs.extend(jumps.get(&addr).into_iter().flatten().map(|&to| Edge {
+                        from: start,
+                        to,
+                        kind: EdgeKind::Indirect,
+                    }));
+                    if term.has_delay_slot() && body.last().unwrap().addr == addr {
+                        succs.push(Edge {
+                            from: start,
+                            to: addr + 4,
+                            kind: EdgeKind::DelaySlot,
+                        });
+                    }
+                    (term, succs)
+                }
+                None => {
+                    let end = start + body.len() as u32 * 4;
+                    let edge = Edge {
+                        from: start,
+                        to: end,
+                        kind: EdgeKind::FallThrough,
+                    };
+                    (Terminator::FallThrough, vec![edge])
+                }
+            };
+
+            let block = BasicBlock {
+                start,
+                instrs: body.to_vec(),
+                terminator,
+                succs,
+                preds: Vec::new(),
+            };
+            blocks.insert(start, block);
+        }
+
+        let all_edges = blocks
+            .values()
+            .flat_map(|b| b.succs.iter().copied())
+            .collect::<Vec<_>>();
+        for edge in all_edges {
+            if let Some(block) = blocks.get_mut(&edge.to) {
+                block.preds.push(edge);
+            }
+        }
+
+        Self { blocks }
+    }
+
+    /// every block, in address order
+    pub fn blocks(&self) -> impl Iterator<Item = &BasicBlock> {
+        self.blocks.values()
+    }
+
+    /// the block starting at `start`
+    pub fn block(&self, start: u32) -> Option<&BasicBlock> {
+        self.blocks.get(&start)
+    }
+
+    /// the block that `addr` is a part of
+    pub fn block_containing(&self, addr: u32) -> Option<&BasicBlock> {
+        self.blocks
+            .range(..=addr)
+            .next_back()
+            .map(|(_, b)| b)
+            .filter(|b| b.contains(addr))
+    }
+
+    pub fn successors(&self, start: u32) -> &[Edge] {
+        self.block(start).map_or(&[], |b| &b.succs)
+    }
+
+    pub fn predecessors(&self, start: u32) -> &[Edge] {
+        self.block(start).map_or(&[], |b| &b.preds)
+    }
+}
+
+/// outgoing edges of the block at `from` that ends with `term` at `addr`
+fn edges(from: u32, addr: u32, term: Terminator) -> Vec<Edge> {
+    let edge = |to: u32, kind| Edge { from, to, kind };
+    // past the delay slot
+    let next = addr + 8;
+
+    match term {
+        Terminator::FallThrough => vec![edge(addr + 4, EdgeKind::FallThrough)],
+        Terminator::Branch(s) => vec![
+            edge(s.value(), EdgeKind::Taken),
+            edge(next, EdgeKind::FallThrough),
+        ],
+        Terminator::BranchLink(s) | Terminator::Call(s) => vec![
+            edge(s.value(), EdgeKind::Call),
+            edge(next, EdgeKind::FallThrough),
+        ],
+        Terminator::Jump(s) => vec![edge(s.value(), EdgeKind::Taken)],
+        Terminator::CallReg { .. } => vec![edge(next, EdgeKind::FallThrough)],
+        Terminator::JumpReg(_) | Terminator::Break => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assemble::assemble, decode_bytes};
+
+    const VADDR: u32 = 0x0400_1000;
+
+    fn decode(src: &str) -> Vec<Instruction> {
+        let bytes = assemble(src, VADDR)
+            .unwrap()
+            .iter()
+            .flat_map(|w| w.to_be_bytes())
+            .collect::<Vec<_>>();
+        decode_bytes(&bytes, VADDR).unwrap()
+    }
+
+    fn cfg(src: &str) -> Cfg {
+        Cfg::new(&decode(src))
+    }
+
+    /// the target and kind of each edge out of the block at `start`
+    fn succs(cfg: &Cfg, start: u32) -> Vec<(u32, EdgeKind)> {
+        cfg.successors(start)
+            .iter()
+            .map(|e| (e.to, e.kind))
+            .collect()
+    }
+
+    #[test]
+    fn conditional_branch_has_two_edges() {
+        let cfg = cfg("
+            bne t0, r0, done
+            nop
+            addiu t1, r0, 1
+        done:
+            break
+        ");
+        let starts = cfg.blocks().map(|b| b.start).collect::<Vec<_>>();
+        assert_eq!(starts, [VADDR, VADDR + 8, VADDR + 0xC]);
+        assert!(matches!(
+            cfg.block(VADDR).unwrap().terminator,
+            Terminator::Branch(_)
+        ));
+        assert_eq!(
+            succs(&cfg, VADDR),
+            [
+                (VADDR + 0xC, EdgeKind::Taken),
+                (VADDR + 8, EdgeKind::FallThrough)
+            ]
+        );
+        assert_eq!(
+            succs(&cfg, VADDR + 8),
+            [(VADDR + 0xC, EdgeKind::FallThrough)]
+        );
+        assert_eq!(succs(&cfg, VADDR + 0xC), []);
+        assert_eq!(cfg.predecessors(VADDR + 0xC).len(), 2);
+        assert_eq!(cfg.block_containing(VADDR + 4).unwrap().start, VADDR);
+    }
+
+    #[test]
+    fn always_taken_branches_jump() {
+        for branch in ["beq r0, r0, done", "blez r0, done", "bgez r0, done"] {
+            let cfg = cfg(&format!(
+                "
+                {}
+                nop
+                addiu t1, r0, 1
+            done:
+                break
+                ",
+                branch
+            ));
+            let block = cfg.block(VADDR).unwrap();
+            assert!(
+                matches!(block.terminator, Terminator::Jump(_)),
+                "{}",
+                branch
+            );
+            assert_eq!(
+                succs(&cfg, VADDR),
+                [(VADDR + 0xC, EdgeKind::Taken)],
+                "{}",
+                branch
+            );
+            assert!(cfg.predecessors(VADDR + 8).is_empty(), "{}", branch);
+        }
+    }
+
+    #[test]
+    fn branches_on_other_registers_are_conditional() {
+        for branch in [
+            "blez t0, done",
+            "bgtz r0, done",
+            "bltz r0, done",
+            "bgez t0, done",
+        ] {
+            let cfg = cfg(&format!("{}\nnop\nnop\ndone:\nbreak", branch));
+            let block = cfg.block(VADDR).unwrap();
+            assert!(
+                matches!(block.terminator, Terminator::Branch(_)),
+                "{}",
+                branch
+            );
+        }
+    }
+
+    #[test]
+    fn calls_return_past_the_delay_slot() {
+        let cfg = cfg("
+            jal sub
+            nop
+            break
+        sub:
+            jr ra
+            nop
+        ");
+        assert_eq!(
+            succs(&cfg, VADDR),
+            [
+                (VADDR + 0xC, EdgeKind::Call),
+                (VADDR + 8, EdgeKind::FallThrough)
+            ]
+        );
+        assert_eq!(
+            cfg.block(VADDR + 0xC).unwrap().terminator,
+            Terminator::JumpReg(GpReg::RA)
+        );
+        assert_eq!(succs(&cfg, VADDR + 0xC), []);
+    }
+
+    #[test]
+    fn branch_into_delay_slot_splits_it() {
+        let cfg = cfg("
+            bne t0, r0, slot
+            nop
+            j end
+        slot:
+            addiu t1, r0, 1
+        end:
+            break
+        ");
+        assert_eq!(cfg.block(VADDR + 8).unwrap().instrs.len(), 1);
+        assert_eq!(
+            succs(&cfg, VADDR + 8),
+            [
+                (VADDR + 0x10, EdgeKind::Taken),
+                (VADDR + 0xC, EdgeKind::DelaySlot)
+            ]
+        );
+        assert_eq!(cfg.predecessors(VADDR + 0xC).len(), 2);
+    }
+
+    #[test]
+    fn indirect_jumps_use_known_targets() {
+        let instrs = decode("jr t0\nnop\nbreak\nbreak");
+        let jumps = BTreeMap::from([(VADDR, vec![VADDR + 8, VADDR + 0xC])]);
+        let cfg = Cfg::with_indirect(&instrs, &jumps);
+        assert_eq!(
+            succs(&cfg, VADDR),
+            [
+                (VADDR + 8, EdgeKind::Indirect),
+                (VADDR + 0xC, EdgeKind::Indirect)
+            ]
+        );
+        assert!(cfg.block(VADDR + 0xC).is_some());
+    }
+}
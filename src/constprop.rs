@@ -0,0 +1,433 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::{
+    cfg::{Cfg, EdgeKind, Terminator},
+    ops::{
+        cop0::Cop0Op,
+        regimm::RegImm,
+        special::{Special, SpecialData, SpecialOpCode},
+        vu::VUOp,
+        RspOpcode,
+    },
+    regs::su::GpReg,
+    Instruction,
+};
+
+/// most entries read from a jump table whose index is not known
+const MAX_TABLE_ENTRIES: u32 = 256;
+/// size of DMEM, which wraps around on access
+const DMEM_SIZE: u32 = 0x1000;
+/// passes over the CFG before giving up on finding more targets
+const MAX_PASSES: usize = 16;
+
+/// What is known about a [`GpReg`] at some point in the code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+    Unknown,
+    Const(u32),
+    /// the `size` bytes at DMEM address `addr`, which may be changed at run
+    /// time, so are only known if they are an entry of a table of code
+    Entry {
+        addr: u32,
+        size: u32,
+    },
+    /// an entry of `size` bytes loaded from the table at DMEM address `table`,
+    /// at an index that is not known
+    Table {
+        table: u32,
+        size: u32,
+    },
+}
+
+impl Value {
+    fn meet(self, other: Self) -> Self {
+        if self == other {
+            self
+        } else {
+            Self::Unknown
+        }
+    }
+
+    fn map(self, op: impl FnOnce(u32) -> u32) -> Self {
+        match self {
+            Self::Const(v) => Self::Const(op(v)),
+            _ => Self::Unknown,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Regs([Value; 32]);
+
+impl Regs {
+    const UNKNOWN: Self = Self([Value::Unknown; 32]);
+
+    fn get(&self, reg: GpReg) -> Value {
+        match reg {
+            GpReg::R0 => Value::Const(0),
+            _ => self.0[reg as usize],
+        }
+    }
+
+    fn set(&mut self, reg: GpReg, value: Value) {
+        self.0[reg as usize] = value;
+    }
+
+    /// merge `other` into `self`, returning whether anything changed
+    fn meet(&mut self, other: &Self) -> bool {
+        let old = *self;
+        for (a, b) in self.0.iter_mut().zip(other.0) {
+            *a = a.meet(b);
+        }
+        old != *self
+    }
+}
+
+/// Build the CFG of `instrs`, following each `jr` whose targets can be found
+/// through the contents of `dmem`.
+///
+/// `dmem` starts at DMEM address zero. A `jr` target is the low 12 bits of its
+/// register's value, taken as an offset into the 4 KiB of IMEM that holds `instrs`.
+pub fn resolve(instrs: &[Instruction], dmem: &[u8]) -> (Cfg, BTreeMap<u32, Vec<u32>>) {
+    let mut jumps = BTreeMap::new();
+    for _ in 0..MAX_PASSES {
+        let cfg = Cfg::with_indirect(instrs, &jumps);
+        let mut changed = false;
+        for (addr, targets) in jump_targets(&cfg, dmem) {
+            let known: &mut Vec<u32> = jumps.entry(addr).or_default();
+            for target in targets {
+                if !known.contains(&target) {
+                    known.push(target);
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            return (cfg, jumps);
+        }
+    }
+
+    (Cfg::with_indirect(instrs, &jumps), jumps)
+}
+
+/// The values of the register each `jr` in `cfg` jumps through, keyed by the `jr`'s address.
+///
+/// `jr ra` is a return and is never tracked.
+pub fn jump_values(cfg: &Cfg) -> BTreeMap<u32, Value> {
+    let mut values = BTreeMap::new();
+    walk(cfg, |instr, regs| {
+        if let Some(Terminator::JumpReg(rs)) = Terminator::of(&instr.op) {
+            if rs != GpReg::RA {
+                let value = regs.get(rs);
+                values
+                    .entry(instr.addr)
+                    .and_modify(|v: &mut Value| *v = v.meet(value))
+                    .or_insert(value);
+            }
+        }
+    });
+
+    values
+}
+
+/// A DMEM access by a load or store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemRef {
+    /// DMEM address of the first byte
+    pub addr: u32,
+    /// number of bytes accessed, or for vector ops the size of their item
+    pub size: u32,
+}
+
+/// The DMEM accessed by each load and store in `cfg` whose base register is
+/// always known, keyed by the instruction's address
+pub fn mem_refs(cfg: &Cfg) -> BTreeMap<u32, MemRef> {
+    let mut refs = BTreeMap::new();
+    let mut unknown = BTreeSet::new();
+    walk(cfg, |instr, regs| {
+        let (base, offset, size) = match access(instr) {
+            Some(a) => a,
+            None => return,
+        };
+        match regs.get(base) {
+            Value::Const(b) if !unknown.contains(&instr.addr) => {
+                let addr = b.wrapping_add(offset as u32) % DMEM_SIZE;
+                let new = MemRef { addr, size };
+                if *refs.entry(instr.addr).or_insert(new) != new {
+                    refs.remove(&instr.addr);
+                    unknown.insert(instr.addr);
+                }
+            }
+            _ => {
+                refs.remove(&instr.addr);
+                unknown.insert(instr.addr);
+            }
+        }
+    });
+
+    refs
+}
+
+/// base register, offset and size of the memory access made by `instr`
+fn access(instr: &Instruction) -> Option<(GpReg, i16, u32)> {
+    match instr.op {
+        RspOpcode::LB(d) | RspOpcode::LBU(d) | RspOpcode::SB(d) => Some((d.base, d.offset, 1)),
+        RspOpcode::LH(d) | RspOpcode::LHU(d) | RspOpcode::SH(d) => Some((d.base, d.offset, 2)),
+        RspOpcode::LW(d) | RspOpcode::LWU(d) | RspOpcode::SW(d) => Some((d.base, d.offset, 4)),
+        RspOpcode::LWC2(ls) | RspOpcode::SWC2(ls) => Some((ls.base, ls.offset, ls.item_size())),
+        _ => None,
+    }
+}
+
+/// Track registers through `cfg`, calling `visit` with each instruction and
+/// the registers just before it runs.
+///
+/// Registers are tracked along every edge except calls. Subroutines start with
+/// nothing known, and nothing is known once a call returns. An instruction may
+/// be visited more than once, with less known each time.
+fn walk(cfg: &Cfg, mut visit: impl FnMut(&Instruction, &Regs)) {
+    let mut entry = HashMap::new();
+    let mut queue = Vec::new();
+    for block in cfg.blocks() {
+        let called = block.preds.iter().any(|e| e.kind == EdgeKind::Call);
+        if block.preds.is_empty() || called {
+            entry.insert(block.start, Regs::UNKNOWN);
+            queue.push(block.start);
+        }
+    }
+
+    while let Some(start) = queue.pop() {
+        let block = match cfg.block(start) {
+            Some(b) => b,
+            None => continue,
+        };
+        let mut regs = entry[&start];
+        for instr in &block.instrs {
+            visit(instr, &regs);
+            step(&mut regs, instr);
+        }
+
+        let returned = matches!(
+            block.terminator,
+            Terminator::Call(_) | Terminator::BranchLink(_) | Terminator::CallReg { .. }
+        );
+        for edge in &block.succs {
+            let out = match edge.kind {
+                EdgeKind::Call => continue,
+                EdgeKind::FallThrough if returned => Regs::UNKNOWN,
+                _ => regs,
+            };
+            if cfg.block(edge.to).is_none() {
+                continue;
+            }
+            let changed = match entry.get_mut(&edge.to) {
+                Some(known) => known.meet(&out),
+                None => {
+                    entry.insert(edge.to, out);
+                    true
+                }
+            };
+            if changed {
+                queue.push(edge.to);
+            }
+        }
+    }
+}
+
+/// The code addresses each `jr` in `cfg` can reach, keyed by the `jr`'s address.
+///
+/// A table with an unknown index is read from its first entry that points into
+/// the code until the next entry that does not.
+pub fn jump_targets(cfg: &Cfg, dmem: &[u8]) -> BTreeMap<u32, Vec<u32>> {
+    let range = match (cfg.blocks().next(), cfg.blocks().last()) {
+        (Some(first), Some(last)) => first.start..last.end(),
+        _ => return BTreeMap::new(),
+    };
+    let imem = |pc: u32| {
+        let addr = (range.start & !0xFFF) | (pc & 0xFFF);
+        (addr.is_multiple_of(4) && range.contains(&addr)).then_some(addr)
+    };
+
+    let mut jumps = BTreeMap::new();
+    for (addr, value) in jump_values(cfg) {
+        let targets = match value {
+            Value::Unknown => continue,
+            Value::Const(pc) => imem(pc).into_iter().collect(),
+            Value::Entry { addr, size } => {
+                load(dmem, addr, size).and_then(imem).into_iter().collect()
+            }
+            Value::Table { table, size } => (0..MAX_TABLE_ENTRIES)
+                .map(|i| load(dmem, table.wrapping_add(i * size), size).and_then(imem))
+                .skip_while(Option::is_none)
+                .map_while(|t| t)
+                .collect::<Vec<_>>(),
+        };
+        if !targets.is_empty() {
+            jumps.insert(addr, targets);
+        }
+    }
+
+    jumps
+}
+
+/// big-endian value of `size` bytes at DMEM address `addr`
+fn load(dmem: &[u8], addr: u32, size: u32) -> Option<u32> {
+    let start = (addr % DMEM_SIZE) as usize;
+    let bytes = dmem.get(start..start + size as usize)?;
+    Some(bytes.iter().fold(0, |v, &b| (v << 8) | b as u32))
+}
+
+/// update `regs` with the effect of `instr`
+fn step(regs: &mut Regs, instr: &Instruction) {
+    let dst = match written(instr) {
+        Some(GpReg::R0) | None => return,
+        Some(reg) => reg,
+    };
+    let link = instr.addr.wrapping_add(8);
+    // DMEM is filled by the host at run time, so what is loaded from it is
+    // only read from the image once it turns out to be a jump target
+    let load_from = |base: GpReg, offset: i16, size: u32| match regs.get(base) {
+        Value::Const(b) => Value::Entry {
+            addr: b.wrapping_add(offset as u32) % DMEM_SIZE,
+            size,
+        },
+        // the offset is the table and the base register its index
+        Value::Unknown | Value::Entry { .. } if size > 1 => Value::Table {
+            table: offset as u16 as u32 % DMEM_SIZE,
+            size,
+        },
+        _ => Value::Unknown,
+    };
+
+    let value = match instr.op {
+        RspOpcode::LUI(d) => Value::Const((d.imm as u32) << 16),
+        RspOpcode::ADDI(d) | RspOpcode::ADDIU(d) => {
+            regs.get(d.rs).map(|v| v.wrapping_add(d.value()))
+        }
+        RspOpcode::ANDI(d) => regs.get(d.rs).map(|v| v & d.value()),
+        RspOpcode::ORI(d) => regs.get(d.rs).map(|v| v | d.value()),
+        RspOpcode::XORI(d) => regs.get(d.rs).map(|v| v ^ d.value()),
+        RspOpcode::LH(d) | RspOpcode::LHU(d) => load_from(d.base, d.offset, 2),
+        RspOpcode::LW(d) | RspOpcode::LWU(d) => load_from(d.base, d.offset, 4),
+        RspOpcode::JAL(_) | RspOpcode::RegImm(_) => Value::Const(link),
+        RspOpcode::Special(sub) if sub.jump_reg().is_some() => Value::Const(link),
+        RspOpcode::Special(sub) => alu(&sub, regs),
+        _ => Value::Unknown,
+    };
+
+    regs.set(dst, value);
+}
+
+/// what is known of the result of the special op `sub`
+fn alu(sub: &Special, regs: &Regs) -> Value {
+    use SpecialOpCode::{ADD, ADDU, OR};
+
+    let (rs, rt) = match sub.data {
+        SpecialData::ThreeReg(d) | SpecialData::ShiftReg(d) => (regs.get(d.rs), regs.get(d.rt)),
+        SpecialData::ShiftImm(d) => (Value::Const(d.by as u32), regs.get(d.src)),
+        SpecialData::Jr(_) | SpecialData::JalrReg(_) | SpecialData::Break(_) => {
+            return Value::Unknown
+        }
+    };
+
+    match (sub.opcode, rs, rt) {
+        // moves
+        (ADD | ADDU | OR, v, Value::Const(0)) | (ADD | ADDU | OR, Value::Const(0), v) => v,
+        (op, Value::Const(a), Value::Const(b)) => {
+            op.eval(a, b).map_or(Value::Unknown, Value::Const)
+        }
+        _ => Value::Unknown,
+    }
+}
+
+/// the GP register that `instr` writes, if any
+fn written(instr: &Instruction) -> Option<GpReg> {
+    match instr.op {
+        RspOpcode::ADDI(d)
+        | RspOpcode::ADDIU(d)
+        | RspOpcode::SLTI(d)
+        | RspOpcode::SLTIU(d)
+        | RspOpcode::ANDI(d)
+        | RspOpcode::ORI(d)
+        | RspOpcode::XORI(d) => Some(d.rt),
+        RspOpcode::LUI(d) => Some(d.rt),
+        RspOpcode::LB(d)
+        | RspOpcode::LH(d)
+        | RspOpcode::LW(d)
+        | RspOpcode::LBU(d)
+        | RspOpcode::LHU(d)
+        | RspOpcode::LWU(d) => Some(d.dst),
+        RspOpcode::COP2(VUOp::MFC2(m)) => Some(m.rt),
+        RspOpcode::COP2(VUOp::CFC2(c)) => Some(c.rt),
+        RspOpcode::COP0(Cop0Op::MFC0(rt, _)) => Some(rt),
+        RspOpcode::JAL(_) | RspOpcode::RegImm(RegImm::BLTZAL(_) | RegImm::BGEZAL(_)) => {
+            Some(GpReg::RA)
+        }
+        RspOpcode::Special(sub) => match sub.data {
+            SpecialData::ThreeReg(d) | SpecialData::ShiftReg(d) => Some(d.rd),
+            SpecialData::ShiftImm(d) => Some(d.dst),
+            SpecialData::JalrReg(d) => Some(d.rd),
+            SpecialData::Jr(_) | SpecialData::Break(_) => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode_bytes;
+
+    const VADDR: u32 = 0x0400_1000;
+
+    fn decode(words: &[u32]) -> Vec<Instruction> {
+        let bytes = words
+            .iter()
+            .flat_map(|w| w.to_be_bytes())
+            .collect::<Vec<_>>();
+        decode_bytes(&bytes, VADDR).unwrap()
+    }
+
+    #[test]
+    fn command_index_from_dmem() {
+        let instrs = decode(&[
+            0x94080000, // lhu t0, 0(zero): the command, written by the host
+            0x00084040, // sll t0, t0, 1
+            0x95090010, // lhu t1, 0x10(t0)
+            0x01200008, // jr t1
+            0x00000000, // nop
+            0x0000000D, // break
+            0x24020001, // addiu v0, zero, 1
+            0x0000000D, // break
+            0x24020002, // addiu v0, zero, 2
+            0x0000000D, // break
+        ]);
+        let mut dmem = vec![0; 0x20];
+        dmem[0x10..0x16].copy_from_slice(&[0x10, 0x18, 0x10, 0x20, 0xFF, 0xFF]);
+
+        let (_, jumps) = resolve(&instrs, &dmem);
+        assert_eq!(jumps[&0x0400_100C], [0x0400_1018, 0x0400_1020]);
+    }
+
+    #[test]
+    fn entry_at_known_address() {
+        let instrs = decode(&[
+            0x94080012, // lhu t0, 0x12(zero)
+            0x01000008, // jr t0
+            0x00000000, // nop
+            0x0000000D, // break
+        ]);
+        let mut dmem = vec![0; 0x20];
+        dmem[0x12..0x14].copy_from_slice(&[0x10, 0x0C]);
+
+        let (_, jumps) = resolve(&instrs, &dmem);
+        assert_eq!(jumps[&0x0400_1004], [0x0400_100C]);
+        assert_eq!(
+            jump_values(&Cfg::new(&instrs))[&0x0400_1004],
+            Value::Entry {
+                addr: 0x12,
+                size: 2
+            }
+        );
+    }
+}
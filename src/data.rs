@@ -0,0 +1,145 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write},
+};
+
+use crate::{
+    print::{Formatter, Print},
+    utils::render,
+    Sym,
+};
+
+/// most bytes written on one line
+const ROW_SIZE: u32 = 16;
+
+/// A labeled DMEM address
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct DataLabel {
+    /// size of each item of the data here, from its type or from the loads and
+    /// stores made to it
+    pub(crate) item: Option<u32>,
+    /// bytes covered by the label, if known
+    pub(crate) len: Option<u32>,
+}
+
+/// Write `dmem` as data directives, with a label at each address in `labels`.
+///
+/// The item size of each label types the data up to the next label, or the end
+/// of the label's length: bytes, halfwords, or words for anything larger. A label
+/// without an item size keeps the type before it, and data past the end of a
+/// label is words again. Data not aligned to its type is written as bytes.
+pub(crate) fn write_data(
+    w: &mut dyn Write,
+    dmem: &[u8],
+    labels: &BTreeMap<u32, DataLabel>,
+    f: &dyn Formatter,
+) -> fmt::Result {
+    let len = dmem.len() as u32;
+    let mut size = 4;
+    let mut addr = 0;
+    let mut label_end = None;
+    let mut values = Vec::new();
+    while addr < len {
+        if let Some(label) = labels.get(&addr) {
+            let name = render(|s| Sym::Data(addr).print(f, s));
+            writeln!(w, "{}:", name)?;
+            size = label.item.map_or(size, |a| a.min(4));
+            label_end = label
+                .len
+                .filter(|&l| l != 0)
+                .map(|l| addr.saturating_add(l));
+        } else if label_end == Some(addr) {
+            size = 4;
+            label_end = None;
+        }
+
+        let next_label = labels.range(addr + 1..).next().map_or(len, |(&a, _)| a);
+        let end = next_label
+            .min(len)
+            .min(label_end.unwrap_or(len))
+            .min((addr / ROW_SIZE + 1) * ROW_SIZE)
+            // always write at least a byte, so an empty range cannot stall
+            .max(addr + 1);
+        let item = if addr % size == 0 && end - addr >= size {
+            size
+        } else {
+            1
+        };
+
+        values.clear();
+        while addr + item <= end {
+            let bytes = &dmem[addr as usize..(addr + item) as usize];
+            values.push(bytes.iter().fold(0, |v, &b| (v << 8) | b as u32));
+            addr += item;
+        }
+        w.write_char('\t')?;
+        f.data(item, &values, w)?;
+        writeln!(w)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PrintOpts;
+
+    /// DMEM holding each byte's own address
+    fn data(len: u32, labels: &[(u32, Option<u32>, Option<u32>)]) -> String {
+        let dmem = (0..len as u8).collect::<Vec<_>>();
+        let labels = labels
+            .iter()
+            .map(|&(addr, item, len)| (addr, DataLabel { item, len }))
+            .collect();
+        render(|s| write_data(s, &dmem, &labels, &PrintOpts::default()))
+    }
+
+    #[test]
+    fn item_size_lasts_for_label_len() {
+        assert_eq!(
+            data(0xC, &[(0, Some(2), Some(4))]),
+            "data_0x0000:\n\t.dh 0x0001, 0x0203\n\t.dw 0x04050607, 0x08090A0B\n"
+        );
+    }
+
+    #[test]
+    fn label_without_item_keeps_type() {
+        assert_eq!(
+            data(0xC, &[(0, Some(2), None), (4, None, None)]),
+            "data_0x0000:\n\t.dh 0x0001, 0x0203\n\
+             data_0x0004:\n\t.dh 0x0405, 0x0607, 0x0809, 0x0A0B\n"
+        );
+    }
+
+    #[test]
+    fn zero_len_is_unknown() {
+        assert_eq!(
+            data(8, &[(0, Some(1), Some(0))]),
+            "data_0x0000:\n\t.db 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07\n"
+        );
+    }
+
+    #[test]
+    fn unaligned_data_is_bytes() {
+        assert_eq!(
+            data(
+                0x24,
+                &[
+                    (0, None, None),
+                    (0x11, Some(2), Some(4)),
+                    (0x18, Some(8), None)
+                ]
+            ),
+            "data_0x0000:\n\
+             \t.dw 0x00010203, 0x04050607, 0x08090A0B, 0x0C0D0E0F\n\
+             \t.db 0x10\n\
+             data_0x0011:\n\
+             \t.db 0x11, 0x12, 0x13, 0x14\n\
+             \t.db 0x15, 0x16, 0x17\n\
+             data_0x0018:\n\
+             \t.dw 0x18191A1B, 0x1C1D1E1F\n\
+             \t.dw 0x20212223\n"
+        );
+    }
+}
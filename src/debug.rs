@@ -0,0 +1,465 @@
+//! An interactive debugger over a [`Sim`], driven one command line at a time.
+//!
+//! Code is shown with the labels and names of the static disassembly, and
+//! breakpoints and memory can be given by address or by label.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::{self, Write},
+};
+
+use crate::{
+    decode,
+    print::{Formatter, Print},
+    regs::{
+        su::GpReg,
+        vu::{VUCtrlReg, VUReg},
+    },
+    sim::{Cop0, Halt, Sim, MEM_SIZE},
+    symbols::{parse_number, Named},
+    utils::render,
+    Analysis, Instruction, Microcode, PrintOpts, RspDisasmError, Sym,
+};
+
+/// most instructions `continue` and `next` run before stopping anyway
+const MAX_RUN: u64 = 10_000_000;
+/// instructions `list` shows before the one asked for
+const LIST_BEFORE: u32 = 4;
+/// instructions `list` shows from the one asked for
+const LIST_AFTER: u32 = 8;
+/// bytes `x` shows if no length is given
+const DUMP_LEN: u32 = 64;
+
+const HELP: &str = "\
+s, step [n]           run n instructions, stopping at delay slots and breakpoints [1]
+n, next               run an instruction, or a whole call and its delay slot
+c, continue           run until a breakpoint or a break
+b, break [loc]        stop before the instruction at loc, or list breakpoints
+d, delete [loc]       remove the breakpoint at loc, or all of them
+l, list [loc]         disassemble around loc [the PC]
+r, regs               show the scalar registers
+v, vregs [reg]        show a vector register, or all of them and the flags
+x addr [len]          dump len bytes of DMEM from addr or a data label [64]
+h, help               show this
+q, quit               stop debugging
+an empty line repeats the last command; loc is an address or a code label
+";
+
+/// A [`Sim`] running microcode, with breakpoints and the labels of the
+/// microcode's disassembly
+pub struct Debugger<'a, C> {
+    pub sim: Sim<C>,
+    ucode: Microcode<'a>,
+    analysis: Analysis,
+    opts: PrintOpts,
+    /// IMEM addresses to stop before
+    breakpoints: BTreeSet<u32>,
+    /// IMEM address of each code label, by name
+    code: HashMap<String, u32>,
+    /// DMEM address of each data label, by name
+    data: HashMap<String, u32>,
+    /// the command an empty line repeats
+    last: String,
+}
+
+impl<'a, C: Cop0> Debugger<'a, C> {
+    /// Debug `ucode` on an RSP connected to `cop0`, stopped before its first instruction
+    pub fn new(ucode: Microcode<'a>, opts: PrintOpts, cop0: C) -> Result<Self, RspDisasmError> {
+        let analysis = Analysis::new(&ucode)?;
+        let f = Named::new(&opts, ucode.symbols);
+        let code = analysis
+            .syms
+            .iter()
+            .map(|sym| (render(|s| sym.print(&f, s)), imem_addr(sym.value())))
+            .collect();
+        let data = analysis
+            .data
+            .keys()
+            .map(|&addr| (render(|s| Sym::Data(addr).print(&f, s)), addr))
+            .collect();
+
+        Ok(Self {
+            sim: Sim::load(&ucode, cop0),
+            ucode,
+            analysis,
+            opts,
+            breakpoints: BTreeSet::new(),
+            code,
+            data,
+            last: String::new(),
+        })
+    }
+
+    /// Run the command `line`, writing what it shows to `w`.
+    ///
+    /// An empty line repeats the last command. Returns `false` once the user quits.
+    pub fn exec(&mut self, line: &str, w: &mut dyn Write) -> Result<bool, fmt::Error> {
+        let line = match line.trim() {
+            "" => self.last.clone(),
+            line => line.to_string(),
+        };
+        self.last.clone_from(&line);
+
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let (cmd, args) = match words.split_first() {
+            Some((cmd, args)) => (*cmd, args),
+            None => return Ok(true),
+        };
+        match (cmd, args) {
+            ("s" | "step", []) => self.step(1, w)?,
+            ("s" | "step", [n]) => match parse_number(n) {
+                Ok(n) => self.step(n, w)?,
+                Err(_) => writeln!(w, "`{}` is not a number", n)?,
+            },
+            ("n" | "next", []) => self.next(w)?,
+            ("c" | "continue", []) => self.run_until(|_| false, w)?,
+            ("b" | "break", []) => {
+                for &pc in &self.breakpoints {
+                    self.write_instr(pc, w)?;
+                }
+            }
+            ("b" | "break", [loc]) => {
+                if let Some(pc) = self.code_addr(loc, w)? {
+                    self.breakpoints.insert(pc);
+                    writeln!(w, "breakpoint at {:08X}", self.vaddr(pc))?;
+                }
+            }
+            ("d" | "delete", []) => self.breakpoints.clear(),
+            ("d" | "delete", [loc]) => {
+                if let Some(pc) = self.code_addr(loc, w)? {
+                    if !self.breakpoints.remove(&pc) {
+                        writeln!(w, "no breakpoint at {:08X}", self.vaddr(pc))?;
+                    }
+                }
+            }
+            ("l" | "list", []) => self.list(self.sim.pc(), w)?,
+            ("l" | "list", [loc]) => {
+                if let Some(pc) = self.code_addr(loc, w)? {
+                    self.list(pc, w)?;
+                }
+            }
+            ("r" | "regs", []) => self.regs(w)?,
+            ("v" | "vregs", []) => self.vregs(w)?,
+            ("v" | "vregs", [reg]) => match parse_vu_reg(reg) {
+                Some(reg) => self.vreg(reg, w)?,
+                None => writeln!(w, "`{}` is not a vector register", reg)?,
+            },
+            ("x", [loc]) => self.dump(loc, DUMP_LEN, w)?,
+            ("x", [loc, len]) => match parse_number(len) {
+                Ok(len) => self.dump(loc, len, w)?,
+                Err(_) => writeln!(w, "`{}` is not a number", len)?,
+            },
+            ("h" | "help", []) => w.write_str(HELP)?,
+            ("q" | "quit", []) => return Ok(false),
+            _ => writeln!(w, "cannot run `{}`; try `help`", line)?,
+        }
+
+        Ok(true)
+    }
+
+    /// run `n` instructions, stopping early at a breakpoint or if the RSP halts
+    fn step(&mut self, n: u32, w: &mut dyn Write) -> fmt::Result {
+        for i in 1..=n {
+            if let Some(halt) = self.sim.step() {
+                return self.stopped(Some(halt), w);
+            }
+            if i < n && self.breakpoints.contains(&self.sim.pc()) {
+                writeln!(w, "breakpoint")?;
+                break;
+            }
+        }
+        self.stopped(None, w)
+    }
+
+    /// Step over a call and its delay slot, to where it returns; anything
+    /// else is a single step
+    fn next(&mut self, w: &mut dyn Write) -> fmt::Result {
+        let pc = self.sim.pc();
+        let call = matches!(
+            self.instr(pc).mnemonic(),
+            "jal" | "jalr" | "bltzal" | "bgezal"
+        );
+        if call {
+            let ret = (pc + 8) % MEM_SIZE as u32;
+            self.run_until(|sim| sim.pc() == ret && sim.branch_target().is_none(), w)
+        } else {
+            self.step(1, w)
+        }
+    }
+
+    /// Run until `done`, a breakpoint or a halt
+    fn run_until(&mut self, done: impl Fn(&Sim<C>) -> bool, w: &mut dyn Write) -> fmt::Result {
+        for _ in 0..MAX_RUN {
+            if let Some(halt) = self.sim.step() {
+                return self.stopped(Some(halt), w);
+            }
+            if done(&self.sim) {
+                return self.stopped(None, w);
+            }
+            if self.breakpoints.contains(&self.sim.pc()) {
+                writeln!(w, "breakpoint")?;
+                return self.stopped(None, w);
+            }
+        }
+        self.stopped(Some(Halt::StepLimit), w)
+    }
+
+    /// say why the RSP stopped, and show the instruction it stopped at
+    fn stopped(&self, halt: Option<Halt>, w: &mut dyn Write) -> fmt::Result {
+        match halt {
+            None => (),
+            Some(Halt::Break) => writeln!(w, "halted by break")?,
+            Some(Halt::Unsupported(instr)) => {
+                let text = render(|s| instr.op.print(&self.formatter(), s));
+                writeln!(w, "cannot simulate `{}`", text)?
+            }
+            Some(Halt::StepLimit) => writeln!(w, "still running after {} instructions", MAX_RUN)?,
+        }
+        self.write_instr(self.sim.pc(), w)?;
+        if let Some(target) = self.sim.branch_target() {
+            writeln!(w, "    (delay slot, then {:08X})", self.vaddr(target))?;
+        }
+        Ok(())
+    }
+
+    fn list(&self, pc: u32, w: &mut dyn Write) -> fmt::Result {
+        let start = pc.wrapping_sub(LIST_BEFORE * 4);
+        for i in 0..LIST_BEFORE + LIST_AFTER {
+            self.write_instr(imem_addr(start.wrapping_add(i * 4)), w)?;
+        }
+        Ok(())
+    }
+
+    /// Show the instruction at IMEM address `pc` and its labels, marking the
+    /// PC with `=>` and breakpoints with `*`
+    fn write_instr(&self, pc: u32, w: &mut dyn Write) -> fmt::Result {
+        let f = self.formatter();
+        let instr = self.instr(pc);
+        for sym in self.analysis.labels_at(instr.addr) {
+            sym.print(&f, w)?;
+            writeln!(w, ":")?;
+        }
+
+        let marker = if pc == self.sim.pc() { "=>" } else { "  " };
+        let bp = if self.breakpoints.contains(&pc) {
+            '*'
+        } else {
+            ' '
+        };
+        write!(
+            w,
+            "{}{} {:08X}: {:08X}  ",
+            marker, bp, instr.addr, instr.word
+        )?;
+        instr.op.print(&f, w)?;
+        writeln!(w)
+    }
+
+    fn regs(&self, w: &mut dyn Write) -> fmt::Result {
+        writeln!(w, "pc = {:03X}", self.sim.pc())?;
+        for row in 0..8 {
+            for col in 0..4 {
+                let reg = GpReg::try_from(row * 4 + col).unwrap();
+                let name = render(|s| self.opts.gp_reg(reg, s));
+                write!(w, "{:>6} = {:08X}", name, self.sim.regs.get(reg))?;
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+
+    fn vreg(&self, reg: VUReg, w: &mut dyn Write) -> fmt::Result {
+        let name = render(|s| self.opts.vu_reg(reg, s));
+        write!(w, "{:>6} =", name)?;
+        for lane in self.sim.vu.reg(reg) {
+            write!(w, " {:04X}", lane)?;
+        }
+        writeln!(w)
+    }
+
+    /// show every vector register, the accumulator and the flags
+    fn vregs(&self, w: &mut dyn Write) -> fmt::Result {
+        for reg in (0..32).filter_map(VUReg::new) {
+            self.vreg(reg, w)?;
+        }
+        for (name, shift) in [("acc_hi", 32), ("acc_md", 16), ("acc_lo", 0)] {
+            write!(w, "{:>6} =", name)?;
+            for lane in 0..8 {
+                write!(w, " {:04X}", (self.sim.vu.acc(lane) >> shift) as u16)?;
+            }
+            writeln!(w)?;
+        }
+        for reg in [VUCtrlReg::Vco, VUCtrlReg::Vcc, VUCtrlReg::Vce] {
+            let name = render(|s| self.opts.vu_ctrl_reg(reg, s));
+            write!(w, "{:>6} = {:04X}", name, self.sim.vu.ctrl(reg))?;
+        }
+        writeln!(w)
+    }
+
+    /// hex dump `len` bytes of DMEM from `loc`, an address or a data label,
+    /// wrapping around to its start; at most all of DMEM is shown
+    fn dump(&self, loc: &str, len: u32, w: &mut dyn Write) -> fmt::Result {
+        let start = match self
+            .data
+            .get(loc)
+            .copied()
+            .or_else(|| parse_number(loc).ok())
+        {
+            Some(addr) => addr % MEM_SIZE as u32,
+            None => return writeln!(w, "no data at `{}`", loc),
+        };
+        let len = len.min(MEM_SIZE as u32);
+        let dmem = self.sim.mem.dmem();
+        for row in (0..len).step_by(16) {
+            write!(w, "{:03X}:", (start + row) % MEM_SIZE as u32)?;
+            for i in row..len.min(row + 16) {
+                write!(w, " {:02X}", dmem[(start + i) as usize % MEM_SIZE])?;
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+
+    /// IMEM address of `loc`, an address or a code label, or `None` after
+    /// saying why there is none
+    fn code_addr(&self, loc: &str, w: &mut dyn Write) -> Result<Option<u32>, fmt::Error> {
+        match self
+            .code
+            .get(loc)
+            .copied()
+            .or_else(|| parse_number(loc).ok())
+        {
+            Some(addr) if addr % 4 == 0 => Ok(Some(imem_addr(addr))),
+            Some(_) => writeln!(w, "`{}` is not word aligned", loc).map(|_| None),
+            None => writeln!(w, "no code at `{}`", loc).map(|_| None),
+        }
+    }
+
+    /// The instruction at IMEM address `pc`, as disassembled if IMEM still
+    /// holds it there
+    fn instr(&self, pc: u32) -> Instruction {
+        let addr = self.vaddr(pc);
+        let word = self.sim.mem.instr(pc).word;
+        let index = addr.wrapping_sub(self.ucode.vaddr) as usize / 4;
+        match self.analysis.instrs.get(index) {
+            Some(instr) if instr.addr == addr && instr.word == word => *instr,
+            _ => decode(word, addr),
+        }
+    }
+
+    /// address of IMEM address `pc` in the microcode's vram
+    fn vaddr(&self, pc: u32) -> u32 {
+        (self.ucode.vaddr & !(MEM_SIZE as u32 - 1)) | pc
+    }
+
+    fn formatter(&self) -> Named<'_> {
+        Named::new(&self.opts, self.ucode.symbols)
+    }
+}
+
+/// the IMEM address that `addr` runs at
+fn imem_addr(addr: u32) -> u32 {
+    (addr % MEM_SIZE as u32) & !3
+}
+
+/// `$v3`, `v3` or `3`
+fn parse_vu_reg(s: &str) -> Option<VUReg> {
+    let s = s.strip_prefix('$').unwrap_or(s);
+    let s = s.strip_prefix('v').unwrap_or(s);
+    s.parse().ok().and_then(VUReg::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assemble::assemble, sim::Cop0Regs};
+
+    const VADDR: u32 = 0x0400_1000;
+
+    /// run `lines` on a debugger over `src`, with DMEM holding each byte's
+    /// own low address bits, returning what the last line shows
+    fn exec(src: &str, lines: &[&str]) -> String {
+        let bytes = assemble(src, VADDR)
+            .unwrap()
+            .iter()
+            .flat_map(|w| w.to_be_bytes())
+            .collect::<Vec<_>>();
+        let dmem = (0..MEM_SIZE).map(|i| i as u8).collect::<Vec<_>>();
+        let ucode = Microcode::new(&bytes, VADDR).with_dmem(&dmem);
+        let mut dbg = Debugger::new(ucode, PrintOpts::default(), Cop0Regs::default()).unwrap();
+        let mut out = String::new();
+        for line in lines {
+            out.clear();
+            assert!(dbg.exec(line, &mut out).unwrap());
+        }
+        out
+    }
+
+    const SRC: &str = "
+        lw t0, 0xFF8(r0)
+        bne t0, r0, done
+        addiu t1, r0, 1
+        addiu t1, r0, 2
+    done:
+        break
+    ";
+
+    #[test]
+    fn step_shows_next_instruction() {
+        assert_eq!(
+            exec(SRC, &["s"]),
+            "=>  04001004: 15000002  bne t0, r0, @L04001010\n"
+        );
+        assert_eq!(
+            exec(SRC, &["s 2"]),
+            "=>  04001008: 24090001  addiu t1, r0, 1\n    (delay slot, then 04001010)\n"
+        );
+        assert_eq!(exec(SRC, &["s", ""]), exec(SRC, &["s 2"]));
+        assert_eq!(exec(SRC, &["s two"]), "`two` is not a number\n");
+    }
+
+    #[test]
+    fn step_stops_at_breakpoint() {
+        assert_eq!(
+            exec(SRC, &["b @L04001010", "s 5"]),
+            "breakpoint\n@L04001010:\n=>* 04001010: 0000000D  break 0\n"
+        );
+        assert_eq!(
+            exec(SRC, &["b @L04001010", "s 5", "s"]),
+            "halted by break\n=>  04001014: 00000000  nop\n"
+        );
+    }
+
+    #[test]
+    fn break_and_delete() {
+        assert_eq!(exec(SRC, &["b @L04001010"]), "breakpoint at 04001010\n");
+        assert_eq!(
+            exec(SRC, &["b 0x1004", "b 0x10", "b"]),
+            "  * 04001004: 15000002  bne t0, r0, @L04001010\n\
+             @L04001010:\n  * 04001010: 0000000D  break 0\n"
+        );
+        assert_eq!(exec(SRC, &["b 0x6"]), "`0x6` is not word aligned\n");
+        assert_eq!(exec(SRC, &["b nowhere"]), "no code at `nowhere`\n");
+
+        assert_eq!(exec(SRC, &["b 0x4", "d 0x4", "b"]), "");
+        assert_eq!(exec(SRC, &["b 0x4", "b 0x8", "d", "b"]), "");
+        assert_eq!(exec(SRC, &["d 0x4"]), "no breakpoint at 04001004\n");
+        assert_eq!(
+            exec(SRC, &["b 0x4", "d 0x4", "c"]),
+            "halted by break\n=>  04001014: 00000000  nop\n"
+        );
+    }
+
+    #[test]
+    fn dump_wraps_around_dmem() {
+        assert_eq!(
+            exec(SRC, &["x data_0x0FF8 24"]),
+            "FF8: F8 F9 FA FB FC FD FE FF 00 01 02 03 04 05 06 07\n\
+             008: 08 09 0A 0B 0C 0D 0E 0F\n"
+        );
+        assert_eq!(exec(SRC, &["x 0x1FF8 4"]), "FF8: F8 F9 FA FB\n");
+        assert_eq!(exec(SRC, &["x 0 0x2000"]).lines().count(), MEM_SIZE / 16);
+        assert_eq!(exec(SRC, &["x data_0x0FF0"]), "no data at `data_0x0FF0`\n");
+        assert_eq!(exec(SRC, &["x 0 many"]), "`many` is not a number\n");
+    }
+}
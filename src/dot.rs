@@ -0,0 +1,235 @@
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fmt::{self, Write},
+};
+
+use crate::{
+    cfg::{BasicBlock, Cfg, EdgeKind, Terminator},
+    func::Function,
+    print::{Formatter, Print},
+    symbols::Named,
+    utils::render,
+    Analysis, Microcode, PrintOpts, RspDisasmError, Sym,
+};
+
+/// Disassemble `ucode` into Graphviz DOT, one `digraph` per function.
+///
+/// Each graph holds every block reachable from the function's entry without
+/// following calls or entering another function.
+/// Taken branches are green, fallthroughs after a conditional branch red,
+/// unconditional jumps blue and delay slots dashed. Calls, and jumps out of
+/// the function, point at a separate node for their target.
+pub fn disassemble_dot(ucode: &Microcode, opts: PrintOpts) -> Result<String, RspDisasmError> {
+    let analysis = Analysis::new(ucode)?;
+    let f = Named::new(&opts, ucode.symbols);
+    let cfg = Cfg::with_indirect(&analysis.instrs, &analysis.jumps);
+
+    let mut s = String::with_capacity(ucode.imem.len() * 16);
+    for func in analysis.funcs.iter() {
+        write_function(&mut s, &cfg, func, &analysis, &f).unwrap();
+    }
+
+    Ok(s)
+}
+
+fn write_function(
+    w: &mut dyn Write,
+    cfg: &Cfg,
+    func: &Function,
+    analysis: &Analysis,
+    f: &dyn Formatter,
+) -> fmt::Result {
+    let is_outside = |addr| {
+        (addr != func.entry && analysis.funcs.get(addr).is_some()) || cfg.block(addr).is_none()
+    };
+    let name = render(|s| func.sym().print(f, s));
+    writeln!(w, "digraph \"{}\" {{", escape(&name))?;
+    writeln!(w, "\tnode [shape=box, fontname=\"monospace\"];")?;
+
+    let mut calls = BTreeSet::new();
+    for block in reachable(cfg, func.entry, is_outside) {
+        write!(w, "\t\"{:08X}\" [label=\"", block.start)?;
+        write_label(w, block, analysis, f)?;
+        writeln!(w, "\"];")?;
+
+        for edge in &block.succs {
+            if edge.kind == EdgeKind::Call {
+                calls.insert(edge.to);
+                writeln!(
+                    w,
+                    "\t\"{:08X}\" -> \"call_{:08X}\" [style=dotted];",
+                    edge.from, edge.to
+                )?;
+                continue;
+            }
+            if is_outside(edge.to) {
+                // a jump into another function or out of the decoded data
+                calls.insert(edge.to);
+                writeln!(
+                    w,
+                    "\t\"{:08X}\" -> \"call_{:08X}\" [color=blue];",
+                    edge.from, edge.to
+                )?;
+                continue;
+            }
+            let style = match (edge.kind, block.terminator) {
+                (EdgeKind::DelaySlot, _) => "style=dashed",
+                (EdgeKind::Taken, Terminator::Jump(_)) => "color=blue",
+                (EdgeKind::Taken, _) => "color=darkgreen",
+                (EdgeKind::FallThrough, Terminator::Branch(_)) => "color=red",
+                _ => "color=black",
+            };
+            writeln!(
+                w,
+                "\t\"{:08X}\" -> \"{:08X}\" [{}];",
+                edge.from, edge.to, style
+            )?;
+        }
+    }
+
+    for target in calls {
+        let sym = analysis.funcs.relabel(Sym::Global(target));
+        let name = render(|s| sym.print(f, s));
+        writeln!(
+            w,
+            "\t\"call_{:08X}\" [shape=ellipse, label=\"{}\"];",
+            target,
+            escape(&name)
+        )?;
+    }
+
+    writeln!(w, "}}")
+}
+
+/// blocks reachable from `entry` without following calls or going `outside`, in address order
+fn reachable(cfg: &Cfg, entry: u32, outside: impl Fn(u32) -> bool) -> Vec<&BasicBlock> {
+    let mut seen = BTreeSet::new();
+    let mut queue = VecDeque::from([entry]);
+    while let Some(addr) = queue.pop_front() {
+        if (addr != entry && outside(addr)) || !seen.insert(addr) {
+            continue;
+        }
+        let succs = cfg.successors(addr);
+        queue.extend(
+            succs
+                .iter()
+                .filter(|e| e.kind != EdgeKind::Call)
+                .map(|e| e.to),
+        );
+    }
+
+    seen.into_iter().filter_map(|a| cfg.block(a)).collect()
+}
+
+/// the block's labels and disassembly, as left justified DOT lines
+fn write_label(
+    w: &mut dyn Write,
+    block: &BasicBlock,
+    analysis: &Analysis,
+    f: &dyn Formatter,
+) -> fmt::Result {
+    for sym in analysis.labels_at(block.start) {
+        let name = render(|s| sym.print(f, s));
+        write!(w, "{}:\\l", escape(&name))?;
+    }
+
+    let mut delay_slot = false;
+    for instr in &block.instrs {
+        let mut text = render(|s| instr.op.print(f, s));
+        if delay_slot {
+            text.push(' ');
+            f.comment("delay slot", &mut text)?;
+        }
+        write!(w, "{:08X}  {}\\l", instr.addr, escape(&text))?;
+        delay_slot = Terminator::of(&instr.op).is_some_and(|t| t.has_delay_slot());
+    }
+
+    Ok(())
+}
+
+/// escape `s` for a quoted DOT string
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble::assemble;
+
+    const VADDR: u32 = 0x0400_1000;
+
+    fn dot(src: &str) -> String {
+        let bytes = assemble(src, VADDR)
+            .unwrap()
+            .iter()
+            .flat_map(|w| w.to_be_bytes())
+            .collect::<Vec<_>>();
+        disassemble_dot(&Microcode::new(&bytes, VADDR), PrintOpts::default()).unwrap()
+    }
+
+    const SRC: &str = "
+        bne t0, r0, skip
+        nop
+        jal sub
+        nop
+    skip:
+        break
+    sub:
+        j sub
+        nop
+    ";
+
+    #[test]
+    fn one_graph_per_function() {
+        assert_eq!(
+            dot(SRC),
+            r#"digraph "subr_04001000" {
+	node [shape=box, fontname="monospace"];
+	"04001000" [label="subr_04001000:\l04001000  bne t0, r0, @L04001010\l04001004  nop /* delay slot */\l"];
+	"04001000" -> "04001010" [color=darkgreen];
+	"04001000" -> "04001008" [color=red];
+	"04001008" [label="04001008  jal subr_04001014\l0400100C  nop /* delay slot */\l"];
+	"04001008" -> "call_04001014" [style=dotted];
+	"04001008" -> "04001010" [color=black];
+	"04001010" [label="@L04001010:\l04001010  break 0\l"];
+	"call_04001014" [shape=ellipse, label="subr_04001014"];
+}
+digraph "subr_04001014" {
+	node [shape=box, fontname="monospace"];
+	"04001014" [label="subr_04001014:\l04001014  j subr_04001014\l04001018  nop /* delay slot */\l"];
+	"04001014" -> "04001014" [color=blue];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn jump_to_another_function_leaves_the_graph() {
+        let s = dot("
+            jal a
+            nop
+            jal b
+            nop
+            break
+        a:
+            j b
+            nop
+        b:
+            jr ra
+            nop
+        ");
+        let a = s
+            .split("digraph")
+            .find(|g| g.starts_with(" \"subr_04001014\""))
+            .unwrap();
+        assert!(a.contains("\"04001014\" -> \"call_0400101C\" [color=blue];"));
+        assert!(a.contains("\"call_0400101C\" [shape=ellipse, label=\"subr_0400101C\"];"));
+        assert!(!a.contains("\"0400101C\" ["));
+    }
+
+    #[test]
+    fn escapes_quotes_and_backslashes() {
+        assert_eq!(escape(r#"a"b\c"#), r#"a\"b\\c"#);
+    }
+}
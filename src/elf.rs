@@ -0,0 +1,615 @@
+//! Reading and writing the 32-bit big-endian MIPS ELF files that RSP
+//! toolchains use.
+//!
+//! [`Elf`] reads linked and relocatable files, but only what a disassembly
+//! needs: section headers, section contents and the symbol table.
+//! [`disassemble_elf`] writes a relocatable object.
+
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt,
+};
+
+use crate::{
+    print::Print,
+    symbols::{self, Named, Symbol, SymbolType, Symbols},
+    utils::render,
+    Analysis, Microcode, PrintOpts, RspDisasmError, Sym, Syntax,
+};
+
+const MAGIC: &[u8] = b"\x7FELF";
+const CLASS_32: u8 = 1;
+const DATA_BIG_ENDIAN: u8 = 2;
+const VERSION_CURRENT: u8 = 1;
+const TYPE_REL: u16 = 1;
+const MACHINE_MIPS: u16 = 8;
+const EF_MIPS_NOREORDER: u32 = 1;
+
+const EHDR_SIZE: usize = 52;
+const SHDR_SIZE: usize = 40;
+const SYM_SIZE: usize = 16;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_NOBITS: u32 = 8;
+
+const SHF_WRITE: u32 = 1;
+const SHF_ALLOC: u32 = 2;
+const SHF_EXECINSTR: u32 = 4;
+
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+const STT_SECTION: u8 = 3;
+const STT_FILE: u8 = 4;
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const SHN_UNDEF: u16 = 0;
+const SHN_ABS: u16 = 0xFFF1;
+
+/// section indices of the written object
+const TEXT_INDEX: u16 = 1;
+const DATA_INDEX: u16 = 2;
+
+/// size of the DMEM image built from `.data`
+const DMEM_SIZE: usize = 0x1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ElfError {
+    /// the file does not start with the ELF magic
+    NotElf,
+    /// the file is not 32-bit, big-endian or MIPS
+    Unsupported(&'static str),
+    /// a header, table or section runs past the end of the file
+    Truncated,
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotElf => write!(f, "not an ELF file"),
+            Self::Unsupported(what) => write!(f, "unsupported ELF file: {}", what),
+            Self::Truncated => write!(f, "ELF file is truncated"),
+        }
+    }
+}
+
+impl std::error::Error for ElfError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section<'a> {
+    pub name: String,
+    pub kind: u32,
+    /// address the section is linked at, or zero in a relocatable object
+    pub addr: u32,
+    /// contents, empty for `.bss` and other sections without file data
+    pub data: &'a [u8],
+    pub size: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElfSymbol {
+    pub name: String,
+    pub value: u32,
+    pub size: u32,
+    /// `STT_*` type
+    pub kind: u8,
+    /// `STB_*` binding
+    pub bind: u8,
+    /// index of the section the symbol is defined in
+    pub section: u16,
+}
+
+/// A parsed ELF file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Elf<'a> {
+    sections: Vec<Section<'a>>,
+    symbols: Vec<ElfSymbol>,
+}
+
+impl<'a> Elf<'a> {
+    /// whether `data` starts like an ELF file
+    pub fn is_elf(data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+
+    pub fn parse(data: &'a [u8]) -> Result<Self, ElfError> {
+        if !Self::is_elf(data) {
+            return Err(ElfError::NotElf);
+        }
+        let r = Reader(data);
+        if r.u8(4)? != CLASS_32 {
+            return Err(ElfError::Unsupported("not 32-bit"));
+        }
+        if r.u8(5)? != DATA_BIG_ENDIAN {
+            return Err(ElfError::Unsupported("not big-endian"));
+        }
+        if r.u16(18)? != MACHINE_MIPS {
+            return Err(ElfError::Unsupported("not MIPS"));
+        }
+
+        let shoff = r.u32(32)? as usize;
+        let shentsize = r.u16(46)? as usize;
+        let shnum = r.u16(48)? as usize;
+        let shstrndx = r.u16(50)? as usize;
+
+        let headers = (0..shnum)
+            .map(|i| SectionHeader::read(&r, shoff + i * shentsize))
+            .collect::<Result<Vec<_>, _>>()?;
+        let names = match headers.get(shstrndx) {
+            Some(h) => h.contents(data)?,
+            None => &[],
+        };
+        let sections = headers
+            .iter()
+            .map(|h| {
+                let data = match h.kind {
+                    SHT_NOBITS => &[],
+                    _ => h.contents(data)?,
+                };
+                Ok(Section {
+                    name: c_str(names, h.name),
+                    kind: h.kind,
+                    addr: h.addr,
+                    data,
+                    size: h.size,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut symbols = Vec::new();
+        for h in headers.iter().filter(|h| h.kind == SHT_SYMTAB) {
+            let table = h.contents(data)?;
+            let strings = match headers.get(h.link as usize) {
+                Some(s) => s.contents(data)?,
+                None => &[],
+            };
+            let t = Reader(table);
+            let entsize = (h.entsize as usize).max(16);
+            // the first entry is always the null symbol
+            for off in (entsize..table.len()).step_by(entsize) {
+                let info = t.u8(off + 12)?;
+                symbols.push(ElfSymbol {
+                    name: c_str(strings, t.u32(off)?),
+                    value: t.u32(off + 4)?,
+                    size: t.u32(off + 8)?,
+                    kind: info & 0xF,
+                    bind: info >> 4,
+                    section: t.u16(off + 14)?,
+                });
+            }
+        }
+
+        Ok(Self { sections, symbols })
+    }
+
+    pub fn sections(&self) -> &[Section<'a>] {
+        &self.sections
+    }
+
+    pub fn section(&self, name: &str) -> Option<&Section<'a>> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    /// every entry of the symbol table but the null symbol
+    pub fn symtab(&self) -> &[ElfSymbol] {
+        &self.symbols
+    }
+
+    /// the code: `.text`
+    pub fn text(&self) -> Option<&Section<'a>> {
+        self.section(".text")
+    }
+
+    /// A DMEM image holding `.data` at its address within DMEM
+    pub fn dmem(&self) -> Option<Vec<u8>> {
+        let data = self.section(".data")?;
+        let start = data.addr as usize % DMEM_SIZE;
+        let mut dmem = vec![0; (start + data.data.len()).min(DMEM_SIZE)];
+        let len = dmem.len() - start;
+        dmem[start..].copy_from_slice(&data.data[..len]);
+
+        Some(dmem)
+    }
+
+    /// The names of the code and data symbols, as labels.
+    ///
+    /// Symbols in `.text` name IMEM and all others DMEM; absolute symbols are
+    /// only kept if they lie within `.data`. Where several share an address, a
+    /// global symbol wins over a local one.
+    pub fn to_symbols(&self) -> Symbols {
+        let text = self.sections.iter().position(|s| s.name == ".text");
+        let data = self
+            .section(".data")
+            .map_or(0..0, |d| d.addr..d.addr.wrapping_add(d.size));
+        let mut named = self
+            .symbols
+            .iter()
+            .filter(|s| !matches!(s.kind, STT_SECTION | STT_FILE) && s.section != SHN_UNDEF)
+            .filter(|s| s.section != SHN_ABS || data.contains(&s.value))
+            .filter(|s| symbols::parse_name(&s.name).is_ok())
+            .collect::<Vec<_>>();
+        named.sort_by_key(|s| s.bind != STB_LOCAL);
+
+        let mut symbols = Symbols::new();
+        for sym in named {
+            let ty = if Some(sym.section as usize) == text {
+                SymbolType::Code
+            } else {
+                SymbolType::Data
+            };
+            symbols.insert(Symbol {
+                name: sym.name.clone(),
+                addr: sym.value,
+                size: (sym.size != 0).then_some(sym.size),
+                ty,
+            });
+        }
+
+        symbols
+    }
+}
+
+/// Disassemble `ucode` into a relocatable MIPS ELF object.
+///
+/// The instruction words are placed in `.text` and DMEM, if any, in `.data`,
+/// unchanged. Every label of the disassembly is a symbol, named as in
+/// [`disassemble_gas`](crate::disassemble_gas): functions and data are global,
+/// branch targets local. Jumps are not relocated, so link `.text` at
+/// `ucode.vaddr` and `.data` at DMEM address zero.
+pub fn disassemble_elf(ucode: &Microcode) -> Result<Vec<u8>, RspDisasmError> {
+    let opts = PrintOpts {
+        syntax: Syntax::Gnu,
+        ..Default::default()
+    };
+    let analysis = Analysis::new(ucode)?;
+    let f = Named::new(&opts, ucode.symbols);
+    let name = |sym: Sym| render(|s| sym.print(&f, s));
+    let bind = |sym: Sym| {
+        if sym.is_global() {
+            STB_GLOBAL
+        } else {
+            STB_LOCAL
+        }
+    };
+    let range = ucode.vaddr..ucode.vaddr.wrapping_add(ucode.imem.len() as u32);
+
+    let section = |section| ElfSymbol {
+        name: String::new(),
+        value: 0,
+        size: 0,
+        kind: STT_SECTION,
+        bind: STB_LOCAL,
+        section,
+    };
+    let mut syms = vec![section(TEXT_INDEX)];
+    if ucode.dmem.is_some() {
+        syms.push(section(DATA_INDEX));
+    }
+    for instr in &analysis.instrs {
+        let func = analysis.funcs.get(instr.addr);
+        for sym in analysis.labels_at(instr.addr) {
+            let (kind, size) = match func {
+                Some(func) if func.sym() == sym => (STT_FUNC, func.end - instr.addr),
+                _ => (STT_NOTYPE, 0),
+            };
+            syms.push(ElfSymbol {
+                name: name(sym),
+                value: instr.addr - ucode.vaddr,
+                size,
+                kind,
+                bind: bind(sym),
+                section: TEXT_INDEX,
+            });
+        }
+    }
+    if ucode.dmem.is_some() {
+        for (&addr, label) in &analysis.data {
+            let sym = Sym::Data(addr);
+            syms.push(ElfSymbol {
+                name: name(sym),
+                value: addr,
+                size: label.len.unwrap_or(0),
+                kind: STT_OBJECT,
+                bind: bind(sym),
+                section: DATA_INDEX,
+            });
+        }
+    }
+    // branch and jump targets that are not part of this object
+    let external = analysis
+        .syms
+        .iter()
+        .filter(|sym| !range.contains(&sym.value()))
+        .map(|&sym| (sym.value(), name(sym)))
+        .collect::<BTreeSet<_>>();
+    for (addr, name) in external {
+        syms.push(ElfSymbol {
+            name,
+            value: addr,
+            size: 0,
+            kind: STT_NOTYPE,
+            bind: STB_LOCAL,
+            section: SHN_ABS,
+        });
+    }
+
+    order_symbols(&mut syms);
+    let first_global = syms.iter().position(|s| s.bind != STB_LOCAL);
+
+    let mut strtab = vec![0];
+    let mut symtab = vec![0; SYM_SIZE];
+    for sym in &syms {
+        let name = match sym.name.as_str() {
+            "" => 0,
+            name => {
+                let off = strtab.len() as u32;
+                strtab.extend_from_slice(name.as_bytes());
+                strtab.push(0);
+                off
+            }
+        };
+        symtab.extend_from_slice(&name.to_be_bytes());
+        symtab.extend_from_slice(&sym.value.to_be_bytes());
+        symtab.extend_from_slice(&sym.size.to_be_bytes());
+        symtab.push((sym.bind << 4) | sym.kind);
+        symtab.push(0);
+        symtab.extend_from_slice(&sym.section.to_be_bytes());
+    }
+
+    let mut sections = vec![OutSection {
+        name: ".text",
+        kind: SHT_PROGBITS,
+        flags: SHF_ALLOC | SHF_EXECINSTR,
+        data: ucode.imem,
+        align: 8,
+        ..Default::default()
+    }];
+    if let Some(dmem) = ucode.dmem {
+        sections.push(OutSection {
+            name: ".data",
+            kind: SHT_PROGBITS,
+            flags: SHF_ALLOC | SHF_WRITE,
+            data: dmem,
+            align: 16,
+            ..Default::default()
+        });
+    }
+    let symtab_index = sections.len() as u32 + 1;
+    sections.push(OutSection {
+        name: ".symtab",
+        kind: SHT_SYMTAB,
+        data: &symtab,
+        // the string table follows
+        link: symtab_index + 1,
+        info: first_global.unwrap_or(syms.len()) as u32 + 1,
+        align: 4,
+        entsize: SYM_SIZE as u32,
+        ..Default::default()
+    });
+    sections.push(OutSection {
+        name: ".strtab",
+        kind: SHT_STRTAB,
+        data: &strtab,
+        ..Default::default()
+    });
+
+    let mut shstrtab = vec![0];
+    let mut names = Vec::new();
+    for section in sections.iter().map(|s| s.name).chain([".shstrtab"]) {
+        names.push(shstrtab.len() as u32);
+        shstrtab.extend_from_slice(section.as_bytes());
+        shstrtab.push(0);
+    }
+    sections.push(OutSection {
+        name: ".shstrtab",
+        kind: SHT_STRTAB,
+        data: &shstrtab,
+        ..Default::default()
+    });
+
+    let mut out = vec![0; EHDR_SIZE];
+    let mut offsets = Vec::new();
+    for section in &sections {
+        out.resize(align(out.len(), section.align.max(1) as usize), 0);
+        offsets.push(out.len() as u32);
+        out.extend_from_slice(section.data);
+    }
+    out.resize(align(out.len(), 4), 0);
+    let shoff = out.len() as u32;
+
+    out.extend_from_slice(&[0; SHDR_SIZE]);
+    for ((section, name), offset) in sections.iter().zip(names).zip(offsets) {
+        for field in [
+            name,
+            section.kind,
+            section.flags,
+            0,
+            offset,
+            section.data.len() as u32,
+            section.link,
+            section.info,
+            section.align.max(1),
+            section.entsize,
+        ] {
+            out.extend_from_slice(&field.to_be_bytes());
+        }
+    }
+
+    let header = &mut out[..EHDR_SIZE];
+    header[..4].copy_from_slice(MAGIC);
+    header[4] = CLASS_32;
+    header[5] = DATA_BIG_ENDIAN;
+    header[6] = VERSION_CURRENT;
+    header[16..18].copy_from_slice(&TYPE_REL.to_be_bytes());
+    header[18..20].copy_from_slice(&MACHINE_MIPS.to_be_bytes());
+    header[20..24].copy_from_slice(&(VERSION_CURRENT as u32).to_be_bytes());
+    header[32..36].copy_from_slice(&shoff.to_be_bytes());
+    header[36..40].copy_from_slice(&EF_MIPS_NOREORDER.to_be_bytes());
+    header[40..42].copy_from_slice(&(EHDR_SIZE as u16).to_be_bytes());
+    header[46..48].copy_from_slice(&(SHDR_SIZE as u16).to_be_bytes());
+    header[48..50].copy_from_slice(&(sections.len() as u16 + 1).to_be_bytes());
+    header[50..52].copy_from_slice(&(sections.len() as u16).to_be_bytes());
+
+    Ok(out)
+}
+
+/// a section of a written object, before it is laid out
+#[derive(Default)]
+struct OutSection<'a> {
+    name: &'static str,
+    kind: u32,
+    flags: u32,
+    data: &'a [u8],
+    link: u32,
+    info: u32,
+    align: u32,
+    entsize: u32,
+}
+
+/// Put locals before globals, as the symbol table lists them, and drop
+/// repeats of a symbol, which a user's symbol naming more than one label at the
+/// same address makes. A global is never dropped for a local of the same name.
+fn order_symbols(syms: &mut Vec<ElfSymbol>) {
+    syms.sort_by_key(|s| s.bind != STB_LOCAL);
+    let mut seen = HashSet::new();
+    syms.retain(|s| s.name.is_empty() || seen.insert((s.name.clone(), s.bind, s.value)));
+}
+
+const fn align(offset: usize, align: usize) -> usize {
+    offset.div_ceil(align) * align
+}
+
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    addr: u32,
+    offset: u32,
+    size: u32,
+    link: u32,
+    entsize: u32,
+}
+
+impl SectionHeader {
+    fn read(r: &Reader, off: usize) -> Result<Self, ElfError> {
+        Ok(Self {
+            name: r.u32(off)?,
+            kind: r.u32(off + 4)?,
+            addr: r.u32(off + 12)?,
+            offset: r.u32(off + 16)?,
+            size: r.u32(off + 20)?,
+            link: r.u32(off + 24)?,
+            entsize: r.u32(off + 36)?,
+        })
+    }
+
+    fn contents<'a>(&self, data: &'a [u8]) -> Result<&'a [u8], ElfError> {
+        let start = self.offset as usize;
+        data.get(start..start + self.size as usize)
+            .ok_or(ElfError::Truncated)
+    }
+}
+
+/// big-endian reads that fail past the end of the data
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn bytes<const N: usize>(&self, off: usize) -> Result<[u8; N], ElfError> {
+        self.0
+            .get(off..off + N)
+            .and_then(|b| b.try_into().ok())
+            .ok_or(ElfError::Truncated)
+    }
+
+    fn u8(&self, off: usize) -> Result<u8, ElfError> {
+        Ok(self.bytes::<1>(off)?[0])
+    }
+
+    fn u16(&self, off: usize) -> Result<u16, ElfError> {
+        self.bytes(off).map(u16::from_be_bytes)
+    }
+
+    fn u32(&self, off: usize) -> Result<u32, ElfError> {
+        self.bytes(off).map(u32::from_be_bytes)
+    }
+}
+
+/// the NUL-terminated string at `off` in a string table
+fn c_str(table: &[u8], off: u32) -> String {
+    let s = table.get(off as usize..).unwrap_or_default();
+    let end = s.iter().position(|&b| b == 0).unwrap_or(s.len());
+    String::from_utf8_lossy(&s[..end]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(name: &str, addr: u32, size: u32) -> Section<'static> {
+        Section {
+            name: name.to_string(),
+            kind: SHT_PROGBITS,
+            addr,
+            data: &[],
+            size,
+        }
+    }
+
+    fn symbol(name: &str, value: u32, bind: u8, section: u16) -> ElfSymbol {
+        ElfSymbol {
+            name: name.to_string(),
+            value,
+            size: 0,
+            kind: STT_NOTYPE,
+            bind,
+            section,
+        }
+    }
+
+    #[test]
+    fn absolute_symbols_only_name_data() {
+        let elf = Elf {
+            sections: vec![
+                section("", 0, 0),
+                section(".text", 0x0400_1000, 0x20),
+                section(".data", 0x0400_0000, 0x100),
+            ],
+            symbols: vec![
+                symbol("main", 0x0400_1000, STB_GLOBAL, TEXT_INDEX),
+                symbol("inside", 0x0400_0040, STB_LOCAL, SHN_ABS),
+                symbol("past_end", 0x0400_0100, STB_LOCAL, SHN_ABS),
+                symbol("far_code", 0x0400_2040, STB_LOCAL, SHN_ABS),
+            ],
+        };
+        let symbols = elf.to_symbols();
+        let names = symbols.iter().map(|s| s.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["main", "inside"]);
+        assert_eq!(symbols.name(Sym::Data(0x40)), Some("inside"));
+    }
+
+    #[test]
+    fn same_name_local_and_global_are_kept() {
+        let mut syms = vec![
+            symbol("", 0, STB_LOCAL, TEXT_INDEX),
+            symbol("foo", 0x10, STB_GLOBAL, TEXT_INDEX),
+            symbol("foo", 0x10, STB_LOCAL, TEXT_INDEX),
+            symbol("foo", 0x10, STB_LOCAL, TEXT_INDEX),
+            symbol("", 0, STB_LOCAL, DATA_INDEX),
+        ];
+        order_symbols(&mut syms);
+        let order = syms
+            .iter()
+            .map(|s| (s.name.as_str(), s.bind, s.section))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            order,
+            [
+                ("", STB_LOCAL, TEXT_INDEX),
+                ("foo", STB_LOCAL, TEXT_INDEX),
+                ("", STB_LOCAL, DATA_INDEX),
+                ("foo", STB_GLOBAL, TEXT_INDEX),
+            ]
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    ops::Range,
+};
+
+use crate::{
+    cfg::{Cfg, EdgeKind, Terminator},
+    regs::su::GpReg,
+    Instruction, Sym,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FuncKind {
+    /// the first instruction of the decoded data
+    Entry,
+    /// target of a `jal` or linking branch, expected to return with `jr ra`
+    Subroutine,
+    /// target of a `jr` through a jump table, or known code that no branch,
+    /// jump or call reaches
+    Handler,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Function {
+    pub entry: u32,
+    /// address following the last instruction
+    pub end: u32,
+    pub kind: FuncKind,
+    /// whether a `jr ra` can be reached from the entry
+    pub returns: bool,
+}
+
+impl Function {
+    /// label of the function's entry
+    pub const fn sym(&self) -> Sym {
+        match self.kind {
+            FuncKind::Handler => Sym::Handler(self.entry),
+            FuncKind::Entry | FuncKind::Subroutine => Sym::Global(self.entry),
+        }
+    }
+
+    pub const fn contains(&self, addr: u32) -> bool {
+        addr >= self.entry && addr < self.end
+    }
+}
+
+/// The functions of a control flow graph, found from its calls and unreachable code
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Functions {
+    funcs: BTreeMap<u32, Function>,
+    /// blocks that nothing reaches and that are not known entries
+    unreached: BTreeSet<u32>,
+    range: Range<u32>,
+}
+
+impl Functions {
+    /// Find the functions of `cfg`, whose code is entered at `entry` and may be
+    /// entered at the `known` addresses, such as named code.
+    ///
+    /// A function runs from its entry to the end of the last block it reaches
+    /// without calling or jumping into another function, but never past the next
+    /// function's entry. Code that nothing reaches only starts a function if it
+    /// is known, as it is often padding or the targets of an unresolved jump.
+    pub fn analyze(cfg: &Cfg, entry: u32, known: &BTreeSet<u32>) -> Self {
+        let range = match (cfg.blocks().next(), cfg.blocks().last()) {
+            (Some(first), Some(last)) => first.start..last.end(),
+            _ => return Self::default(),
+        };
+
+        let mut kinds = BTreeMap::new();
+        if cfg.block(entry).is_some() {
+            kinds.insert(entry, FuncKind::Entry);
+        }
+        for edge in cfg.blocks().flat_map(|b| &b.succs) {
+            if cfg.block(edge.to).is_none() {
+                continue;
+            }
+            match edge.kind {
+                EdgeKind::Call => {
+                    kinds.entry(edge.to).or_insert(FuncKind::Subroutine);
+                }
+                EdgeKind::Indirect => {
+                    kinds.entry(edge.to).or_insert(FuncKind::Handler);
+                }
+                _ => (),
+            }
+        }
+        let mut unreached = BTreeSet::new();
+        for block in cfg.blocks().filter(|b| b.preds.is_empty()) {
+            if known.contains(&block.start) {
+                kinds.entry(block.start).or_insert(FuncKind::Handler);
+            } else if !kinds.contains_key(&block.start) {
+                unreached.insert(block.start);
+            }
+        }
+
+        let entries = kinds.keys().copied().collect::<Vec<_>>();
+        let mut funcs = BTreeMap::new();
+        for (i, (&entry, &kind)) in kinds.iter().enumerate() {
+            let limit = entries.get(i + 1).copied().unwrap_or(range.end);
+            let mut end = entry;
+            let mut returns = false;
+
+            let mut seen = BTreeSet::new();
+            let mut queue = VecDeque::from([entry]);
+            while let Some(addr) = queue.pop_front() {
+                let block = match cfg.block(addr) {
+                    Some(b) if seen.insert(addr) => b,
+                    _ => continue,
+                };
+                if (entry..limit).contains(&block.start) {
+                    end = end.max(block.end().min(limit));
+                }
+                returns |= block.terminator == Terminator::JumpReg(GpReg::RA);
+                queue.extend(
+                    block
+                        .succs
+                        .iter()
+                        .filter(|e| e.kind != EdgeKind::Call && !kinds.contains_key(&e.to))
+                        .map(|e| e.to),
+                );
+            }
+
+            let func = Function {
+                entry,
+                end,
+                kind,
+                returns,
+            };
+            funcs.insert(entry, func);
+        }
+
+        Self {
+            funcs,
+            unreached,
+            range,
+        }
+    }
+
+    /// every function, in address order
+    pub fn iter(&self) -> impl Iterator<Item = &Function> {
+        self.funcs.values()
+    }
+
+    /// starts of the code that nothing reaches and that is not a function, in address order
+    pub fn unreached(&self) -> impl Iterator<Item = u32> + '_ {
+        self.unreached.iter().copied()
+    }
+
+    /// the function starting at `entry`
+    pub fn get(&self, entry: u32) -> Option<&Function> {
+        self.funcs.get(&entry)
+    }
+
+    /// the function that `addr` is a part of
+    pub fn containing(&self, addr: u32) -> Option<&Function> {
+        self.funcs
+            .range(..=addr)
+            .next_back()
+            .map(|(_, f)| f)
+            .filter(|f| f.contains(addr))
+    }
+
+    /// The label kind of `sym` given these functions: function entries are named
+    /// for their kind and any other target within the analyzed code is local
+    pub fn relabel(&self, sym: Sym) -> Sym {
+        match self.funcs.get(&sym.value()) {
+            Some(func) => func.sym(),
+            None if self.range.contains(&sym.value()) => Sym::Static(sym.value()),
+            None => sym,
+        }
+    }
+
+    /// `instr` with its target relabeled
+    pub fn relabel_instr(&self, mut instr: Instruction) -> Instruction {
+        if let Some(target) = instr.target() {
+            instr.op.set_target(self.relabel(target));
+        }
+        instr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode_bytes;
+
+    const VADDR: u32 = 0x0400_1000;
+
+    /// `break`, padding, then code that nothing reaches at 0x0400100C
+    fn analyze(known: &[u32]) -> Functions {
+        let words = [
+            0x0000000Du32, // break
+            0x00000000,    // nop
+            0x00000000,    // nop
+            0x0000000D,    // break
+            0x24020001,    // addiu v0, zero, 1
+            0x03E00008,    // jr ra
+            0x00000000,    // nop
+        ];
+        let bytes = words
+            .iter()
+            .flat_map(|w| w.to_be_bytes())
+            .collect::<Vec<_>>();
+        let instrs = decode_bytes(&bytes, VADDR).unwrap();
+        let cfg = Cfg::new(&instrs);
+        Functions::analyze(&cfg, VADDR, &known.iter().copied().collect())
+    }
+
+    #[test]
+    fn unreached_code_is_not_a_handler() {
+        let funcs = analyze(&[]);
+        let entries = funcs.iter().map(|f| (f.entry, f.kind)).collect::<Vec<_>>();
+        assert_eq!(entries, [(VADDR, FuncKind::Entry)]);
+        assert_eq!(
+            funcs.unreached().collect::<Vec<_>>(),
+            [0x0400_1004, 0x0400_1010]
+        );
+        assert_eq!(
+            funcs.relabel(Sym::Global(0x0400_1010)),
+            Sym::Static(0x0400_1010)
+        );
+    }
+
+    #[test]
+    fn named_unreached_code_is_a_handler() {
+        let funcs = analyze(&[0x0400_1010]);
+        let handler = funcs.get(0x0400_1010).unwrap();
+        assert_eq!(handler.kind, FuncKind::Handler);
+        assert_eq!(handler.end, 0x0400_101C);
+        assert!(handler.returns);
+        assert_eq!(funcs.unreached().collect::<Vec<_>>(), [0x0400_1004]);
+    }
+}
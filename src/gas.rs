@@ -0,0 +1,77 @@
+use std::{collections::BTreeSet, fmt::Write};
+
+use crate::{
+    data::write_data, print::Print, symbols::Named, write_listing, Analysis, Microcode, PrintOpts,
+    RspDisasmError, Syntax,
+};
+
+/// Disassemble `ucode` into a GNU as source file for libdragon's `rsp.inc`.
+///
+/// The code is placed in `.text`; link it at `ucode.vaddr` so that jumps
+/// resolve to the original addresses. DMEM, if any, is placed in `.data`.
+pub fn disassemble_gas(ucode: &Microcode, opts: PrintOpts) -> Result<String, RspDisasmError> {
+    let opts = PrintOpts {
+        syntax: Syntax::Gnu,
+        ..opts
+    };
+    let analysis = Analysis::new(ucode)?;
+    let f = Named::new(&opts, ucode.symbols);
+    let range = ucode.vaddr..ucode.vaddr.wrapping_add(ucode.imem.len() as u32);
+
+    let mut s = String::with_capacity(analysis.instrs.len() * 32 + 256);
+    writeln!(&mut s, "#include <rsp.inc>").unwrap();
+    writeln!(&mut s).unwrap();
+    // the listing already has its delay slots filled
+    writeln!(&mut s, ".set noreorder").unwrap();
+    writeln!(&mut s, ".set noat").unwrap();
+    writeln!(&mut s).unwrap();
+
+    // branch and jump targets that are not part of this file
+    let external = analysis
+        .syms
+        .iter()
+        .filter(|sym| !range.contains(&sym.value()))
+        .map(|sym| (sym.value(), *sym))
+        .collect::<BTreeSet<_>>();
+    for (_, sym) in &external {
+        write!(&mut s, ".set ").unwrap();
+        sym.print(&f, &mut s).unwrap();
+        writeln!(&mut s, ", {:#010X}", sym.value()).unwrap();
+    }
+    if !external.is_empty() {
+        writeln!(&mut s).unwrap();
+    }
+
+    writeln!(&mut s, ".text").unwrap();
+    write_listing(&mut s, &analysis, &f).unwrap();
+
+    if let Some(dmem) = ucode.dmem {
+        writeln!(&mut s).unwrap();
+        writeln!(&mut s, ".data").unwrap();
+        write_data(&mut s, dmem, &analysis.data, &f).unwrap();
+    }
+
+    Ok(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dmem_labels() {
+        // lw t0, 0x40(zero); lqv $v01[0], 0x40(zero); break
+        let imem = [0x8C080040u32, 0xC8012004, 0x0000000D]
+            .iter()
+            .flat_map(|w| w.to_be_bytes())
+            .collect::<Vec<_>>();
+        let dmem = [0; 0x80];
+        let ucode = Microcode::new(&imem, 0x0400_1000).with_dmem(&dmem);
+        let s = disassemble_gas(&ucode, PrintOpts::default()).unwrap();
+
+        assert!(s.contains("\tlw t0, %lo(data_0x0040)(zero)\n"), "{}", s);
+        // rsp.inc scales the offset itself, so vector ops keep it numeric
+        assert!(s.contains("\tlqv $v01, 0, 0x40, zero\n"), "{}", s);
+        assert!(s.contains("data_0x0040:\n"), "{}", s);
+    }
+}
@@ -0,0 +1,99 @@
+use std::fmt;
+
+use crate::{
+    ops::RspOpcode,
+    print::{Formatter, Print},
+    regs::{
+        cop0::Cop0Reg,
+        su::GpReg,
+        vu::{Element, VUCtrlReg, VUReg},
+    },
+    sym::Sym,
+    PrintOpts,
+};
+
+/// A single decoded RSP instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    /// address of the instruction
+    pub addr: u32,
+    /// raw instruction word
+    pub word: u32,
+    /// decoded opcode and operands
+    pub op: RspOpcode,
+}
+
+impl Instruction {
+    pub fn mnemonic(&self) -> &'static str {
+        self.op.mnemonic()
+    }
+
+    pub fn operands(&self) -> Vec<Operand> {
+        self.op.operands()
+    }
+
+    /// branch or jump target of this instruction, if any
+    pub fn target(&self) -> Option<Sym> {
+        self.op.get_symbol()
+    }
+
+    /// DMEM label of the address this load or store accesses, if known
+    pub fn data(&self) -> Option<Sym> {
+        self.op.get_data_symbol()
+    }
+
+    /// re-encode this instruction at its address
+    pub fn encode(&self) -> u32 {
+        self.op.encode(self.addr)
+    }
+
+    pub fn is_supported(&self) -> bool {
+        !matches!(self.op, RspOpcode::Unsupported(_))
+    }
+
+    /// print this instruction with a custom [`Formatter`]
+    pub fn fmt_with(&self, f: &dyn Formatter, w: &mut dyn fmt::Write) -> fmt::Result {
+        self.op.print(f, w)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.op.print(&PrintOpts::default(), f)
+    }
+}
+
+/// A typed operand of an [`RspOpcode`], listed in assembly order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    GpReg(GpReg),
+    VuReg(VUReg),
+    VuCtrlReg(VUCtrlReg),
+    Cop0Reg(Cop0Reg),
+    /// element modifier of a vector compute op (`$v3[1h]`)
+    Element(Element),
+    /// lane index into a vector register for moves and loads/stores (`$v3[4]`)
+    ElementIndex(u8),
+    /// sign or zero extended immediate, or a shift amount
+    Imm(i32),
+    /// unsigned field without arithmetic meaning (`break` code, raw word)
+    Code(u32),
+    /// load/store address `offset(base)`
+    Mem {
+        base: GpReg,
+        offset: i16,
+        /// DMEM label of the address, if it is known
+        data: Option<Sym>,
+    },
+    /// branch or jump target
+    Target(Sym),
+}
+
+/// Decode a single instruction `word` located at `pc`
+pub fn decode(word: u32, pc: u32) -> Instruction {
+    Instruction {
+        addr: pc,
+        word,
+        op: RspOpcode::decode(word, pc),
+    }
+}
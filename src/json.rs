@@ -0,0 +1,127 @@
+use std::fmt::{self, Write};
+
+use crate::{
+    print::{Formatter, Print},
+    symbols::Named,
+    utils::render,
+    Analysis, Element, Instruction, Microcode, Operand, PrintOpts, RspDisasmError,
+};
+
+/// Disassemble `ucode` into JSON Lines, one object per instruction:
+///
+/// ```text
+/// {"addr":2214592512,"word":3221487616,"mnemonic":"lqv","text":"lqv $v1[0], 0x10(a0)",
+///  "labels":[],"operands":[{"kind":"vu_reg","index":1,"name":"$v1"},...]}
+/// ```
+///
+/// Register and label names are rendered with `opts`.
+pub fn disassemble_json(ucode: &Microcode, opts: PrintOpts) -> Result<String, RspDisasmError> {
+    let analysis = Analysis::new(ucode)?;
+    let f = Named::new(&opts, ucode.symbols);
+
+    let mut s = String::with_capacity(analysis.instrs.len() * 160);
+    for instr in &analysis.instrs {
+        write_instr(&mut s, instr, &analysis, &f).unwrap();
+        s.push('\n');
+    }
+
+    Ok(s)
+}
+
+fn write_instr(
+    w: &mut dyn Write,
+    instr: &Instruction,
+    analysis: &Analysis,
+    f: &dyn Formatter,
+) -> fmt::Result {
+    write!(
+        w,
+        "{{\"addr\":{},\"word\":{},\"mnemonic\":",
+        instr.addr, instr.word
+    )?;
+    write_str(w, instr.mnemonic())?;
+    w.write_str(",\"text\":")?;
+    write_str(w, &render(|s| instr.op.print(f, s)))?;
+
+    w.write_str(",\"labels\":[")?;
+    for (i, sym) in analysis.labels_at(instr.addr).enumerate() {
+        if i != 0 {
+            w.write_str(",")?;
+        }
+        write_str(w, &render(|s| sym.print(f, s)))?;
+    }
+
+    w.write_str("],\"operands\":[")?;
+    for (i, operand) in instr.operands().into_iter().enumerate() {
+        if i != 0 {
+            w.write_str(",")?;
+        }
+        write_operand(w, operand, f)?;
+    }
+    w.write_str("]}")
+}
+
+fn write_operand(w: &mut dyn Write, operand: Operand, f: &dyn Formatter) -> fmt::Result {
+    let (kind, index, name) = match operand {
+        Operand::GpReg(r) => ("gp_reg", r as u32, render(|s| r.print(f, s))),
+        Operand::VuReg(r) => ("vu_reg", r.index() as u32, render(|s| r.print(f, s))),
+        Operand::VuCtrlReg(r) => ("vu_ctrl_reg", r as u32, render(|s| r.print(f, s))),
+        Operand::Cop0Reg(r) => ("cop0_reg", r as u32, render(|s| r.print(f, s))),
+        Operand::Element(e) => ("element", e.encode() as u32, element_name(e)),
+        Operand::ElementIndex(e) => {
+            return write!(w, "{{\"kind\":\"element_index\",\"value\":{}}}", e)
+        }
+        Operand::Imm(imm) => return write!(w, "{{\"kind\":\"imm\",\"value\":{}}}", imm),
+        Operand::Code(code) => return write!(w, "{{\"kind\":\"code\",\"value\":{}}}", code),
+        Operand::Mem { base, offset, data } => {
+            write!(w, "{{\"kind\":\"mem\",\"offset\":{},\"base\":", offset)?;
+            write_operand(w, Operand::GpReg(base), f)?;
+            if let Some(sym) = data {
+                write!(w, ",\"data\":{{\"addr\":{},\"name\":", sym.value())?;
+                write_str(w, &render(|s| sym.print(f, s)))?;
+                w.write_str("}")?;
+            }
+            return w.write_str("}");
+        }
+        Operand::Target(sym) => {
+            write!(
+                w,
+                "{{\"kind\":\"target\",\"addr\":{},\"global\":{},\"name\":",
+                sym.value(),
+                sym.is_global()
+            )?;
+            write_str(w, &render(|s| sym.print(f, s)))?;
+            return w.write_str("}");
+        }
+    };
+
+    write!(w, "{{\"kind\":\"{}\",\"index\":{},\"name\":", kind, index)?;
+    write_str(w, &name)?;
+    w.write_str("}")
+}
+
+/// dialect independent name of an element: `""`, `"1q"`, `"2h"` or `"3"`
+fn element_name(e: Element) -> String {
+    match e {
+        Element::Vector => String::new(),
+        Element::Quarter(x) => format!("{}q", x),
+        Element::Half(x) => format!("{}h", x),
+        Element::Whole(x) => format!("{}", x),
+    }
+}
+
+/// write `s` as a quoted JSON string
+pub(crate) fn write_str(w: &mut dyn Write, s: &str) -> fmt::Result {
+    w.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => w.write_str("\\\"")?,
+            '\\' => w.write_str("\\\\")?,
+            '\n' => w.write_str("\\n")?,
+            '\t' => w.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(w, "\\u{:04x}", c as u32)?,
+            c => w.write_char(c)?,
+        }
+    }
+    w.write_char('"')
+}
@@ -1,21 +1,59 @@
-mod ops;
+mod armips;
+pub mod assemble;
+pub mod cfg;
+pub mod constprop;
+mod data;
+pub mod debug;
+mod dot;
+pub mod elf;
+pub mod func;
+mod gas;
+mod instr;
+mod json;
+pub mod ops;
 mod print;
-mod regs;
+pub mod regs;
+pub mod sim;
 mod sym;
+pub mod symbols;
 mod utils;
+mod verify;
 
-use ops::RspOpcode;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashSet},
     fmt::{self, Write},
+    io,
 };
 
+use cfg::Cfg;
+use data::DataLabel;
+use func::{Function, Functions};
 use print::Print;
-pub use print::PrintOpts;
+use symbols::Named;
+use utils::render;
 
-#[derive(Debug, Clone)]
+pub use armips::{disassemble_armips, ArmipsFile};
+pub use dot::disassemble_dot;
+pub use elf::{disassemble_elf, Elf};
+pub use gas::disassemble_gas;
+pub use instr::{decode, Instruction, Operand};
+pub use json::disassemble_json;
+pub use ops::RspOpcode;
+pub use print::{Disp, Formatter, PrintOpts, Syntax};
+pub use regs::{
+    cop0::Cop0Reg,
+    su::GpReg,
+    vu::{Element, VUCtrlReg, VUReg},
+};
+pub use sym::Sym;
+pub use symbols::Symbols;
+pub use verify::{verify_bytes, Mismatch};
+
+#[derive(Debug)]
 pub enum RspDisasmError {
     UnalignedInput(usize),
+    /// the output writer failed
+    Io(io::Error),
 }
 
 impl fmt::Display for RspDisasmError {
@@ -27,56 +65,241 @@ impl fmt::Display for RspDisasmError {
                 size,
                 if *size > 1 { "s" } else { "" }
             ),
+            Self::Io(e) => write!(f, "could not write disassembly: {}", e),
         }
     }
 }
 
-impl std::error::Error for RspDisasmError {}
+impl std::error::Error for RspDisasmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
-pub fn disassemble_bytes(
+impl From<io::Error> for RspDisasmError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Decode big-endian instruction words in `data`, with the first instruction at `vaddr`
+pub fn decode_bytes(data: &[u8], vaddr: u32) -> Result<Vec<Instruction>, RspDisasmError> {
+    Ok(decode_iter(data, vaddr)?.collect())
+}
+
+/// Lazily decode `data`, one instruction per word
+fn decode_iter(
     data: &[u8],
     vaddr: u32,
-    opts: PrintOpts,
-) -> Result<String, RspDisasmError> {
-    if data.len() % 4 != 0 {
+) -> Result<impl Iterator<Item = Instruction> + '_, RspDisasmError> {
+    if !data.len().is_multiple_of(4) {
         return Err(RspDisasmError::UnalignedInput(data.len()));
     }
 
-    let n_instr = data.len() / 4;
-    let (syms, ops) = data
+    Ok(data
         .chunks_exact(4)
         .enumerate()
-        .map(|(i, bytes)| (vaddr + i as u32 * 4, bytes))
-        .map(parse_op)
-        .fold(
-            (HashMap::new(), Vec::with_capacity(n_instr)),
-            |(mut syms, mut arr), (pc, word, op)| {
-                let sym = op.get_symbol().map(|s| (s.value(), s));
-                // todo: combine syms to preserve global
-                syms.extend(sym);
-                arr.push((pc, word, op));
-                (syms, arr)
-            },
-        );
-
-    let mut s = String::with_capacity(n_instr * 32);
-    for (pc, word, op) in ops {
-        if let Some(sym) = syms.get(&pc) {
-            if sym.is_global() {
-                writeln!(&mut s).unwrap();
-            }
-            writeln!(&mut s, "{}:", sym).unwrap();
+        .map(move |(i, bytes)| (vaddr + i as u32 * 4, bytes))
+        .map(parse_op))
+}
+
+/// Microcode to disassemble
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Microcode<'a> {
+    /// instruction words
+    pub imem: &'a [u8],
+    /// address of the first instruction
+    pub vaddr: u32,
+    /// data memory, starting at DMEM address zero, used to resolve jump tables
+    /// and the addresses of loads and stores
+    pub dmem: Option<&'a [u8]>,
+    /// names that replace generated labels
+    pub symbols: Option<&'a Symbols>,
+}
+
+impl<'a> Microcode<'a> {
+    pub const fn new(imem: &'a [u8], vaddr: u32) -> Self {
+        Self {
+            imem,
+            vaddr,
+            dmem: None,
+            symbols: None,
+        }
+    }
+
+    pub const fn with_dmem(self, dmem: &'a [u8]) -> Self {
+        Self {
+            dmem: Some(dmem),
+            ..self
+        }
+    }
+
+    pub const fn with_symbols(self, symbols: &'a Symbols) -> Self {
+        Self {
+            symbols: Some(symbols),
+            ..self
         }
-        write!(&mut s, "/* {:08X} {:08X} */\t", pc, word).unwrap();
-        op.print(opts, &mut s).unwrap();
-        writeln!(&mut s).unwrap();
     }
+}
+
+pub fn disassemble_bytes(
+    data: &[u8],
+    vaddr: u32,
+    opts: PrintOpts,
+) -> Result<String, RspDisasmError> {
+    disassemble_with(&Microcode::new(data, vaddr), &opts)
+}
+
+/// Disassemble `ucode` into a listing rendered by a custom [`Formatter`]
+pub fn disassemble_with(ucode: &Microcode, f: &dyn Formatter) -> Result<String, RspDisasmError> {
+    let analysis = Analysis::new(ucode)?;
+    let f = Named::new(f, ucode.symbols);
+
+    let mut s = String::with_capacity(ucode.imem.len() * 8);
+    write_listing(&mut s, &analysis, &f).unwrap();
 
     Ok(s)
 }
 
-fn parse_op((pc, bytes): (u32, &[u8])) -> (u32, u32, RspOpcode) {
+/// Disassemble `ucode` into a listing written straight to `w`.
+///
+/// The text is never held in memory. Many small writes are made,
+/// so `w` should be buffered.
+pub fn disassemble_to(
+    w: &mut impl io::Write,
+    ucode: &Microcode,
+    f: &dyn Formatter,
+) -> Result<(), RspDisasmError> {
+    let analysis = Analysis::new(ucode)?;
+    let f = Named::new(f, ucode.symbols);
+
+    let mut out = utils::IoWriter::new(w);
+    write_listing(&mut out, &analysis, &f).map_err(|_| RspDisasmError::Io(out.into_error()))
+}
+
+/// Decoded instructions, with their targets relabeled to match their functions
+/// and their loads and stores labeled with the DMEM they access
+struct Analysis {
+    instrs: Vec<Instruction>,
+    funcs: Functions,
+    /// targets of each `jr` that could be resolved, keyed by its address
+    jumps: BTreeMap<u32, Vec<u32>>,
+    /// DMEM labels, from loads and stores and from the user's symbols
+    data: BTreeMap<u32, DataLabel>,
+    /// every label that is referenced or starts a function
+    syms: HashSet<Sym>,
+}
+
+impl Analysis {
+    fn new(ucode: &Microcode) -> Result<Self, RspDisasmError> {
+        let instrs = decode_bytes(ucode.imem, ucode.vaddr)?;
+        let (cfg, jumps) = match ucode.dmem {
+            Some(dmem) => constprop::resolve(&instrs, dmem),
+            None => (Cfg::new(&instrs), BTreeMap::new()),
+        };
+        let code = ucode.vaddr..ucode.vaddr.wrapping_add(ucode.imem.len() as u32);
+        let named = ucode.symbols.into_iter().flat_map(Symbols::code);
+        let named = named
+            .map(|s| s.addr)
+            .filter(|addr| addr % 4 == 0 && code.contains(addr))
+            .collect::<BTreeSet<_>>();
+        let funcs = Functions::analyze(&cfg, ucode.vaddr, &named);
+        let mut instrs = instrs
+            .into_iter()
+            .map(|i| funcs.relabel_instr(i))
+            .collect::<Vec<_>>();
+
+        let mut data = BTreeMap::new();
+        if let Some(dmem) = ucode.dmem {
+            let in_dmem = |addr: u32| (addr as usize) < dmem.len();
+            let refs = constprop::mem_refs(&cfg);
+            for instr in &mut instrs {
+                let r = match refs.get(&instr.addr) {
+                    Some(r) if in_dmem(r.addr) => r,
+                    _ => continue,
+                };
+                instr.op.set_data_symbol(Sym::Data(r.addr));
+                let label: &mut DataLabel = data.entry(r.addr).or_default();
+                label.item = Some(label.item.map_or(r.size, |s| s.min(r.size)));
+            }
+            let named = ucode.symbols.into_iter().flat_map(Symbols::data);
+            for sym in named.filter(|s| in_dmem(s.offset())) {
+                let label = data.entry(sym.offset()).or_default();
+                label.item = sym.ty.item_size().or(label.item);
+                label.len = sym.size;
+            }
+        }
+
+        let mut syms = instrs
+            .iter()
+            .filter_map(Instruction::target)
+            .chain(funcs.iter().map(Function::sym))
+            .chain(funcs.unreached().map(Sym::Static))
+            .collect::<HashSet<_>>();
+        // named code needs a label even if nothing branches to it
+        for addr in named {
+            let labeled = [Sym::Global(addr), Sym::Handler(addr), Sym::Static(addr)];
+            if !labeled.iter().any(|s| syms.contains(s)) {
+                syms.insert(Sym::Global(addr));
+            }
+        }
+
+        Ok(Self {
+            instrs,
+            funcs,
+            jumps,
+            data,
+            syms,
+        })
+    }
+
+    /// labels defined at `addr`
+    fn labels_at(&self, addr: u32) -> impl Iterator<Item = Sym> + '_ {
+        [Sym::Global(addr), Sym::Handler(addr), Sym::Static(addr)]
+            .into_iter()
+            .filter(|s| self.syms.contains(s))
+    }
+}
+
+fn write_listing(w: &mut dyn Write, analysis: &Analysis, f: &dyn Formatter) -> fmt::Result {
+    let mut text = String::new();
+    let mut current = None;
+    for &Instruction { addr, word, op } in &analysis.instrs {
+        let func = analysis.funcs.get(addr);
+        for sym in analysis.labels_at(addr) {
+            let name = render(|s| sym.print(f, s));
+            match func {
+                Some(func) if func.sym() == sym => {
+                    writeln!(w)?;
+                    f.func_start(&name, w)?;
+                    current = Some((func.end, name));
+                }
+                _ => write!(w, "{}:", name)?,
+            }
+            writeln!(w)?;
+        }
+
+        text.clear();
+        op.print(f, &mut text)?;
+        f.line(addr, word, &text, w)?;
+        writeln!(w)?;
+
+        if let Some((end, name)) = &current {
+            if *end == addr + 4 {
+                f.func_end(name, w)?;
+                writeln!(w)?;
+                current = None;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_op((pc, bytes): (u32, &[u8])) -> Instruction {
     let word = u32::from_be_bytes(bytes.try_into().unwrap());
-    let op = RspOpcode::decode(word, pc);
-    (pc, word, op)
+    decode(word, pc)
 }
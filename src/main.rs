@@ -1,37 +1,412 @@
 use std::{
-    io::{Read, Seek, SeekFrom},
+    fs::File,
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
     path::PathBuf,
 };
 
-use clap::Parser;
+use clap::{CommandFactory, ErrorKind, Parser, Subcommand, ValueEnum};
+use rspdisasm::{
+    debug::Debugger,
+    sim::{Rcp, Sim, RDRAM_SIZE},
+    symbols::Layout,
+    Elf, Microcode, PrintOpts, Symbols,
+};
+
+/// vram of the first instruction of a raw binary, if not given
+const DEFAULT_VRAM: u32 = 0x84000000;
 
 /// Disassemble N64 RSP microcode
 #[derive(Debug, Parser)]
+#[clap(args_conflicts_with_subcommands = true)]
 struct Args {
-    /// input ROM or binary
-    #[clap(short, long, value_parser)]
-    input: PathBuf,
-    /// output for disassembled text, or stdout if not present
+    #[clap(subcommand)]
+    command: Option<Command>,
+    #[clap(flatten)]
+    input: Input,
+    /// output for disassembled text, `--verify` or `--trace`, or stdout if not
+    /// present. The listing and traces are written as they are made; the
+    /// other formats are built in memory first
     #[clap(short, long, value_parser)]
     output: Option<PathBuf>,
-    /// offset in `input` to begin disassembly
+    /// reassemble the disassembly, in armips or bass syntax, and report words that do not match
+    /// the input
+    #[clap(long, action)]
+    verify: bool,
+    /// run the microcode from its first instruction until it breaks, writing
+    /// what each instruction changes instead of disassembling it
+    #[clap(long, value_enum)]
+    trace: Option<TraceFormat>,
+    /// most instructions to run with `--trace`
+    #[clap(long, value_parser, default_value_t = 1_000_000)]
+    max_steps: u64,
+    /// file to write the RDP commands sent during `--trace` to, as big-endian words
+    #[clap(long, value_parser)]
+    rdp_output: Option<PathBuf>,
+    /// style of the disassembled text
+    #[clap(short, long, value_enum, default_value_t = Format::Listing)]
+    format: Format,
+    /// binary that armips should build from `--format armips` output
+    #[clap(long, value_parser, default_value = "rsp.bin")]
+    armips_bin: String,
+    /// binary that armips should build from the DMEM in `--format armips` output
+    #[clap(long, value_parser, default_value = "rsp_data.bin")]
+    armips_data_bin: String,
+    /// have armips patch the microcode into a copy of `input` rather than a new file
+    #[clap(long, action)]
+    armips_patch: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Step through microcode on a simulated RSP, with breakpoints
+    Debug(Input),
+}
+
+/// The microcode to work on, its data and its names
+#[derive(Debug, clap::Args)]
+struct Input {
+    /// input ROM, binary or ELF object [required]
+    #[clap(short, long, value_parser)]
+    input: Option<PathBuf>,
+    /// offset in `input` to begin disassembly, unless it is an ELF file
     #[clap(short = 'p', long, value_parser, default_value_t = 0)]
     offset: u64,
-    /// number of bytes to disassemble
+    /// number of bytes to disassemble, required unless `input` is an ELF file
     #[clap(short = 'n', long, value_parser)]
-    size: usize,
-    /// vram of first instruction (not really important)
-    #[clap(short, long, value_parser, default_value_t = 0x84000000)]
+    size: Option<usize>,
+    /// vram of first instruction (not really important) [default: 0x84000000,
+    /// or the address of an ELF file's `.text`]
+    #[clap(short, long, value_parser)]
+    vram: Option<u32>,
+    /// ROM or binary holding the microcode's DMEM, used to resolve jump tables
+    /// and written as data by `--format armips` and `gas` [default: an ELF
+    /// file's `.data`]
+    #[clap(long, value_parser)]
+    dmem: Option<PathBuf>,
+    /// offset in `dmem` of DMEM address zero
+    #[clap(long, value_parser, default_value_t = 0)]
+    dmem_offset: u64,
+    /// number of bytes of DMEM to read
+    #[clap(long, value_parser, default_value_t = 0x1000)]
+    dmem_size: u64,
+    /// file of `name = address [size] [type]` lines naming code and data
+    #[clap(long, value_parser)]
+    symbols: Vec<PathBuf>,
+    /// GNU ld map file to import RSP symbols from
+    #[clap(long, value_parser)]
+    ld_map: Vec<PathBuf>,
+    /// splat `symbol_addrs.txt` to import RSP symbols from
+    #[clap(long, value_parser)]
+    splat_symbols: Vec<PathBuf>,
+    /// RDRAM image that `--trace` and `debug` run against, from address zero
+    #[clap(long, value_parser)]
+    rdram: Option<PathBuf>,
+    /// assembler syntax of `--format listing`, `json` and `dot`, and of `debug`
+    #[clap(short, long, value_enum, default_value_t = Syntax::Armips)]
+    syntax: Syntax,
+}
+
+/// Microcode read from the files named by an [`Input`]
+struct Loaded {
+    path: PathBuf,
+    imem: Vec<u8>,
     vram: u32,
+    dmem: Option<Vec<u8>>,
+    symbols: Symbols,
+}
+
+impl Loaded {
+    fn microcode(&self) -> Microcode<'_> {
+        let mut ucode = Microcode::new(&self.imem, self.vram);
+        if let Some(dmem) = &self.dmem {
+            ucode = ucode.with_dmem(dmem);
+        }
+        if !self.symbols.is_empty() {
+            ucode = ucode.with_symbols(&self.symbols);
+        }
+        ucode
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// instructions with address and word comments
+    Listing,
+    /// a complete armips source file
+    Armips,
+    /// a complete GNU as source file for libdragon's rsp.inc
+    Gas,
+    /// JSON Lines with one object per instruction
+    Json,
+    /// a Graphviz DOT control flow graph per subroutine
+    Dot,
+    /// a relocatable MIPS ELF object with the microcode as `.text` and `.data`
+    Elf,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum TraceFormat {
+    /// the instruction, then one indented line per change
+    Text,
+    /// JSON Lines with one object per instruction
+    Json,
+}
+
+/// kinds of symbol files, in the order they are applied
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum SymbolFormat {
+    LdMap,
+    Splat,
+    List,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Syntax {
+    Armips,
+    Gnu,
+    Bass,
+}
+
+impl From<Syntax> for rspdisasm::Syntax {
+    fn from(s: Syntax) -> Self {
+        match s {
+            Syntax::Armips => Self::Armips,
+            Syntax::Gnu => Self::Gnu,
+            Syntax::Bass => Self::Bass,
+        }
+    }
 }
+
 fn main() {
     let args = Args::parse();
-    let opts = rspdisasm::PrintOpts::default();
-    let mut f = std::fs::File::open(args.input).unwrap();
-    f.seek(SeekFrom::Start(args.offset)).unwrap();
-    let mut data = vec![0u8; args.size];
-    f.read_exact(&mut data).unwrap();
-
-    let result = rspdisasm::disassemble_bytes(&data, args.vram, opts).unwrap();
-    println!("{result}");
+    match &args.command {
+        Some(Command::Debug(input)) => debug(input),
+        None => disassemble(&args),
+    }
+}
+
+fn disassemble(args: &Args) {
+    let opts = print_opts(&args.input);
+    let loaded = load(&args.input);
+    let ucode = loaded.microcode();
+
+    if args.verify {
+        let mismatches = rspdisasm::verify_bytes(ucode.imem, ucode.vaddr, opts).unwrap();
+        let mut out = output(&args.output);
+        for m in &mismatches {
+            writeln!(out, "{m}").unwrap();
+        }
+        out.flush().unwrap();
+        eprintln!(
+            "{} of {} words did not reassemble to the original bytes",
+            mismatches.len(),
+            ucode.imem.len() / 4
+        );
+        if !mismatches.is_empty() {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(format) = args.trace {
+        let mut sim = Sim::load(&ucode, rcp(&args.input));
+        let mut out = output(&args.output);
+        let mut text = String::new();
+        let halt = sim.run_traced(args.max_steps, |step| {
+            text.clear();
+            match format {
+                TraceFormat::Text => step.write_text(&mut text, &opts).unwrap(),
+                TraceFormat::Json => {
+                    step.write_json(&mut text, &opts).unwrap();
+                    text.push('\n');
+                }
+            }
+            out.write_all(text.as_bytes()).unwrap();
+        });
+        out.flush().unwrap();
+        eprintln!("halted after {} steps: {:?}", sim.steps(), halt);
+
+        if let Some(path) = &args.rdp_output {
+            let bytes = sim.cop0.rdp.iter().flat_map(|w| w.to_be_bytes());
+            std::fs::write(path, bytes.collect::<Vec<_>>()).unwrap();
+        }
+        return;
+    }
+
+    let mut out = output(&args.output);
+
+    let result = match args.format {
+        Format::Listing => rspdisasm::disassemble_to(&mut out, &ucode, &opts),
+        Format::Armips => {
+            let file = rspdisasm::ArmipsFile {
+                output: args.armips_bin.clone(),
+                input: args
+                    .armips_patch
+                    .then(|| loaded.path.to_string_lossy().into_owned()),
+                file_offset: if args.armips_patch {
+                    args.input.offset as u32
+                } else {
+                    0
+                },
+                data_output: args.armips_data_bin.clone(),
+            };
+            rspdisasm::disassemble_armips(&ucode, opts, &file)
+                .and_then(|s| Ok(out.write_all(s.as_bytes())?))
+        }
+        Format::Gas => {
+            rspdisasm::disassemble_gas(&ucode, opts).and_then(|s| Ok(out.write_all(s.as_bytes())?))
+        }
+        Format::Json => {
+            rspdisasm::disassemble_json(&ucode, opts).and_then(|s| Ok(out.write_all(s.as_bytes())?))
+        }
+        Format::Dot => {
+            rspdisasm::disassemble_dot(&ucode, opts).and_then(|s| Ok(out.write_all(s.as_bytes())?))
+        }
+        Format::Elf => rspdisasm::disassemble_elf(&ucode).and_then(|elf| Ok(out.write_all(&elf)?)),
+    };
+    result.unwrap();
+    out.flush().unwrap();
+}
+
+/// Read commands from stdin and run them in a debugger until the user quits
+fn debug(input: &Input) {
+    let loaded = load(input);
+    let mut debugger = Debugger::new(loaded.microcode(), print_opts(input), rcp(input))
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {}", loaded.path.display(), e);
+            std::process::exit(1);
+        });
+
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    let mut line = String::from("list");
+    let mut text = String::new();
+    loop {
+        text.clear();
+        let running = debugger.exec(&line, &mut text).unwrap();
+        stdout.write_all(text.as_bytes()).unwrap();
+        if !running {
+            break;
+        }
+
+        write!(stdout, "(rsp) ").unwrap();
+        stdout.flush().unwrap();
+        line.clear();
+        if stdin.read_line(&mut line).unwrap() == 0 {
+            writeln!(stdout).unwrap();
+            break;
+        }
+    }
+}
+
+fn print_opts(input: &Input) -> PrintOpts {
+    PrintOpts {
+        syntax: input.syntax.into(),
+        ..Default::default()
+    }
+}
+
+/// Read the microcode, its DMEM and its symbols named by `args`, exiting if
+/// they cannot be read
+fn load(args: &Input) -> Loaded {
+    let path = args.input.clone().unwrap_or_else(|| {
+        Args::command()
+            .error(ErrorKind::MissingRequiredArgument, "--input is required")
+            .exit()
+    });
+    let input = std::fs::read(&path).unwrap();
+    let elf = Elf::is_elf(&input).then(|| {
+        Elf::parse(&input).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path.display(), e);
+            std::process::exit(1);
+        })
+    });
+    let (data, vram) = match &elf {
+        Some(elf) => {
+            let text = elf.text().unwrap_or_else(|| {
+                eprintln!("{}: no .text section", path.display());
+                std::process::exit(1);
+            });
+            (text.data, args.vram.unwrap_or(text.addr))
+        }
+        None => {
+            let size = args.size.unwrap_or_else(|| {
+                Args::command()
+                    .error(
+                        ErrorKind::MissingRequiredArgument,
+                        "--size is required unless the input is an ELF file",
+                    )
+                    .exit()
+            });
+            let start = args.offset as usize;
+            let data = input.get(start..start + size).unwrap_or_else(|| {
+                eprintln!("{}: file is too short", path.display());
+                std::process::exit(1);
+            });
+            (data, args.vram.unwrap_or(DEFAULT_VRAM))
+        }
+    };
+
+    let dmem = match &args.dmem {
+        Some(path) => {
+            let mut f = File::open(path).unwrap();
+            f.seek(SeekFrom::Start(args.dmem_offset)).unwrap();
+            let mut dmem = Vec::new();
+            f.take(args.dmem_size).read_to_end(&mut dmem).unwrap();
+            Some(dmem)
+        }
+        None => elf.as_ref().and_then(Elf::dmem),
+    };
+    let layout = Layout {
+        vram,
+        size: data.len() as u32,
+    };
+    let mut symbols = elf.as_ref().map_or_else(Symbols::new, Elf::to_symbols);
+    let files = args
+        .ld_map
+        .iter()
+        .map(|p| (p, SymbolFormat::LdMap))
+        .chain(args.splat_symbols.iter().map(|p| (p, SymbolFormat::Splat)))
+        .chain(args.symbols.iter().map(|p| (p, SymbolFormat::List)));
+    for (path, format) in files {
+        let src = std::fs::read_to_string(path).unwrap();
+        let parsed = match format {
+            SymbolFormat::LdMap => Ok(Symbols::parse_ld_map(&src, layout)),
+            SymbolFormat::Splat => Symbols::parse_splat(&src, layout),
+            SymbolFormat::List => Symbols::parse(&src),
+        }
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {}", path.display(), e);
+            std::process::exit(1);
+        });
+        for sym in parsed.iter() {
+            symbols.insert(sym.clone());
+        }
+    }
+
+    Loaded {
+        imem: data.to_vec(),
+        vram,
+        dmem,
+        symbols,
+        path,
+    }
+}
+
+/// The RCP that simulated microcode talks to, with the RDRAM image named by `args`
+fn rcp(args: &Input) -> Rcp {
+    let mut rdram = match &args.rdram {
+        Some(path) => std::fs::read(path).unwrap(),
+        None => Vec::new(),
+    };
+    rdram.resize(rdram.len().max(RDRAM_SIZE), 0);
+    Rcp::new(rdram)
+}
+
+/// buffered writer to `path`, or to stdout if there is no path
+fn output(path: &Option<PathBuf>) -> Box<dyn Write> {
+    match path {
+        Some(path) => Box::new(BufWriter::new(File::create(path).unwrap())),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    }
 }
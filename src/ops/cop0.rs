@@ -1,16 +1,21 @@
-use crate::print::Print;
+use crate::instr::Operand;
+use crate::print::{Formatter, Print};
 use crate::regs::{cop0::Cop0Reg, su::GpReg};
 use crate::utils;
 use std::fmt::{self, Write};
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) enum Cop0Op {
+pub enum Cop0Op {
     MFC0(GpReg, Cop0Reg),
     MTC0(GpReg, Cop0Reg),
 }
 
 impl Cop0Op {
     pub(crate) fn decode(op: u32) -> Option<Self> {
+        if op & 0x7FF != 0 {
+            return None;
+        }
         let direction = utils::u8_at(21, 5, op);
         let rt = GpReg::at_bit(16, op).ok()?;
         let rd = Cop0Reg::at_bit(11, op).ok()?;
@@ -20,23 +25,35 @@ impl Cop0Op {
             _ => None,
         }
     }
+
+    pub(crate) fn encode(&self) -> u32 {
+        let (direction, rt, rd) = match self {
+            Self::MFC0(rt, rd) => (0x00, rt, rd),
+            Self::MTC0(rt, rd) => (0x04, rt, rd),
+        };
+
+        (direction << 21) | ((*rt as u32) << 16) | ((*rd as u32) << 11)
+    }
+
+    pub(crate) const fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::MFC0(..) => "mfc0",
+            Self::MTC0(..) => "mtc0",
+        }
+    }
+
+    pub(crate) fn operands(&self, out: &mut Vec<Operand>) {
+        let (Self::MFC0(rt, rd) | Self::MTC0(rt, rd)) = self;
+        out.extend([Operand::GpReg(*rt), Operand::Cop0Reg(*rd)]);
+    }
 }
 
 impl Print for Cop0Op {
-    fn print(&self, opts: crate::PrintOpts, w: &mut impl Write) -> fmt::Result {
-        let (rt, rd) = match self {
-            Self::MFC0(rt, rd) => {
-                write!(w, "mfc0 ")?;
-                (rt, rd)
-            }
-            Self::MTC0(rt, rd) => {
-                write!(w, "mtc0 ")?;
-                (rt, rd)
-            }
-        };
+    fn print(&self, f: &dyn Formatter, w: &mut dyn Write) -> fmt::Result {
+        let (Self::MFC0(rt, rd) | Self::MTC0(rt, rd)) = self;
 
-        rt.print(opts, w)?;
-        write!(w, ", ")?;
-        rd.print(opts, w)
+        rt.print(f, w)?;
+        f.separator(w)?;
+        rd.print(f, w)
     }
 }
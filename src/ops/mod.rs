@@ -1,24 +1,25 @@
 use num_enum::{TryFromPrimitive, TryFromPrimitiveError};
 
-pub(crate) mod cop0;
-pub(crate) mod regimm;
-pub(crate) mod special;
-pub(crate) mod vu;
+pub mod cop0;
+pub mod regimm;
+pub mod special;
+pub mod vu;
 
 use std::fmt::{self, Write};
 
 use self::{cop0::Cop0Op, regimm::RegImm, special::Special, vu::VUOp};
 use crate::{
-    print::Print,
+    instr::Operand,
+    print::{Disp, Formatter, Print},
     regs::{su::GpReg, vu::VUReg},
     sym::Sym,
     utils::*,
-    PrintOpts,
 };
 
 // todo: refactor into enum struct
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub(crate) enum RspOpcode {
+pub enum RspOpcode {
     Nop,
     Special(Special),    // 0x00
     RegImm(RegImm),      // 0x01
@@ -103,7 +104,51 @@ impl RspOpcode {
             _ => Some(Self::Unsupported(op)),
         };
 
-        return decoded.unwrap_or_else(|| Self::Unsupported(op));
+        decoded.unwrap_or(Self::Unsupported(op))
+    }
+
+    /// Encode this op as if it were located at `vaddr`.
+    ///
+    /// Branch offsets are recomputed from their target symbols, so an op can be
+    /// moved to a new address. For every `word`,
+    /// `RspOpcode::decode(word, vaddr).encode(vaddr) == word`: words with
+    /// fields that the decoder would drop are kept as `Unsupported`.
+    pub fn encode(&self, vaddr: u32) -> u32 {
+        let (opcode, fields) = match self {
+            Self::Nop => return 0,
+            Self::Special(sub) => (0x00, sub.encode()),
+            Self::RegImm(sub) => (0x01, sub.encode(vaddr)),
+            Self::J(s) => (0x02, s.jmp_field()),
+            Self::JAL(s) => (0x03, s.jmp_field()),
+            Self::BEQ(d) => (0x04, d.encode(vaddr)),
+            Self::BNE(d) => (0x05, d.encode(vaddr)),
+            Self::BLEZ(d) => (0x06, d.encode(vaddr)),
+            Self::BGTZ(d) => (0x07, d.encode(vaddr)),
+            Self::ADDI(d) => (0x08, d.encode()),
+            Self::ADDIU(d) => (0x09, d.encode()),
+            Self::SLTI(d) => (0x0A, d.encode()),
+            Self::SLTIU(d) => (0x0B, d.encode()),
+            Self::ANDI(d) => (0x0C, d.encode()),
+            Self::ORI(d) => (0x0D, d.encode()),
+            Self::XORI(d) => (0x0E, d.encode()),
+            Self::LUI(d) => (0x0F, d.encode()),
+            Self::COP0(sub) => (0x10, sub.encode()),
+            Self::COP2(sub) => (0x12, sub.encode()),
+            Self::LB(d) => (0x20, d.encode()),
+            Self::LH(d) => (0x21, d.encode()),
+            Self::LW(d) => (0x23, d.encode()),
+            Self::LBU(d) => (0x24, d.encode()),
+            Self::LHU(d) => (0x25, d.encode()),
+            Self::LWU(d) => (0x27, d.encode()),
+            Self::SB(d) => (0x28, d.encode()),
+            Self::SH(d) => (0x29, d.encode()),
+            Self::SW(d) => (0x2B, d.encode()),
+            Self::LWC2(cmd) => (0x32, cmd.encode()),
+            Self::SWC2(cmd) => (0x3A, cmd.encode()),
+            Self::Unsupported(word) => return *word,
+        };
+
+        (opcode << 26) | fields
     }
 
     pub fn get_symbol(&self) -> Option<Sym> {
@@ -115,150 +160,181 @@ impl RspOpcode {
             _ => None,
         }
     }
+
+    /// Point the branch or jump of this op at `sym`, which must be at the same address
+    pub(crate) fn set_target(&mut self, sym: Sym) {
+        match self {
+            Self::J(s) | Self::JAL(s) => *s = sym,
+            Self::BEQ(d) | Self::BNE(d) => d.target = sym,
+            Self::BLEZ(d) | Self::BGTZ(d) => d.target = sym,
+            Self::RegImm(
+                RegImm::BLTZ(r) | RegImm::BGEZ(r) | RegImm::BLTZAL(r) | RegImm::BGEZAL(r),
+            ) => r.sym = sym,
+            _ => (),
+        }
+    }
+
+    /// The DMEM label of the address this load or store accesses, if known
+    pub fn get_data_symbol(&self) -> Option<Sym> {
+        match self {
+            Self::LB(d)
+            | Self::LH(d)
+            | Self::LW(d)
+            | Self::LBU(d)
+            | Self::LHU(d)
+            | Self::LWU(d) => d.data,
+            Self::SB(d) | Self::SH(d) | Self::SW(d) => d.data,
+            Self::LWC2(d) | Self::SWC2(d) => d.data,
+            _ => None,
+        }
+    }
+
+    /// Label the address accessed by this load or store with `sym`
+    pub(crate) fn set_data_symbol(&mut self, sym: Sym) {
+        match self {
+            Self::LB(d)
+            | Self::LH(d)
+            | Self::LW(d)
+            | Self::LBU(d)
+            | Self::LHU(d)
+            | Self::LWU(d) => d.data = Some(sym),
+            Self::SB(d) | Self::SH(d) | Self::SW(d) => d.data = Some(sym),
+            Self::LWC2(d) | Self::SWC2(d) => d.data = Some(sym),
+            _ => (),
+        }
+    }
+
+    /// The assembler mnemonic of this op, without operands.
+    /// Unsupported words are reported as `.word`
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::Nop => "nop",
+            Self::Special(sub) => sub.mnemonic(),
+            Self::RegImm(sub) => sub.mnemonic(),
+            Self::J(_) => "j",
+            Self::JAL(_) => "jal",
+            Self::BEQ(_) => "beq",
+            Self::BNE(_) => "bne",
+            Self::BLEZ(_) => "blez",
+            Self::BGTZ(_) => "bgtz",
+            Self::ADDI(_) => "addi",
+            Self::ADDIU(_) => "addiu",
+            Self::SLTI(_) => "slti",
+            Self::SLTIU(_) => "sltiu",
+            Self::ANDI(_) => "andi",
+            Self::ORI(_) => "ori",
+            Self::XORI(_) => "xori",
+            Self::LUI(_) => "lui",
+            Self::COP0(sub) => sub.mnemonic(),
+            Self::COP2(sub) => sub.mnemonic(),
+            Self::LB(_) => "lb",
+            Self::LH(_) => "lh",
+            Self::LW(_) => "lw",
+            Self::LBU(_) => "lbu",
+            Self::LHU(_) => "lhu",
+            Self::LWU(_) => "lwu",
+            Self::SB(_) => "sb",
+            Self::SH(_) => "sh",
+            Self::SW(_) => "sw",
+            Self::LWC2(cmd) => cmd.opcode.load_mnemonic(),
+            Self::SWC2(cmd) => cmd.opcode.store_mnemonic(),
+            Self::Unsupported(_) => ".word",
+        }
+    }
+
+    /// The operands of this op, in the order they are printed
+    pub fn operands(&self) -> Vec<Operand> {
+        let mut out = Vec::with_capacity(4);
+        match self {
+            Self::Nop => (),
+            Self::Special(sub) => sub.operands(&mut out),
+            Self::RegImm(sub) => sub.get_regs().operands(&mut out),
+            Self::J(s) | Self::JAL(s) => out.push(Operand::Target(*s)),
+            Self::BEQ(d) | Self::BNE(d) => d.operands(&mut out),
+            Self::BLEZ(d) | Self::BGTZ(d) => d.operands(&mut out),
+            Self::ADDI(d)
+            | Self::ADDIU(d)
+            | Self::SLTI(d)
+            | Self::SLTIU(d)
+            | Self::ANDI(d)
+            | Self::ORI(d)
+            | Self::XORI(d) => d.operands(&mut out),
+            Self::LUI(d) => d.operands(&mut out),
+            Self::COP0(sub) => sub.operands(&mut out),
+            Self::COP2(sub) => sub.operands(&mut out),
+            Self::LB(d)
+            | Self::LH(d)
+            | Self::LW(d)
+            | Self::LBU(d)
+            | Self::LHU(d)
+            | Self::LWU(d)
+            | Self::SB(d)
+            | Self::SH(d)
+            | Self::SW(d) => d.operands(&mut out),
+            Self::LWC2(cmd) | Self::SWC2(cmd) => cmd.operands(&mut out),
+            Self::Unsupported(word) => out.push(Operand::Code(*word)),
+        }
+        out
+    }
 }
 
 impl Print for RspOpcode {
-    fn print(&self, opts: PrintOpts, w: &mut impl fmt::Write) -> fmt::Result {
+    fn print(&self, f: &dyn Formatter, w: &mut dyn Write) -> fmt::Result {
+        if let Self::Unsupported(b) = self {
+            f.word(*b, w)?;
+            w.write_str(" ")?;
+            return f.comment("unrecognized op", w);
+        }
+
+        f.mnemonic(self.mnemonic(), w)?;
         match self {
-            Self::Nop => write!(w, "nop"),
-            Self::Special(sub) => sub.print(opts, w),
-            Self::RegImm(sub) => sub.print(opts, w),
-            Self::J(s) => {
-                // `j {symbol}`
-                write!(w, "j ")?;
-                s.print(opts, w)
-            }
-            Self::JAL(s) => {
-                // `jal {symbol}`
-                write!(w, "jal ")?;
-                s.print(opts, w)
-            }
-            Self::BEQ(regs) => {
-                // `beq {rs, rt, local}
-                write!(w, "beq ")?;
-                regs.print(opts, w)
-            }
-            Self::BNE(regs) => {
-                // `bne {rs, rt, local}`
-                write!(w, "bne ")?;
-                regs.print(opts, w)
-            }
-            Self::BLEZ(d) => {
-                // 'blez {rs, offset}
-                write!(w, "blez ")?;
-                d.print(opts, w)
-            }
-            Self::BGTZ(d) => {
-                // 'bgtz {rs, offset}
-                write!(w, "bgtz ")?;
-                d.print(opts, w)
-            }
-            Self::ADDI(d) => {
-                // addi {rt, rs, imm}
-                write!(w, "addi ")?;
-                d.print(opts, w)
-            }
-            Self::ADDIU(d) => {
-                // addiu {rt, rs, imm}
-                write!(w, "addiu ")?;
-                d.print(opts, w)
-            }
-            Self::SLTI(d) => {
-                // slti {rt, rs, imm}
-                write!(w, "slti ")?;
-                d.print(opts, w)
-            }
-            Self::SLTIU(d) => {
-                // sltiu {rt, rs, imm}
-                write!(w, "sltiu ")?;
-                d.print(opts, w)
-            }
-            Self::ANDI(d) => {
-                // andi {rt, rs, imm}
-                write!(w, "andi ")?;
-                d.print(opts, w)
-            }
-            Self::ORI(d) => {
-                // ori {rt, rs, imm}
-                write!(w, "ori ")?;
-                d.print(opts, w)
-            }
-            Self::XORI(d) => {
-                // xori {rt, rs, imm}
-                write!(w, "xori ")?;
-                d.print(opts, w)
-            }
-            Self::LUI(d) => {
-                // lui {rt, imm}
-                write!(w, "xori ")?;
-                d.print(opts, w)
-            }
-            Self::COP0(sub) => sub.print(opts, w),
-            Self::COP2(sub) => sub.print(opts, w),
-            Self::LB(d) => {
-                // lb {rt, offset(base)
-                write!(w, "lb ")?;
-                d.print(opts, w)
-            }
-            Self::LH(d) => {
-                // lh {rt, offset(base)
-                write!(w, "ld ")?;
-                d.print(opts, w)
-            }
-            Self::LW(d) => {
-                // lw {rt, offset(base)
-                write!(w, "lw ")?;
-                d.print(opts, w)
-            }
-            Self::LBU(d) => {
-                // lbu {rt, offset(base)
-                write!(w, "lbu ")?;
-                d.print(opts, w)
-            }
-            Self::LHU(d) => {
-                // lhu {rt, offset(base)
-                write!(w, "lhu ")?;
-                d.print(opts, w)
-            }
-            Self::LWU(d) => {
-                // lwu {rt, offset(base)
-                write!(w, "lwu ")?;
-                d.print(opts, w)
-            }
-            Self::SB(d) => {
-                // sb {rt, offset(base)
-                write!(w, "sb ")?;
-                d.print(opts, w)
-            }
-            Self::SH(d) => {
-                // sh {rt, offset(base)
-                write!(w, "sh ")?;
-                d.print(opts, w)
-            }
-            Self::SW(d) => {
-                // sw {rt, offset(base)
-                write!(w, "sw ")?;
-                d.print(opts, w)
-            }
-            Self::LWC2(cmd) => {
-                write!(w, "l")?;
-                cmd.print(opts, w)
-            }
-            Self::SWC2(cmd) => {
-                write!(w, "s")?;
-                cmd.print(opts, w)
-            }
-            Self::Unsupported(b) => write!(w, "; unrecognized op [{:08X}]", b),
+            Self::Nop | Self::COP2(VUOp::Nop) => return Ok(()),
+            _ => w.write_str(" ")?,
+        }
+
+        match self {
+            Self::Special(sub) => sub.print(f, w),
+            Self::RegImm(sub) => sub.get_regs().print(f, w),
+            // `j {symbol}`
+            Self::J(s) | Self::JAL(s) => s.print(f, w),
+            // `beq {rs, rt, local}`
+            Self::BEQ(d) | Self::BNE(d) => d.print(f, w),
+            // `blez {rs, local}`
+            Self::BLEZ(d) | Self::BGTZ(d) => d.print(f, w),
+            // `addi {rt, rs, imm}`
+            Self::ADDI(d)
+            | Self::ADDIU(d)
+            | Self::SLTI(d)
+            | Self::SLTIU(d)
+            | Self::ANDI(d)
+            | Self::ORI(d)
+            | Self::XORI(d) => d.print(f, w),
+            // `lui {rt, imm}`
+            Self::LUI(d) => d.print(f, w),
+            Self::COP0(sub) => sub.print(f, w),
+            Self::COP2(sub) => sub.print(f, w),
+            // `lw {rt, offset(base)}`
+            Self::LB(d)
+            | Self::LH(d)
+            | Self::LW(d)
+            | Self::LBU(d)
+            | Self::LHU(d)
+            | Self::LWU(d)
+            | Self::SB(d)
+            | Self::SH(d)
+            | Self::SW(d) => d.print(f, w),
+            Self::LWC2(cmd) | Self::SWC2(cmd) => cmd.print(f, w),
+            Self::Nop | Self::Unsupported(_) => Ok(()),
         }
     }
 }
 
 // todo: error propagation with error sum type
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub(crate) struct BrTwoReg {
-    rs: GpReg,
-    rt: GpReg,
-    target: Sym,
+pub struct BrTwoReg {
+    pub(crate) rs: GpReg,
+    pub(crate) rt: GpReg,
+    pub(crate) target: Sym,
 }
 
 impl BrTwoReg {
@@ -269,45 +345,69 @@ impl BrTwoReg {
 
         Some(Self { rs, rt, target })
     }
+
+    fn encode(&self, vaddr: u32) -> u32 {
+        ((self.rs as u32) << 21) | ((self.rt as u32) << 16) | self.target.branch_field(vaddr)
+    }
+
+    fn operands(&self, out: &mut Vec<Operand>) {
+        out.extend([
+            Operand::GpReg(self.rs),
+            Operand::GpReg(self.rt),
+            Operand::Target(self.target),
+        ]);
+    }
 }
 
 impl Print for BrTwoReg {
-    fn print(&self, opts: PrintOpts, w: &mut impl Write) -> fmt::Result {
-        self.rs.print(opts, w)?;
-        write!(w, ", ")?;
-        self.rt.print(opts, w)?;
-        write!(w, ", ")?;
-        self.target.print(opts, w)
+    fn print(&self, f: &dyn Formatter, w: &mut dyn Write) -> fmt::Result {
+        self.rs.print(f, w)?;
+        f.separator(w)?;
+        self.rt.print(f, w)?;
+        f.separator(w)?;
+        self.target.print(f, w)
     }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub(crate) struct BrOneReg {
-    rs: GpReg,
-    target: Sym,
+pub struct BrOneReg {
+    pub(crate) rs: GpReg,
+    pub(crate) target: Sym,
 }
 
 impl BrOneReg {
     fn from_op(op: u32, vaddr: u32) -> Option<Self> {
+        // rt is unused
+        if u8_at(16, 5, op) != 0 {
+            return None;
+        }
         let rs = GpReg::at_bit(21, op).ok()?;
         let target = Sym::from_branch(op, vaddr);
 
         Some(Self { rs, target })
     }
+
+    fn encode(&self, vaddr: u32) -> u32 {
+        ((self.rs as u32) << 21) | self.target.branch_field(vaddr)
+    }
+
+    fn operands(&self, out: &mut Vec<Operand>) {
+        out.extend([Operand::GpReg(self.rs), Operand::Target(self.target)]);
+    }
 }
 
 impl Print for BrOneReg {
-    fn print(&self, opts: PrintOpts, w: &mut impl Write) -> fmt::Result {
-        self.rs.print(opts, w)?;
-        write!(w, ", ")?;
-        self.target.print(opts, w)
+    fn print(&self, f: &dyn Formatter, w: &mut dyn Write) -> fmt::Result {
+        self.rs.print(f, w)?;
+        f.separator(w)?;
+        self.target.print(f, w)
     }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub(crate) struct TwoRegImm {
-    rs: GpReg,
-    rt: GpReg,
+pub struct TwoRegImm {
+    pub(crate) rs: GpReg,
+    pub(crate) rt: GpReg,
     imm: i16,
     as_hex: bool,
 }
@@ -325,48 +425,86 @@ impl TwoRegImm {
             as_hex: false,
         })
     }
+
+    fn encode(&self) -> u32 {
+        ((self.rs as u32) << 21) | ((self.rt as u32) << 16) | (self.imm as u16 as u32)
+    }
+
+    /// the immediate as the op uses it
+    pub(crate) const fn value(&self) -> u32 {
+        // the logical ops (printed as hex) zero-extend their immediate
+        if self.as_hex {
+            self.imm as u16 as u32
+        } else {
+            self.imm as u32
+        }
+    }
+
+    fn operands(&self, out: &mut Vec<Operand>) {
+        out.extend([
+            Operand::GpReg(self.rt),
+            Operand::GpReg(self.rs),
+            Operand::Imm(self.value() as i32),
+        ]);
+    }
 }
 
 impl Print for TwoRegImm {
-    fn print(&self, opts: PrintOpts, w: &mut impl Write) -> fmt::Result {
-        self.rt.print(opts, w)?;
-        write!(w, ", ")?;
-        self.rs.print(opts, w)?;
+    fn print(&self, f: &dyn Formatter, w: &mut dyn Write) -> fmt::Result {
+        self.rt.print(f, w)?;
+        f.separator(w)?;
+        self.rs.print(f, w)?;
+        f.separator(w)?;
         if self.as_hex {
-            write!(w, ", {:#06X}", self.imm)
+            f.hex_imm(self.imm as u16, w)
         } else {
-            write!(w, ", {}", self.imm)
+            f.imm(self.imm as i32, w)
         }
     }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub(crate) struct OneRegImm {
-    rt: GpReg,
-    imm: u16,
+pub struct OneRegImm {
+    pub(crate) rt: GpReg,
+    pub(crate) imm: u16,
 }
 
 impl OneRegImm {
     fn from_op(op: u32) -> Option<Self> {
+        // rs is unused
+        if u8_at(21, 5, op) != 0 {
+            return None;
+        }
         let rt = GpReg::at_bit(16, op).ok()?;
         let imm = op as u16;
 
         Some(Self { rt, imm })
     }
+
+    fn encode(&self) -> u32 {
+        ((self.rt as u32) << 16) | self.imm as u32
+    }
+
+    fn operands(&self, out: &mut Vec<Operand>) {
+        out.extend([Operand::GpReg(self.rt), Operand::Imm(self.imm as i32)]);
+    }
 }
 
 impl Print for OneRegImm {
-    fn print(&self, opts: PrintOpts, w: &mut impl Write) -> fmt::Result {
-        self.rt.print(opts, w)?;
-        write!(w, ", {:#x}", self.imm)
+    fn print(&self, f: &dyn Formatter, w: &mut dyn Write) -> fmt::Result {
+        self.rt.print(f, w)?;
+        f.separator(w)?;
+        f.hex_imm(self.imm, w)
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) struct MipsLoadStore {
-    dst: GpReg,
-    base: GpReg,
-    offset: i16,
+pub struct MipsLoadStore {
+    pub(crate) dst: GpReg,
+    pub(crate) base: GpReg,
+    pub(crate) offset: i16,
+    /// DMEM label of the accessed address, if it is known
+    pub(crate) data: Option<Sym>,
 }
 
 impl MipsLoadStore {
@@ -375,29 +513,57 @@ impl MipsLoadStore {
         let base = GpReg::at_bit(21, op).ok()?;
         let offset = (op & 0xFFFF) as i16;
 
-        Some(Self { dst, base, offset })
+        Some(Self {
+            dst,
+            base,
+            offset,
+            data: None,
+        })
+    }
+
+    fn encode(&self) -> u32 {
+        ((self.base as u32) << 21) | ((self.dst as u32) << 16) | (self.offset as u16 as u32)
+    }
+
+    fn operands(&self, out: &mut Vec<Operand>) {
+        out.extend([
+            Operand::GpReg(self.dst),
+            Operand::Mem {
+                base: self.base,
+                offset: self.offset,
+                data: self.data,
+            },
+        ]);
     }
 }
 
 impl Print for MipsLoadStore {
-    fn print(&self, opts: PrintOpts, w: &mut impl Write) -> fmt::Result {
-        self.dst.print(opts, w)?;
-        write!(w, ", {:#x}(", self.offset)?;
-        self.base.print(opts, w)?;
-        write!(w, ")")
+    fn print(&self, f: &dyn Formatter, w: &mut dyn Write) -> fmt::Result {
+        self.dst.print(f, w)?;
+        f.separator(w)?;
+        let name = self.data.map(|sym| render(|s| sym.print(f, s)));
+        f.offset(disp(self.offset, self.data, name.as_deref()), self.base, w)
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) struct Cop2LoadStore {
-    opcode: RspAddressMode,
-    vt: VUReg,
-    element: u8,
-    base: GpReg,
-    offset: i16,
+pub struct Cop2LoadStore {
+    pub(crate) opcode: RspAddressMode,
+    pub(crate) vt: VUReg,
+    pub(crate) element: u8,
+    pub(crate) base: GpReg,
+    /// already scaled by the item size
+    pub(crate) offset: i16,
+    /// DMEM label of the accessed address, if it is known
+    pub(crate) data: Option<Sym>,
 }
 
 impl Cop2LoadStore {
+    /// size of the item loaded or stored, which scales the offset
+    pub(crate) fn item_size(&self) -> u32 {
+        self.opcode.item_size() as u32
+    }
+
     fn decode(op: u32) -> Option<Self> {
         let opcode = RspAddressMode::at_bit(11, op).ok()?;
         let vt = VUReg::at_bit(16, op);
@@ -412,31 +578,47 @@ impl Cop2LoadStore {
             element,
             base,
             offset,
+            data: None,
         })
     }
+
+    fn encode(&self) -> u32 {
+        let offset = (self.offset / self.opcode.item_size() as i16) as u32 & 0x7F;
+
+        ((self.base as u32) << 21)
+            | ((self.vt.index() as u32) << 16)
+            | ((self.opcode as u32) << 11)
+            | ((self.element as u32) << 7)
+            | offset
+    }
+
+    fn operands(&self, out: &mut Vec<Operand>) {
+        out.extend([
+            Operand::VuReg(self.vt),
+            Operand::ElementIndex(self.element),
+            Operand::Mem {
+                base: self.base,
+                offset: self.offset,
+                data: self.data,
+            },
+        ]);
+    }
 }
 
 impl Print for Cop2LoadStore {
-    fn print(&self, opts: PrintOpts, w: &mut impl Write) -> fmt::Result {
-        // load or store letter (l || s) is printed before this
-        // this prints the rest of the command
-        let offset = crate::utils::Offset(self.offset);
-        write!(
-            w,
-            "{}v {}[{}], {}(",
-            self.opcode.mnemonic(),
-            self.vt,
-            self.element,
-            offset
-        )?;
-        self.base.print(opts, w)?;
-        write!(w, ")")
+    fn print(&self, f: &dyn Formatter, w: &mut dyn Write) -> fmt::Result {
+        self.vt.print(f, w)?;
+        f.element_index(self.element, w)?;
+        f.separator(w)?;
+        let name = self.data.map(|sym| render(|s| sym.print(f, s)));
+        let disp = disp(self.offset, self.data, name.as_deref());
+        f.vector_offset(disp, self.opcode.item_size(), self.base, w)
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
 #[repr(u8)]
-enum RspAddressMode {
+pub enum RspAddressMode {
     Byte = 0,
     Short = 1,
     Word = 2,
@@ -472,20 +654,94 @@ impl RspAddressMode {
         }
     }
 
-    const fn mnemonic(&self) -> &'static str {
+    const fn load_mnemonic(&self) -> &'static str {
         match self {
-            Self::Byte => "b",
-            Self::Short => "s",
-            Self::Word => "w",
-            Self::Double => "d",
-            Self::Quad => "q",
-            Self::Rest => "r",
-            Self::Pack => "p",
-            Self::UPack => "u",
-            Self::HalfPack => "h",
-            Self::FourthPack => "f",
-            Self::Wrap => "w",
-            Self::Transpose => "t",
+            Self::Byte => "lbv",
+            Self::Short => "lsv",
+            Self::Word => "llv",
+            Self::Double => "ldv",
+            Self::Quad => "lqv",
+            Self::Rest => "lrv",
+            Self::Pack => "lpv",
+            Self::UPack => "luv",
+            Self::HalfPack => "lhv",
+            Self::FourthPack => "lfv",
+            Self::Wrap => "lwv",
+            Self::Transpose => "ltv",
+        }
+    }
+
+    const fn store_mnemonic(&self) -> &'static str {
+        match self {
+            Self::Byte => "sbv",
+            Self::Short => "ssv",
+            Self::Word => "slv",
+            Self::Double => "sdv",
+            Self::Quad => "sqv",
+            Self::Rest => "srv",
+            Self::Pack => "spv",
+            Self::UPack => "suv",
+            Self::HalfPack => "shv",
+            Self::FourthPack => "sfv",
+            Self::Wrap => "swv",
+            Self::Transpose => "stv",
+        }
+    }
+}
+
+/// `offset` as a displacement from the label `data`, named `name`, if it is known
+fn disp<'a>(offset: i16, data: Option<Sym>, name: Option<&'a str>) -> Disp<'a> {
+    match (data, name) {
+        (Some(sym), Some(name)) => Disp::Label {
+            name,
+            delta: offset as i32 - sym.value() as i32,
+            offset,
+        },
+        _ => Disp::Offset(offset),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RspOpcode;
+
+    const VADDR: u32 = 0x0400_1040;
+
+    /// values of the fields not being swept: clear, set, and mixed
+    const FILL: [u32; 3] = [0, 0x03FF_FFFF, 0x0155_5555];
+
+    fn round_trip(word: u32) {
+        let op = RspOpcode::decode(word, VADDR);
+        let encoded = op.encode(VADDR);
+        assert_eq!(
+            encoded, word,
+            "{:08X} encoded as {:08X} ({:?})",
+            word, encoded, op
+        );
+        assert_eq!(RspOpcode::decode(encoded, VADDR), op, "{:08X}", word);
+    }
+
+    /// Every primary opcode, including the ones the RSP lacks, with each of the
+    /// fields that pick a special, regimm, cop0, cop2, lwc2 or swc2 op swept
+    /// through all of its values. The rest of the word is filled with a few
+    /// patterns, so invalid register and padding fields are covered too.
+    #[test]
+    fn decode_encode_round_trip() {
+        for opcode in 0..0x40 {
+            for fill in FILL {
+                // rs/fmt, rt/regimm op and funct
+                for fields in 0..1 << 16 {
+                    let rs = (fields >> 11) & 0x1F;
+                    let rt = (fields >> 6) & 0x1F;
+                    let funct = fields & 0x3F;
+                    let word = (opcode << 26) | (rs << 21) | (rt << 16) | (fill & 0xFFC0) | funct;
+                    round_trip(word);
+                }
+                // rd/address mode, shamt/element and funct/offset
+                for low in 0..1 << 16 {
+                    round_trip((opcode << 26) | (fill & 0x03FF_0000) | low);
+                }
+            }
         }
     }
 }
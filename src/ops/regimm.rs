@@ -1,9 +1,16 @@
 use std::fmt;
 
-use crate::{print::Print, regs::su::GpReg, sym::Sym, utils, PrintOpts};
+use crate::{
+    instr::Operand,
+    print::{Formatter, Print},
+    regs::su::GpReg,
+    sym::Sym,
+    utils,
+};
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub(crate) enum RegImm {
+pub enum RegImm {
     BLTZ(RsSym),
     BGEZ(RsSym),
     BLTZAL(RsSym),
@@ -31,24 +38,32 @@ impl RegImm {
             Self::BGEZAL(r) => *r,
         }
     }
-}
 
-impl Print for RegImm {
-    fn print(&self, opts: PrintOpts, w: &mut impl fmt::Write) -> fmt::Result {
-        match self {
-            Self::BLTZ(_) => write!(w, "bltz "),
-            Self::BGEZ(_) => write!(w, "bgez "),
-            Self::BLTZAL(_) => write!(w, "bltzal "),
-            Self::BGEZAL(_) => write!(w, "bgezal "),
-        }?;
+    pub(crate) fn encode(&self, vaddr: u32) -> u32 {
+        let subop = match self {
+            Self::BLTZ(_) => 0x00,
+            Self::BGEZ(_) => 0x01,
+            Self::BLTZAL(_) => 0x10,
+            Self::BGEZAL(_) => 0x11,
+        };
+        let RsSym { rs, sym } = self.get_regs();
+
+        ((rs as u32) << 21) | (subop << 16) | sym.branch_field(vaddr)
+    }
 
-        self.get_regs().print(opts, w)
+    pub(crate) const fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::BLTZ(_) => "bltz",
+            Self::BGEZ(_) => "bgez",
+            Self::BLTZAL(_) => "bltzal",
+            Self::BGEZAL(_) => "bgezal",
+        }
     }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub(crate) struct RsSym {
-    rs: GpReg,
+pub struct RsSym {
+    pub(crate) rs: GpReg,
     pub(crate) sym: Sym,
 }
 
@@ -59,12 +74,16 @@ impl RsSym {
 
         Some(Self { rs, sym })
     }
+
+    pub(crate) fn operands(&self, out: &mut Vec<Operand>) {
+        out.extend([Operand::GpReg(self.rs), Operand::Target(self.sym)]);
+    }
 }
 
 impl Print for RsSym {
-    fn print(&self, opts: PrintOpts, w: &mut impl fmt::Write) -> fmt::Result {
-        self.rs.print(opts, w)?;
-        write!(w, ", ")?;
-        self.sym.print(opts, w)
+    fn print(&self, f: &dyn Formatter, w: &mut dyn fmt::Write) -> fmt::Result {
+        self.rs.print(f, w)?;
+        f.separator(w)?;
+        self.sym.print(f, w)
     }
 }
@@ -1,12 +1,16 @@
-use crate::{print::Print, regs::su::GpReg};
-use crate::{utils, PrintOpts};
+use crate::utils;
+use crate::{
+    instr::Operand,
+    print::{Formatter, Print},
+    regs::su::GpReg,
+};
 use num_enum::TryFromPrimitive;
 use std::fmt::{self, Write};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) struct Special {
-    opcode: SpecialOpCode,
-    data: SpecialData,
+pub struct Special {
+    pub(crate) opcode: SpecialOpCode,
+    pub(crate) data: SpecialData,
 }
 
 impl Special {
@@ -14,6 +18,18 @@ impl Special {
         use SpecialOpCode::*;
 
         let opcode = SpecialOpCode::try_from((op & 0x3F) as u8).ok()?;
+        // bits that must be clear for each encoding; anything else is not a canonical op
+        let unused = match opcode {
+            SLL | SRL | SRA => 0x03E00000,
+            JR => 0x001FFFC0,
+            JALR => 0x001F07C0,
+            BREAK => 0,
+            _ => 0x000007C0,
+        };
+        if op & unused != 0 {
+            return None;
+        }
+
         let data = match opcode {
             SLL | SRL | SRA => ShiftImm::from_op(op).map(SpecialData::ShiftImm),
 
@@ -23,25 +39,71 @@ impl Special {
 
             BREAK => Some(SpecialData::Break((op >> 6) & 0xFFFFF)),
 
-            SLLV | SRLV | SRAV | ADD | ADDU | SUB | SUBU | AND | OR | XOR | NOR | SLT | SLTU => {
+            SLLV | SRLV | SRAV => ThreeReg::from_op(op).map(SpecialData::ShiftReg),
+
+            ADD | ADDU | SUB | SUBU | AND | OR | XOR | NOR | SLT | SLTU => {
                 ThreeReg::from_op(op).map(SpecialData::ThreeReg)
             }
         }?;
 
         Some(Self { opcode, data })
     }
+
+    pub(crate) const fn mnemonic(&self) -> &'static str {
+        self.opcode.as_mnemonic()
+    }
+
+    /// for `jr` and `jalr`: the register holding the target, and the link register
+    pub(crate) fn jump_reg(&self) -> Option<(GpReg, Option<GpReg>)> {
+        match self.data {
+            SpecialData::Jr(rs) => Some((rs, None)),
+            SpecialData::JalrReg(d) => Some((d.rs, Some(d.rd))),
+            _ => None,
+        }
+    }
+
+    pub(crate) const fn is_break(&self) -> bool {
+        matches!(self.data, SpecialData::Break(_))
+    }
+
+    pub(crate) fn encode(&self) -> u32 {
+        let fields = match self.data {
+            SpecialData::ShiftImm(d) => d.encode(),
+            SpecialData::ThreeReg(d) | SpecialData::ShiftReg(d) => d.encode(),
+            SpecialData::JalrReg(d) => d.encode(),
+            SpecialData::Jr(reg) => (reg as u32) << 21,
+            SpecialData::Break(code) => code << 6,
+        };
+
+        fields | self.opcode as u32
+    }
+
+    pub(crate) fn operands(&self, out: &mut Vec<Operand>) {
+        match self.data {
+            SpecialData::ShiftImm(d) => d.operands(out),
+            SpecialData::ThreeReg(d) => d.operands(out),
+            SpecialData::ShiftReg(d) => out.extend([
+                Operand::GpReg(d.rd),
+                Operand::GpReg(d.rt),
+                Operand::GpReg(d.rs),
+            ]),
+            SpecialData::JalrReg(d) => d.operands(out),
+            SpecialData::Jr(reg) => out.push(Operand::GpReg(reg)),
+            SpecialData::Break(code) => out.push(Operand::Code(code)),
+        }
+    }
 }
 
 impl Print for Special {
-    fn print(&self, opts: PrintOpts, w: &mut impl Write) -> fmt::Result {
-        write!(w, "{} ", self.opcode)?;
-        self.data.print(opts, w)
+    fn print(&self, f: &dyn Formatter, w: &mut dyn Write) -> fmt::Result {
+        self.data.print(f, w)
     }
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
 #[repr(u8)]
-enum SpecialOpCode {
+pub enum SpecialOpCode {
     SLL = 0x00,
     SRL = 0x02,
     SRA = 0x03,
@@ -63,39 +125,97 @@ enum SpecialOpCode {
     SLTU = 0x2B,
 }
 
+impl SpecialOpCode {
+    pub const fn as_mnemonic(&self) -> &'static str {
+        match self {
+            Self::SLL => "sll",
+            Self::SRL => "srl",
+            Self::SRA => "sra",
+            Self::SLLV => "sllv",
+            Self::SRLV => "srlv",
+            Self::SRAV => "srav",
+            Self::JR => "jr",
+            Self::JALR => "jalr",
+            Self::BREAK => "break",
+            Self::ADD => "add",
+            Self::ADDU => "addu",
+            Self::SUB => "sub",
+            Self::SUBU => "subu",
+            Self::AND => "and",
+            Self::OR => "or",
+            Self::XOR => "xor",
+            Self::NOR => "nor",
+            Self::SLT => "slt",
+            Self::SLTU => "sltu",
+        }
+    }
+
+    /// The result of this ALU op on `rs` and `rt`, or `None` for jumps and `break`.
+    ///
+    /// Shifts move `rt` by the low five bits of `rs`, which for the
+    /// immediate shifts is their shift amount.
+    pub(crate) const fn eval(&self, rs: u32, rt: u32) -> Option<u32> {
+        let value = match self {
+            // the RSP has no overflow exceptions
+            Self::ADD | Self::ADDU => rs.wrapping_add(rt),
+            Self::SUB | Self::SUBU => rs.wrapping_sub(rt),
+            Self::AND => rs & rt,
+            Self::OR => rs | rt,
+            Self::XOR => rs ^ rt,
+            Self::NOR => !(rs | rt),
+            Self::SLT => ((rs as i32) < rt as i32) as u32,
+            Self::SLTU => (rs < rt) as u32,
+            Self::SLL | Self::SLLV => rt << (rs & 31),
+            Self::SRL | Self::SRLV => rt >> (rs & 31),
+            Self::SRA | Self::SRAV => ((rt as i32) >> (rs & 31)) as u32,
+            Self::JR | Self::JALR | Self::BREAK => return None,
+        };
+
+        Some(value)
+    }
+}
+
 impl fmt::Display for SpecialOpCode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let s = format!("{:?}", self);
-        write!(f, "{}", s.to_ascii_lowercase())
+        write!(f, "{}", self.as_mnemonic())
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum SpecialData {
+pub(crate) enum SpecialData {
     ShiftImm(ShiftImm),
     ThreeReg(ThreeReg),
+    // variable shifts are written `rd, rt, rs`
+    ShiftReg(ThreeReg),
     JalrReg(JalrReg),
     Jr(GpReg),
     Break(u32),
 }
 
 impl Print for SpecialData {
-    fn print(&self, opts: PrintOpts, w: &mut impl Write) -> fmt::Result {
+    fn print(&self, f: &dyn Formatter, w: &mut dyn Write) -> fmt::Result {
         match self {
-            SpecialData::ShiftImm(d) => d.print(opts, w),
-            SpecialData::ThreeReg(d) => d.print(opts, w),
-            SpecialData::JalrReg(d) => d.print(opts, w),
-            SpecialData::Jr(reg) => reg.print(opts, w),
-            SpecialData::Break(code) => write!(w, "{code}"),
+            SpecialData::ShiftImm(d) => d.print(f, w),
+            SpecialData::ThreeReg(d) => d.print(f, w),
+            SpecialData::ShiftReg(d) => {
+                d.rd.print(f, w)?;
+                f.separator(w)?;
+                d.rt.print(f, w)?;
+                f.separator(w)?;
+                d.rs.print(f, w)
+            }
+            SpecialData::JalrReg(d) => d.print(f, w),
+            SpecialData::Jr(reg) => reg.print(f, w),
+            SpecialData::Break(code) => f.imm(*code as i32, w),
         }
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) struct ShiftImm {
-    dst: GpReg,
-    src: GpReg,
-    by: u8,
+pub struct ShiftImm {
+    pub(crate) dst: GpReg,
+    pub(crate) src: GpReg,
+    pub(crate) by: u8,
 }
 
 impl ShiftImm {
@@ -106,22 +226,35 @@ impl ShiftImm {
 
         Some(Self { dst, src, by })
     }
+
+    fn encode(&self) -> u32 {
+        ((self.src as u32) << 16) | ((self.dst as u32) << 11) | ((self.by as u32) << 6)
+    }
+
+    fn operands(&self, out: &mut Vec<Operand>) {
+        out.extend([
+            Operand::GpReg(self.dst),
+            Operand::GpReg(self.src),
+            Operand::Imm(self.by as i32),
+        ]);
+    }
 }
 
 impl Print for ShiftImm {
-    fn print(&self, opts: PrintOpts, w: &mut impl Write) -> fmt::Result {
-        self.dst.print(opts, w)?;
-        write!(w, ", ")?;
-        self.src.print(opts, w)?;
-        write!(w, ", {}", self.by)
+    fn print(&self, f: &dyn Formatter, w: &mut dyn Write) -> fmt::Result {
+        self.dst.print(f, w)?;
+        f.separator(w)?;
+        self.src.print(f, w)?;
+        f.separator(w)?;
+        f.imm(self.by as i32, w)
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) struct ThreeReg {
-    rd: GpReg,
-    rs: GpReg,
-    rt: GpReg,
+pub struct ThreeReg {
+    pub(crate) rd: GpReg,
+    pub(crate) rs: GpReg,
+    pub(crate) rt: GpReg,
 }
 
 impl ThreeReg {
@@ -131,22 +264,34 @@ impl ThreeReg {
         let rs = GpReg::at_bit(21, op).ok()?;
         Some(Self { rd, rs, rt })
     }
+
+    fn encode(&self) -> u32 {
+        ((self.rs as u32) << 21) | ((self.rt as u32) << 16) | ((self.rd as u32) << 11)
+    }
+
+    fn operands(&self, out: &mut Vec<Operand>) {
+        out.extend([
+            Operand::GpReg(self.rd),
+            Operand::GpReg(self.rs),
+            Operand::GpReg(self.rt),
+        ]);
+    }
 }
 
 impl Print for ThreeReg {
-    fn print(&self, opts: PrintOpts, w: &mut impl Write) -> fmt::Result {
-        self.rd.print(opts, w)?;
-        write!(w, ", ")?;
-        self.rs.print(opts, w)?;
-        write!(w, ", ")?;
-        self.rt.print(opts, w)
+    fn print(&self, f: &dyn Formatter, w: &mut dyn Write) -> fmt::Result {
+        self.rd.print(f, w)?;
+        f.separator(w)?;
+        self.rs.print(f, w)?;
+        f.separator(w)?;
+        self.rt.print(f, w)
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) struct JalrReg {
-    rd: GpReg,
-    rs: GpReg,
+pub struct JalrReg {
+    pub(crate) rd: GpReg,
+    pub(crate) rs: GpReg,
 }
 
 impl JalrReg {
@@ -156,16 +301,28 @@ impl JalrReg {
 
         Some(Self { rd, rs })
     }
+
+    fn encode(&self) -> u32 {
+        ((self.rs as u32) << 21) | ((self.rd as u32) << 11)
+    }
+
+    fn operands(&self, out: &mut Vec<Operand>) {
+        // `rd` is implicit when linking to `ra`
+        if self.rd != GpReg::RA {
+            out.push(Operand::GpReg(self.rd));
+        }
+        out.push(Operand::GpReg(self.rs));
+    }
 }
 
 impl Print for JalrReg {
-    fn print(&self, opts: PrintOpts, w: &mut impl Write) -> fmt::Result {
+    fn print(&self, f: &dyn Formatter, w: &mut dyn Write) -> fmt::Result {
         if self.rd == GpReg::RA {
-            self.rs.print(opts, w)
+            self.rs.print(f, w)
         } else {
-            self.rd.print(opts, w)?;
-            write!(w, ", ")?;
-            self.rs.print(opts, w)
+            self.rd.print(f, w)?;
+            f.separator(w)?;
+            self.rs.print(f, w)
         }
     }
 }
@@ -1,17 +1,19 @@
 use std::fmt;
 
 use crate::{
-    print::Print,
+    instr::Operand,
+    print::{Formatter, Print},
     regs::{
         su::GpReg,
         vu::{Element, VUCtrlReg, VUReg},
     },
-    utils, PrintOpts,
+    utils,
 };
 use num_enum::TryFromPrimitive;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) enum VUOp {
+pub enum VUOp {
     MFC2(MoveVU),
     CFC2(CtrlVU),
     MTC2(MoveVU),
@@ -22,7 +24,8 @@ pub(crate) enum VUOp {
 
 impl VUOp {
     pub(crate) fn from_op(op: u32) -> Option<Self> {
-        if op & 0x7FF != 0 {
+        // the CO bit selects between vector computation and moves
+        if op & (1 << 25) != 0 {
             return Self::decode_vector_op(op);
         }
         let subop = utils::u8_at(21, 5, op);
@@ -36,9 +39,10 @@ impl VUOp {
     }
 
     fn decode_vector_op(op: u32) -> Option<Self> {
-        let opcode = VUOpcode::try_from(utils::u8_at(0, 5, op)).ok()?;
+        let opcode = VUOpcode::try_from(utils::u8_at(0, 6, op)).ok()?;
         if opcode == VUOpcode::VNOP {
-            return Some(Self::Nop);
+            // only the plain encoding, so that nothing is lost
+            return (op & 0x01FFFFC0 == 0).then_some(Self::Nop);
         }
 
         let element = Element::at_bit(21, 4, op)?;
@@ -47,10 +51,17 @@ impl VUOp {
         let vs = match opcode {
             VUOpcode::VRCP
             | VUOpcode::VRCPL
+            | VUOpcode::VRCPH
             | VUOpcode::VMOV
             | VUOpcode::VRSQ
             | VUOpcode::VRSQL
-            | VUOpcode::VRSQH => Element::at_bit(11, 5, op)?.into(),
+            | VUOpcode::VRSQH => {
+                let de = utils::u8_at(11, 5, op);
+                if de > 7 {
+                    return None;
+                }
+                RegEl::Element(de)
+            }
             _ => VUReg::at_bit(11, op).into(),
         };
 
@@ -64,116 +75,181 @@ impl VUOp {
 
         Some(Self::Compute(info))
     }
+
+    pub(crate) fn encode(&self) -> u32 {
+        match self {
+            VUOp::MFC2(sub) => sub.encode(),
+            VUOp::CFC2(sub) => (0x02 << 21) | sub.encode(),
+            VUOp::MTC2(sub) => (0x04 << 21) | sub.encode(),
+            VUOp::CTC2(sub) => (0x06 << 21) | sub.encode(),
+            VUOp::Nop => (1 << 25) | VUOpcode::VNOP as u32,
+            VUOp::Compute(com) => (1 << 25) | com.encode(),
+        }
+    }
+
+    pub(crate) const fn mnemonic(&self) -> &'static str {
+        match self {
+            VUOp::MFC2(_) => "mfc2",
+            VUOp::MTC2(_) => "mtc2",
+            VUOp::CFC2(_) => "cfc2",
+            VUOp::CTC2(_) => "ctc2",
+            VUOp::Nop => "vnop",
+            VUOp::Compute(com) => com.op.as_mnemonic(),
+        }
+    }
+
+    pub(crate) fn operands(&self, out: &mut Vec<Operand>) {
+        match self {
+            VUOp::MFC2(sub) | VUOp::MTC2(sub) => out.extend([
+                Operand::GpReg(sub.rt),
+                Operand::VuReg(sub.vd),
+                Operand::ElementIndex(sub.element),
+            ]),
+            VUOp::CFC2(sub) | VUOp::CTC2(sub) => {
+                out.extend([Operand::GpReg(sub.rt), Operand::VuCtrlReg(sub.vs)])
+            }
+            VUOp::Nop => (),
+            VUOp::Compute(com) => com.operands(out),
+        }
+    }
 }
 
 impl Print for VUOp {
-    fn print(&self, opts: PrintOpts, w: &mut impl fmt::Write) -> fmt::Result {
+    fn print(&self, f: &dyn Formatter, w: &mut dyn fmt::Write) -> fmt::Result {
         match self {
-            VUOp::MFC2(sub) => {
-                write!(w, "mfc2 ")?;
-                sub.print(opts, w)
-            }
-            VUOp::MTC2(sub) => {
-                write!(w, "mtc2 ")?;
-                sub.print(opts, w)
-            }
-            VUOp::CFC2(sub) => {
-                write!(w, "cfc2 ")?;
-                sub.print(opts, w)
-            }
-            VUOp::CTC2(sub) => {
-                write!(w, "ctc2 ")?;
-                sub.print(opts, w)
-            }
-            VUOp::Nop => write!(w, "vnop"),
-            VUOp::Compute(com) => com.print(opts, w),
+            VUOp::MFC2(sub) | VUOp::MTC2(sub) => sub.print(f, w),
+            VUOp::CFC2(sub) | VUOp::CTC2(sub) => sub.print(f, w),
+            VUOp::Nop => Ok(()),
+            VUOp::Compute(com) => com.print(f, w),
         }
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MoveVU {
-    rt: GpReg,
-    vd: VUReg,
+    pub(crate) rt: GpReg,
+    pub(crate) vd: VUReg,
     // not the same as a compute Element
-    element: u8,
+    pub(crate) element: u8,
 }
 
 impl MoveVU {
     fn from_op(op: u32) -> Option<Self> {
-        let rt = GpReg::at_bit(16, 5).ok()?;
+        if op & 0x7F != 0 {
+            return None;
+        }
+        let rt = GpReg::at_bit(16, op).ok()?;
         let vd = VUReg::at_bit(11, op);
         let element = utils::u8_at(7, 4, op);
 
         Some(Self { rt, vd, element })
     }
+
+    fn encode(&self) -> u32 {
+        ((self.rt as u32) << 16) | ((self.vd.index() as u32) << 11) | ((self.element as u32) << 7)
+    }
 }
 
 impl Print for MoveVU {
-    fn print(&self, opts: PrintOpts, w: &mut impl fmt::Write) -> fmt::Result {
-        self.rt.print(opts, w)?;
-        write!(w, ", {}[{}]", self.vd, self.element)
+    fn print(&self, f: &dyn Formatter, w: &mut dyn fmt::Write) -> fmt::Result {
+        self.rt.print(f, w)?;
+        f.separator(w)?;
+        self.vd.print(f, w)?;
+        f.element_index(self.element, w)
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CtrlVU {
-    rt: GpReg,
-    vs: VUCtrlReg,
+    pub(crate) rt: GpReg,
+    pub(crate) vs: VUCtrlReg,
 }
 
 impl CtrlVU {
     fn from_op(op: u32) -> Option<Self> {
-        let rt = GpReg::at_bit(16, 5).ok()?;
+        if op & 0x7FF != 0 {
+            return None;
+        }
+        let rt = GpReg::at_bit(16, op).ok()?;
         let vs = VUCtrlReg::at_bit(11, op).ok()?;
 
         Some(Self { rt, vs })
     }
+
+    fn encode(&self) -> u32 {
+        ((self.rt as u32) << 16) | ((self.vs as u32) << 11)
+    }
 }
 
 impl Print for CtrlVU {
-    fn print(&self, opts: PrintOpts, w: &mut impl fmt::Write) -> fmt::Result {
-        self.rt.print(opts, w)?;
-        write!(w, ", {}", self.vs)
+    fn print(&self, f: &dyn Formatter, w: &mut dyn fmt::Write) -> fmt::Result {
+        self.rt.print(f, w)?;
+        f.separator(w)?;
+        self.vs.print(f, w)
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct VUCompute {
-    op: VUOpcode,
-    vt: VUReg,
-    vs: RegEl, // element idx in scalar ops
-    vd: VUReg,
-    element: Element,
+    pub(crate) op: VUOpcode,
+    pub(crate) vt: VUReg,
+    pub(crate) vs: RegEl, // element idx in scalar ops
+    pub(crate) vd: VUReg,
+    pub(crate) element: Element,
+}
+
+impl VUCompute {
+    fn encode(&self) -> u32 {
+        let vs = match self.vs {
+            RegEl::Reg(vs) => vs.index(),
+            RegEl::Element(de) => de,
+        };
+
+        ((self.element.encode() as u32) << 21)
+            | ((self.vt.index() as u32) << 16)
+            | ((vs as u32) << 11)
+            | ((self.vd.index() as u32) << 6)
+            | self.op as u32
+    }
+
+    fn operands(&self, out: &mut Vec<Operand>) {
+        out.push(Operand::VuReg(self.vd));
+        match self.vs {
+            RegEl::Reg(vs) => out.push(Operand::VuReg(vs)),
+            RegEl::Element(de) => out.push(Operand::ElementIndex(de)),
+        }
+        out.extend([Operand::VuReg(self.vt), Operand::Element(self.element)]);
+    }
 }
 
 impl Print for VUCompute {
-    fn print(&self, opts: PrintOpts, w: &mut impl fmt::Write) -> fmt::Result {
-        // print op code and vd
-        write!(w, "{} {}", self.op.as_mnemonic(), self.vd)?;
+    fn print(&self, f: &dyn Formatter, w: &mut dyn fmt::Write) -> fmt::Result {
+        self.vd.print(f, w)?;
         // then write the rest of the op depending on if there is one or two scalar regs
         match self.vs {
             RegEl::Reg(vs) => {
                 // op vd, vs, vt[e]
-                write!(w, ", {}, {}", vs, self.vt)?;
-                self.element.print(opts, w)?;
+                f.separator(w)?;
+                vs.print(f, w)?;
             }
             RegEl::Element(de) => {
                 // op vd[de], vt[e]
-                de.print(opts, w)?;
-                write!(w, ", {}", self.vt)?;
-                self.element.print(opts, w)?;
+                f.element_index(de, w)?;
             }
         }
+        f.separator(w)?;
+        self.vt.print(f, w)?;
+        self.element.print(f, w)?;
 
         Ok(())
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum RegEl {
+pub(crate) enum RegEl {
     Reg(VUReg),
-    Element(Element),
+    // lane of `vd` written by the scalar ops
+    Element(u8),
 }
 
 impl From<VUReg> for RegEl {
@@ -182,12 +258,7 @@ impl From<VUReg> for RegEl {
     }
 }
 
-impl From<Element> for RegEl {
-    fn from(e: Element) -> Self {
-        Self::Element(e)
-    }
-}
-
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
 #[repr(u8)]
 pub enum VUOpcode {
@@ -238,7 +309,7 @@ pub enum VUOpcode {
 }
 
 impl VUOpcode {
-    const fn as_mnemonic(&self) -> &'static str {
+    pub const fn as_mnemonic(&self) -> &'static str {
         match self {
             Self::VMULF => "vmulf",
             Self::VMULU => "vmulu",
@@ -1,9 +1,20 @@
 use std::fmt::{self, Write};
 
+use crate::{
+    regs::{
+        cop0::Cop0Reg,
+        su::GpReg,
+        vu::{Element, VUCtrlReg, VUReg},
+    },
+    sym::Sym,
+    utils::Offset,
+};
+
 #[derive(Debug, Copy, Clone)]
 pub struct PrintOpts {
     pub reg_names: bool,
     pub armips_cop0_names: bool,
+    pub syntax: Syntax,
 }
 
 impl Default for PrintOpts {
@@ -11,10 +22,356 @@ impl Default for PrintOpts {
         Self {
             reg_names: true,
             armips_cop0_names: true,
+            syntax: Syntax::Armips,
+        }
+    }
+}
+
+/// The assembler that printed code is written for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Syntax {
+    /// armips with `.rsp`
+    Armips,
+    /// GNU as with libdragon's `rsp.inc` macros
+    Gnu,
+    /// bass with `arch n64.rsp`
+    Bass,
+}
+
+/// Displacement of a load/store address from its base register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disp<'a> {
+    /// a plain byte offset
+    Offset(i16),
+    /// a DMEM label, already named, plus `delta` bytes, which add up to `offset`
+    Label {
+        name: &'a str,
+        delta: i32,
+        offset: i16,
+    },
+}
+
+impl Disp<'_> {
+    /// the byte offset, whether or not it is labeled
+    pub const fn offset(&self) -> i16 {
+        match *self {
+            Self::Offset(offset) | Self::Label { offset, .. } => offset,
+        }
+    }
+}
+
+impl fmt::Display for Disp<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Offset(offset) => write!(f, "{}", Offset(offset)),
+            Self::Label { name, delta, .. } => {
+                f.write_str(name)?;
+                match delta {
+                    0 => Ok(()),
+                    d if d < 0 => write!(f, "-{:#X}", d.unsigned_abs()),
+                    d => write!(f, "+{:#X}", d),
+                }
+            }
+        }
+    }
+}
+
+/// Rendering hooks for every piece of a printed instruction.
+///
+/// The default methods produce armips syntax, so a custom dialect only needs to
+/// override the pieces that differ. [`PrintOpts`] implements the built-in dialects.
+///
+/// Labels are only ever printed through [`Formatter::label`]; the other hooks
+/// receive label names as text, so renaming labels never needs more than `label`.
+pub trait Formatter {
+    fn mnemonic(&self, mnemonic: &str, w: &mut dyn Write) -> fmt::Result {
+        w.write_str(mnemonic)
+    }
+
+    /// between two operands
+    fn separator(&self, w: &mut dyn Write) -> fmt::Result {
+        w.write_str(", ")
+    }
+
+    fn gp_reg(&self, reg: GpReg, w: &mut dyn Write) -> fmt::Result {
+        w.write_str(reg.as_mnemonic())
+    }
+
+    fn cop0_reg(&self, reg: Cop0Reg, w: &mut dyn Write) -> fmt::Result {
+        w.write_str(reg.armips_name())
+    }
+
+    fn vu_reg(&self, reg: VUReg, w: &mut dyn Write) -> fmt::Result {
+        write!(w, "$v{}", reg.index())
+    }
+
+    fn vu_ctrl_reg(&self, reg: VUCtrlReg, w: &mut dyn Write) -> fmt::Result {
+        write!(w, "${}", reg.as_mnemonic())
+    }
+
+    /// element modifier printed after the last vector register of a compute op
+    fn element(&self, e: Element, w: &mut dyn Write) -> fmt::Result {
+        match e {
+            Element::Vector => Ok(()),
+            Element::Quarter(x) => write!(w, "[{}q]", x),
+            Element::Half(x) => write!(w, "[{}h]", x),
+            Element::Whole(x) => write!(w, "[{}]", x),
+        }
+    }
+
+    /// lane index printed after the vector register of a move, load/store or scalar op
+    fn element_index(&self, idx: u8, w: &mut dyn Write) -> fmt::Result {
+        write!(w, "[{}]", idx)
+    }
+
+    /// signed immediates, shift amounts and codes
+    fn imm(&self, imm: i32, w: &mut dyn Write) -> fmt::Result {
+        write!(w, "{}", imm)
+    }
+
+    /// zero extended immediates of the logical ops and `lui`
+    fn hex_imm(&self, imm: u16, w: &mut dyn Write) -> fmt::Result {
+        write!(w, "{:#06X}", imm)
+    }
+
+    /// address operand of a scalar load/store
+    fn offset(&self, disp: Disp, base: GpReg, w: &mut dyn Write) -> fmt::Result {
+        write!(w, "{}(", disp)?;
+        self.gp_reg(base, w)?;
+        w.write_str(")")
+    }
+
+    /// address operand of a vector load/store; its offset field counts items of `_size` bytes
+    fn vector_offset(&self, disp: Disp, _size: u8, base: GpReg, w: &mut dyn Write) -> fmt::Result {
+        self.offset(disp, base, w)
+    }
+
+    fn label(&self, sym: Sym, w: &mut dyn Write) -> fmt::Result {
+        match sym {
+            Sym::Global(addr) => write!(w, "subr_{:08X}", addr),
+            Sym::Static(addr) => write!(w, "@L{:08X}", addr),
+            Sym::Handler(addr) => write!(w, "handler_{:08X}", addr),
+            Sym::Data(addr) => write!(w, "data_{:#06X}", addr),
+        }
+    }
+
+    /// start of the function `name`; this also has to define `name` as a label
+    fn func_start(&self, name: &str, w: &mut dyn Write) -> fmt::Result {
+        write!(w, ".func {}", name)
+    }
+
+    /// end of the function `name`
+    fn func_end(&self, _name: &str, w: &mut dyn Write) -> fmt::Result {
+        w.write_str(".endfunc")
+    }
+
+    fn comment(&self, text: &str, w: &mut dyn Write) -> fmt::Result {
+        write!(w, "/* {} */", text)
+    }
+
+    /// raw data word, used for unsupported instructions
+    fn word(&self, value: u32, w: &mut dyn Write) -> fmt::Result {
+        write!(w, ".word {:#010X}", value)
+    }
+
+    /// a line of data: `values` are each `size` (1, 2 or 4) bytes long
+    fn data(&self, size: u32, values: &[u32], w: &mut dyn Write) -> fmt::Result {
+        let directive = match size {
+            1 => ".db",
+            2 => ".dh",
+            _ => ".dw",
+        };
+        write_data(directive, size, values, w)
+    }
+
+    /// a line of a listing: an already printed instruction and its address and word
+    fn line(&self, addr: u32, word: u32, instr: &str, w: &mut dyn Write) -> fmt::Result {
+        self.comment(&format!("{:08X} {:08X}", addr, word), w)?;
+        write!(w, "\t{}", instr)
+    }
+}
+
+impl Formatter for PrintOpts {
+    fn gp_reg(&self, reg: GpReg, w: &mut dyn Write) -> fmt::Result {
+        match (self.reg_names, self.syntax) {
+            // rsp.inc follows regdef.h in calling r0 `zero`
+            (true, Syntax::Gnu) if reg == GpReg::R0 => w.write_str("zero"),
+            (true, _) => w.write_str(reg.as_mnemonic()),
+            (false, Syntax::Bass) => write!(w, "r{}", reg as u8),
+            (false, _) => w.write_str(reg.as_armips_id()),
+        }
+    }
+
+    fn cop0_reg(&self, reg: Cop0Reg, w: &mut dyn Write) -> fmt::Result {
+        match self.syntax {
+            Syntax::Armips if self.armips_cop0_names => w.write_str(reg.armips_name()),
+            Syntax::Armips => w.write_str(reg.nintendo_name()),
+            Syntax::Gnu => w.write_str(reg.libdragon_name()),
+            Syntax::Bass => write!(w, "c{}", reg as u8),
+        }
+    }
+
+    fn vu_reg(&self, reg: VUReg, w: &mut dyn Write) -> fmt::Result {
+        match self.syntax {
+            Syntax::Armips => write!(w, "$v{}", reg.index()),
+            Syntax::Gnu => write!(w, "$v{:02}", reg.index()),
+            Syntax::Bass => write!(w, "v{}", reg.index()),
+        }
+    }
+
+    fn vu_ctrl_reg(&self, reg: VUCtrlReg, w: &mut dyn Write) -> fmt::Result {
+        match self.syntax {
+            Syntax::Armips => write!(w, "${}", reg.as_mnemonic()),
+            Syntax::Gnu => write!(w, "COP2_CTRL_{}", reg.as_mnemonic().to_ascii_uppercase()),
+            Syntax::Bass => w.write_str(reg.as_mnemonic()),
+        }
+    }
+
+    fn element(&self, e: Element, w: &mut dyn Write) -> fmt::Result {
+        // armips attaches the element to the register (`$v3[1h]`),
+        // rsp.inc takes it as an extra macro argument (`$v03, e(1h)`),
+        // and bass writes the raw field for every op (`v3[e5]`)
+        let (open, close) = match self.syntax {
+            Syntax::Armips => ("[", "]"),
+            Syntax::Gnu => (", e(", ")"),
+            Syntax::Bass => return write!(w, "[e{}]", e.encode()),
+        };
+        match e {
+            Element::Vector => Ok(()),
+            Element::Quarter(x) => write!(w, "{}{}q{}", open, x, close),
+            Element::Half(x) => write!(w, "{}{}h{}", open, x, close),
+            Element::Whole(x) => write!(w, "{}{}{}", open, x, close),
+        }
+    }
+
+    fn element_index(&self, idx: u8, w: &mut dyn Write) -> fmt::Result {
+        match self.syntax {
+            Syntax::Armips => write!(w, "[{}]", idx),
+            Syntax::Gnu => write!(w, ", {}", idx),
+            Syntax::Bass => write!(w, "[e{}]", idx),
+        }
+    }
+
+    fn offset(&self, disp: Disp, base: GpReg, w: &mut dyn Write) -> fmt::Result {
+        match (self.syntax, disp) {
+            // gas needs a relocation to fit a label's address in the 16-bit offset
+            (Syntax::Gnu, Disp::Label { .. }) => write!(w, "%lo({})(", disp)?,
+            _ => write!(w, "{}(", disp)?,
+        }
+        self.gp_reg(base, w)?;
+        w.write_str(")")
+    }
+
+    fn vector_offset(&self, disp: Disp, size: u8, base: GpReg, w: &mut dyn Write) -> fmt::Result {
+        match self.syntax {
+            // rsp.inc: `op vt, element, offset, base`, where the macro checks and
+            // scales the offset, which a relocated label cannot take
+            Syntax::Gnu => {
+                write!(w, "{}", Offset(disp.offset()))?;
+                self.separator(w)?;
+                self.gp_reg(base, w)
+            }
+            // bass takes the raw field, counted in items (`lqv v1[e0],1(a0)`)
+            Syntax::Bass => {
+                match disp {
+                    Disp::Offset(offset) => write!(w, "{}(", offset / size as i16)?,
+                    Disp::Label { .. } => write!(w, "({})/{}(", disp, size)?,
+                }
+                self.gp_reg(base, w)?;
+                w.write_str(")")
+            }
+            _ => self.offset(disp, base, w),
+        }
+    }
+
+    fn label(&self, sym: Sym, w: &mut dyn Write) -> fmt::Result {
+        match (sym, self.syntax) {
+            (Sym::Global(addr), _) => write!(w, "subr_{:08X}", addr),
+            (Sym::Handler(addr), _) => write!(w, "handler_{:08X}", addr),
+            (Sym::Data(addr), _) => write!(w, "data_{:#06X}", addr),
+            (Sym::Static(addr), Syntax::Armips) => write!(w, "@L{:08X}", addr),
+            (Sym::Static(addr), Syntax::Gnu) => write!(w, ".L{:08X}", addr),
+            // bass uses `.` for namespaces and has no `@` labels
+            (Sym::Static(addr), Syntax::Bass) => write!(w, "L{:08X}", addr),
+        }
+    }
+
+    fn func_start(&self, name: &str, w: &mut dyn Write) -> fmt::Result {
+        match self.syntax {
+            Syntax::Armips => write!(w, ".func {}", name),
+            // `.func` only marks the function for debug info in GNU as
+            Syntax::Gnu => write!(w, ".func {}\n{}:", name, name),
+            // bass functions are namespaces, which would hide their local labels
+            Syntax::Bass => {
+                self.comment(&format!("function {}", name), w)?;
+                write!(w, "\n{}:", name)
+            }
+        }
+    }
+
+    fn func_end(&self, name: &str, w: &mut dyn Write) -> fmt::Result {
+        match self.syntax {
+            Syntax::Armips | Syntax::Gnu => w.write_str(".endfunc"),
+            Syntax::Bass => self.comment(&format!("end of {}", name), w),
+        }
+    }
+
+    fn comment(&self, text: &str, w: &mut dyn Write) -> fmt::Result {
+        match self.syntax {
+            // bass only has line comments
+            Syntax::Bass => write!(w, "// {}", text),
+            _ => write!(w, "/* {} */", text),
+        }
+    }
+
+    fn word(&self, value: u32, w: &mut dyn Write) -> fmt::Result {
+        match self.syntax {
+            Syntax::Bass => write!(w, "dd {:#010X}", value),
+            _ => write!(w, ".word {:#010X}", value),
+        }
+    }
+
+    fn data(&self, size: u32, values: &[u32], w: &mut dyn Write) -> fmt::Result {
+        let directive = match (self.syntax, size) {
+            (Syntax::Armips, 1) => ".db",
+            (Syntax::Armips, 2) => ".dh",
+            (Syntax::Armips, _) => ".dw",
+            (Syntax::Gnu, 1) => ".byte",
+            (Syntax::Gnu, 2) => ".half",
+            (Syntax::Gnu, _) => ".word",
+            // bass `dw` is a 16-bit halfword
+            (Syntax::Bass, 1) => "db",
+            (Syntax::Bass, 2) => "dw",
+            (Syntax::Bass, _) => "dd",
+        };
+        write_data(directive, size, values, w)
+    }
+
+    fn line(&self, addr: u32, word: u32, instr: &str, w: &mut dyn Write) -> fmt::Result {
+        let comment = format!("{:08X} {:08X}", addr, word);
+        match self.syntax {
+            Syntax::Bass => {
+                write!(w, "\t{}\t", instr)?;
+                self.comment(&comment, w)
+            }
+            _ => {
+                self.comment(&comment, w)?;
+                write!(w, "\t{}", instr)
+            }
         }
     }
 }
 
 pub(crate) trait Print {
-    fn print(&self, opts: PrintOpts, w: &mut impl Write) -> fmt::Result;
+    fn print(&self, f: &dyn Formatter, w: &mut dyn Write) -> fmt::Result;
+}
+
+/// `directive` followed by `values`, in hex padded to `size` bytes
+fn write_data(directive: &str, size: u32, values: &[u32], w: &mut dyn Write) -> fmt::Result {
+    w.write_str(directive)?;
+    for (i, v) in values.iter().enumerate() {
+        let sep = if i == 0 { " " } else { ", " };
+        write!(w, "{}{:#0width$X}", sep, v, width = size as usize * 2 + 2)?;
+    }
+    Ok(())
 }
@@ -1,11 +1,14 @@
 use std::fmt::{self, Write};
 
-use crate::{print::Print, utils};
+use crate::{
+    print::{Formatter, Print},
+    utils,
+};
 use num_enum::{TryFromPrimitive, TryFromPrimitiveError};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
 #[repr(u8)]
-pub(crate) enum Cop0Reg {
+pub enum Cop0Reg {
     DmaCache = 0,
     DmaRead,
     DmaReadLength,
@@ -29,7 +32,7 @@ impl Cop0Reg {
         Self::try_from(utils::u8_at(b, 5, src))
     }
 
-    const fn nintendo_name(&self) -> &'static str {
+    pub const fn nintendo_name(&self) -> &'static str {
         match self {
             Cop0Reg::DmaCache => "DMA_CACHE",
             Cop0Reg::DmaRead => "DMA_READ",
@@ -50,7 +53,29 @@ impl Cop0Reg {
         }
     }
 
-    const fn armips_name(&self) -> &'static str {
+    /// name defined by libdragon's `rsp.inc`
+    pub const fn libdragon_name(&self) -> &'static str {
+        match self {
+            Cop0Reg::DmaCache => "COP0_DMA_SPADDR",
+            Cop0Reg::DmaRead => "COP0_DMA_RAMADDR",
+            Cop0Reg::DmaReadLength => "COP0_DMA_READ",
+            Cop0Reg::DmaWriteLength => "COP0_DMA_WRITE",
+            Cop0Reg::SpStatus => "COP0_SP_STATUS",
+            Cop0Reg::DmaFull => "COP0_DMA_FULL",
+            Cop0Reg::DmaBusy => "COP0_DMA_BUSY",
+            Cop0Reg::SpReserved => "COP0_SEMAPHORE",
+            Cop0Reg::CmdStart => "COP0_DP_START",
+            Cop0Reg::CmdEnd => "COP0_DP_END",
+            Cop0Reg::CmdCurrent => "COP0_DP_CURRENT",
+            Cop0Reg::CmdStatus => "COP0_DP_STATUS",
+            Cop0Reg::CmdClock => "COP0_DP_CLOCK",
+            Cop0Reg::CmdBusy => "COP0_DP_BUSY",
+            Cop0Reg::CmdPipeBusy => "COP0_DP_PIPE_BUSY",
+            Cop0Reg::CmdTmemBusy => "COP0_DP_TMEM_BUSY",
+        }
+    }
+
+    pub const fn armips_name(&self) -> &'static str {
         match self {
             Cop0Reg::DmaCache => "sp_mem_addr",
             Cop0Reg::DmaRead => "sp_dram_addr",
@@ -73,12 +98,7 @@ impl Cop0Reg {
 }
 
 impl Print for Cop0Reg {
-    fn print(&self, opts: crate::PrintOpts, w: &mut impl Write) -> fmt::Result {
-        let name = if opts.armips_cop0_names {
-            self.armips_name()
-        } else {
-            self.nintendo_name()
-        };
-        write!(w, "{}", name)
+    fn print(&self, f: &dyn Formatter, w: &mut dyn Write) -> fmt::Result {
+        f.cop0_reg(*self, w)
     }
 }
@@ -1,3 +1,3 @@
-pub(crate) mod cop0;
-pub(crate) mod su;
-pub(crate) mod vu;
+pub mod cop0;
+pub mod su;
+pub mod vu;
@@ -1,10 +1,13 @@
-use crate::{print::Print, utils};
+use crate::{
+    print::{Formatter, Print},
+    utils,
+};
 use num_enum::{TryFromPrimitive, TryFromPrimitiveError};
 use std::fmt::{self, Write};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, TryFromPrimitive)]
 #[repr(u8)]
-pub(crate) enum GpReg {
+pub enum GpReg {
     R0 = 0,
     AT,
     V0,
@@ -45,7 +48,7 @@ impl GpReg {
         Self::try_from(utils::u8_at(b, 5, op))
     }
 
-    pub(crate) fn as_armips_id(&self) -> &'static str {
+    pub const fn as_armips_id(&self) -> &'static str {
         match self {
             GpReg::R0 => "$0",
             GpReg::AT => "$1",
@@ -82,7 +85,7 @@ impl GpReg {
         }
     }
 
-    pub(crate) fn as_mnemonic(&self) -> &'static str {
+    pub const fn as_mnemonic(&self) -> &'static str {
         match self {
             GpReg::R0 => "r0",
             GpReg::AT => "at",
@@ -121,13 +124,7 @@ impl GpReg {
 }
 
 impl Print for GpReg {
-    fn print(&self, opts: crate::PrintOpts, w: &mut impl Write) -> fmt::Result {
-        let r = if opts.reg_names {
-            self.as_mnemonic()
-        } else {
-            self.as_armips_id()
-        };
-
-        write!(w, "{}", r)
+    fn print(&self, f: &dyn Formatter, w: &mut dyn Write) -> fmt::Result {
+        f.gp_reg(*self, w)
     }
 }
@@ -1,12 +1,29 @@
 use std::fmt;
 
-use crate::{print::Print, utils, PrintOpts};
+use crate::{
+    print::{Formatter, Print},
+    utils,
+};
 use num_enum::{TryFromPrimitive, TryFromPrimitiveError};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) struct VUReg(u8);
+pub struct VUReg(u8);
 
 impl VUReg {
+    /// register `$v{index}`, if `index` is below 32
+    pub const fn new(index: u8) -> Option<Self> {
+        if index < 32 {
+            Some(Self(index))
+        } else {
+            None
+        }
+    }
+
+    /// register number, `0..32`
+    pub const fn index(&self) -> u8 {
+        self.0
+    }
+
     pub(crate) fn at_bit(b: u8, op: u32) -> Self {
         Self(utils::u8_at(b, 5, op))
     }
@@ -18,9 +35,15 @@ impl fmt::Display for VUReg {
     }
 }
 
+impl Print for VUReg {
+    fn print(&self, f: &dyn Formatter, w: &mut dyn fmt::Write) -> fmt::Result {
+        f.vu_reg(*self, w)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
 #[repr(u8)]
-pub(crate) enum VUCtrlReg {
+pub enum VUCtrlReg {
     Vco = 0,
     Vcc = 1,
     Vce = 2,
@@ -31,7 +54,7 @@ impl VUCtrlReg {
         Self::try_from(utils::u8_at(b, 5, op))
     }
 
-    const fn as_mnemonic(&self) -> &'static str {
+    pub const fn as_mnemonic(&self) -> &'static str {
         match self {
             Self::Vco => "vco",
             Self::Vcc => "vcc",
@@ -46,8 +69,14 @@ impl fmt::Display for VUCtrlReg {
     }
 }
 
+impl Print for VUCtrlReg {
+    fn print(&self, f: &dyn Formatter, w: &mut dyn fmt::Write) -> fmt::Result {
+        f.vu_ctrl_reg(*self, w)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) enum Element {
+pub enum Element {
     Vector,
     Quarter(u8),
     Half(u8),
@@ -59,6 +88,16 @@ impl Element {
         Self::from_u8(utils::u8_at(b, size, op))
     }
 
+    /// the 4-bit `e` field of this element
+    pub(crate) const fn encode(&self) -> u8 {
+        match self {
+            Self::Vector => 0b0000,
+            Self::Quarter(x) => 0b0010 | *x,
+            Self::Half(x) => 0b0100 | *x,
+            Self::Whole(x) => 0b1000 | *x,
+        }
+    }
+
     fn from_u8(val: u8) -> Option<Self> {
         if val & 0b11110000 != 0 {
             None
@@ -77,12 +116,7 @@ impl Element {
 }
 
 impl Print for Element {
-    fn print(&self, _opts: PrintOpts, w: &mut impl fmt::Write) -> fmt::Result {
-        match self {
-            Self::Vector => write!(w, ""),
-            Self::Quarter(x) => write!(w, "[{}q]", x),
-            Self::Half(x) => write!(w, "[{}h]", x),
-            Self::Whole(x) => write!(w, "[{}]", x),
-        }
+    fn print(&self, f: &dyn Formatter, w: &mut dyn fmt::Write) -> fmt::Result {
+        f.element(*self, w)
     }
 }
@@ -0,0 +1,586 @@
+//! An instruction-set simulator for the RSP.
+//!
+//! [`Sim`] runs the decoded [`RspOpcode`]s in its 4 KiB of IMEM against its
+//! 4 KiB of DMEM and its registers, one instruction at a time, with nothing
+//! left to chance: the same IMEM, DMEM and registers always run the same way.
+//! Branches and jumps take effect after their delay slot, and the PC wraps
+//! around within IMEM. `break` halts the simulator. `mfc0` and `mtc0` are
+//! handed to a [`Cop0`] hook, which models whatever is attached to the RSP:
+//! [`Cop0Regs`] keeps the values written, and [`Rcp`] gives the RSP RDRAM
+//! to DMA to and from and an RDP to send commands to.
+//!
+//! Vector ops are bit-accurate, down to the accumulator, the flags and the
+//! reciprocal tables.
+//!
+//! [`Sim::step_traced`] reports what each instruction changed, as a
+//! [`TraceStep`] that can be written as text or JSON.
+
+mod rcp;
+mod trace;
+mod vu;
+
+pub use rcp::{Rcp, RDRAM_SIZE};
+pub use trace::{DmemWrite, TraceStep};
+pub use vu::VuRegs;
+
+use trace::Snapshot;
+
+use crate::{
+    decode,
+    ops::{cop0::Cop0Op, regimm::RegImm, special::SpecialData, vu::VUOp, RspOpcode},
+    regs::{cop0::Cop0Reg, su::GpReg},
+    Instruction, Microcode,
+};
+
+/// size of IMEM and of DMEM, which both wrap around on access
+pub const MEM_SIZE: usize = 0x1000;
+/// RSP address of IMEM, where its instructions are decoded
+const IMEM_BASE: u32 = 0x1000;
+/// bits of an address kept by the PC
+const PC_MASK: u32 = 0xFFC;
+
+/// `SP_STATUS` bits set by `break`
+const STATUS_HALT: u32 = 0x1;
+const STATUS_BROKE: u32 = 0x2;
+
+/// IMEM and DMEM, with the instructions of IMEM kept decoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Memory {
+    imem: Vec<u8>,
+    dmem: Vec<u8>,
+    /// the decoded instruction of each IMEM word
+    code: Vec<Instruction>,
+    /// DMEM addresses stored to or copied into, while they are being recorded
+    written: Option<Vec<u32>>,
+}
+
+impl Memory {
+    /// zeroed IMEM and DMEM
+    pub fn new() -> Self {
+        Self {
+            imem: vec![0; MEM_SIZE],
+            dmem: vec![0; MEM_SIZE],
+            code: (0..MEM_SIZE as u32)
+                .step_by(4)
+                .map(|addr| decode(0, IMEM_BASE | addr))
+                .collect(),
+            written: None,
+        }
+    }
+
+    pub fn imem(&self) -> &[u8] {
+        &self.imem
+    }
+
+    pub fn dmem(&self) -> &[u8] {
+        &self.dmem
+    }
+
+    /// DMEM to change directly; these writes are not recorded in traces
+    pub fn dmem_mut(&mut self) -> &mut [u8] {
+        &mut self.dmem
+    }
+
+    /// start recording the DMEM addresses that stores and copies write
+    pub(super) fn record_writes(&mut self) {
+        self.written = Some(Vec::new());
+    }
+
+    /// stop recording, returning the DMEM addresses written since
+    /// [`record_writes`](Self::record_writes) in the order they were written
+    pub(super) fn take_writes(&mut self) -> Vec<u32> {
+        self.written.take().unwrap_or_default()
+    }
+
+    fn set_dmem(&mut self, addr: usize, byte: u8) {
+        let addr = addr % MEM_SIZE;
+        self.dmem[addr] = byte;
+        if let Some(written) = &mut self.written {
+            written.push(addr as u32);
+        }
+    }
+
+    /// Copy `bytes` into IMEM at `addr`, wrapping around at its end, and
+    /// decode the words they change
+    pub fn write_imem(&mut self, addr: u32, bytes: &[u8]) {
+        let start = addr as usize % MEM_SIZE;
+        for (i, &b) in bytes.iter().enumerate() {
+            self.imem[(start + i) % MEM_SIZE] = b;
+        }
+        let words = (start / 4..(start + bytes.len()).div_ceil(4)).take(MEM_SIZE / 4);
+        for word in words.map(|w| w % (MEM_SIZE / 4)) {
+            let addr = (word * 4) as u32;
+            let bytes = &self.imem[word * 4..word * 4 + 4];
+            let value = u32::from_be_bytes(bytes.try_into().unwrap());
+            self.code[word] = decode(value, IMEM_BASE | addr);
+        }
+    }
+
+    /// Copy `bytes` into DMEM at `addr`, wrapping around at its end
+    pub fn write_dmem(&mut self, addr: u32, bytes: &[u8]) {
+        let start = addr as usize % MEM_SIZE;
+        for (i, &b) in bytes.iter().enumerate() {
+            self.set_dmem(start + i, b);
+        }
+    }
+
+    /// the decoded instruction at IMEM address `pc`
+    pub fn instr(&self, pc: u32) -> &Instruction {
+        &self.code[(pc & PC_MASK) as usize / 4]
+    }
+
+    /// Big-endian value of the `size` bytes at DMEM address `addr`.
+    ///
+    /// The address need not be aligned, and wraps around at the end of DMEM.
+    pub fn load(&self, addr: u32, size: u32) -> u32 {
+        (0..size).fold(0, |v, i| {
+            (v << 8) | self.dmem[addr.wrapping_add(i) as usize % MEM_SIZE] as u32
+        })
+    }
+
+    /// store the low `size` bytes of `value` at DMEM address `addr`, big-endian
+    pub fn store(&mut self, addr: u32, size: u32, value: u32) {
+        for i in 0..size {
+            let byte = (value >> ((size - 1 - i) * 8)) as u8;
+            self.set_dmem(addr.wrapping_add(i) as usize, byte);
+        }
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The scalar unit's registers; `r0` is always zero
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GpRegs([u32; 32]);
+
+impl GpRegs {
+    pub fn get(&self, reg: GpReg) -> u32 {
+        self.0[reg as usize]
+    }
+
+    pub fn set(&mut self, reg: GpReg, value: u32) {
+        if reg != GpReg::R0 {
+            self.0[reg as usize] = value;
+        }
+    }
+}
+
+/// What the RSP's cop0 registers are connected to
+pub trait Cop0 {
+    /// value read from `reg` by `mfc0`
+    fn read(&mut self, reg: Cop0Reg, mem: &mut Memory) -> u32;
+
+    /// `mtc0` wrote `value` to `reg`
+    fn write(&mut self, reg: Cop0Reg, value: u32, mem: &mut Memory);
+
+    /// `break` halted the RSP
+    fn brk(&mut self) {}
+}
+
+/// cop0 registers that just keep the last value written to them.
+///
+/// `break` sets the halt and broke bits of `SP_STATUS`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Cop0Regs(pub [u32; 16]);
+
+impl Cop0 for Cop0Regs {
+    fn read(&mut self, reg: Cop0Reg, _mem: &mut Memory) -> u32 {
+        self.0[reg as usize]
+    }
+
+    fn write(&mut self, reg: Cop0Reg, value: u32, _mem: &mut Memory) {
+        self.0[reg as usize] = value;
+    }
+
+    fn brk(&mut self) {
+        self.0[Cop0Reg::SpStatus as usize] |= STATUS_HALT | STATUS_BROKE;
+    }
+}
+
+/// Why the simulator stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Halt {
+    /// a `break` ran
+    Break,
+    /// the instruction at the PC cannot be simulated, and was not run
+    Unsupported(Instruction),
+    /// [`Sim::run`] ran its most instructions
+    StepLimit,
+}
+
+/// An RSP, running the code in its IMEM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sim<C = Cop0Regs> {
+    pub regs: GpRegs,
+    pub vu: VuRegs,
+    pub mem: Memory,
+    pub cop0: C,
+    /// IMEM address of the next instruction
+    pc: u32,
+    /// target of a taken branch, reached after the delay slot at `pc`
+    delay: Option<u32>,
+    halted: bool,
+    /// instructions run so far
+    steps: u64,
+}
+
+impl<C: Cop0 + Default> Default for Sim<C> {
+    fn default() -> Self {
+        Self::new(C::default())
+    }
+}
+
+impl<C: Cop0> Sim<C> {
+    /// an RSP with zeroed memory and registers, starting at IMEM address zero
+    pub fn new(cop0: C) -> Self {
+        Self {
+            regs: GpRegs::default(),
+            vu: VuRegs::default(),
+            mem: Memory::new(),
+            cop0,
+            pc: 0,
+            delay: None,
+            halted: false,
+            steps: 0,
+        }
+    }
+
+    /// An RSP with the instructions of `ucode` in IMEM at its vaddr and its
+    /// DMEM, if any, about to run its first instruction
+    pub fn load(ucode: &Microcode, cop0: C) -> Self {
+        let mut sim = Self::new(cop0);
+        sim.mem.write_imem(ucode.vaddr, ucode.imem);
+        if let Some(dmem) = ucode.dmem {
+            sim.mem.write_dmem(0, dmem);
+        }
+        sim.set_pc(ucode.vaddr);
+        sim
+    }
+
+    pub const fn pc(&self) -> u32 {
+        self.pc
+    }
+
+    /// continue at `pc`, dropping any branch in flight
+    pub fn set_pc(&mut self, pc: u32) {
+        self.pc = pc & PC_MASK;
+        self.delay = None;
+    }
+
+    /// where a taken branch goes once the instruction at the PC, its delay slot, has run
+    pub const fn branch_target(&self) -> Option<u32> {
+        self.delay
+    }
+
+    pub const fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// let a halted RSP run again from its PC
+    pub fn resume(&mut self) {
+        self.halted = false;
+    }
+
+    /// number of instructions run so far
+    pub const fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Run instructions until the RSP halts, or `max_steps` have run
+    pub fn run(&mut self, max_steps: u64) -> Halt {
+        for _ in 0..max_steps {
+            if let Some(halt) = self.step() {
+                return halt;
+            }
+        }
+        Halt::StepLimit
+    }
+
+    /// Like [`run`](Self::run), handing each instruction that runs to `trace`
+    pub fn run_traced(&mut self, max_steps: u64, mut trace: impl FnMut(TraceStep)) -> Halt {
+        for _ in 0..max_steps {
+            if let Some(halt) = self.step_traced(&mut trace) {
+                return halt;
+            }
+        }
+        Halt::StepLimit
+    }
+
+    /// Like [`step`](Self::step), handing what the instruction changed to
+    /// `trace` if it ran
+    pub fn step_traced(&mut self, trace: impl FnOnce(TraceStep)) -> Option<Halt> {
+        let before = Snapshot::new(self);
+        let steps = self.steps;
+        self.mem.record_writes();
+        let halt = self.step();
+        let written = self.mem.take_writes();
+        if self.steps != steps {
+            trace(before.diff(self, written));
+        }
+        halt
+    }
+
+    /// Run the instruction at the PC, returning why the RSP halted, if it did.
+    ///
+    /// A halted RSP runs nothing until it is [resumed](Self::resume).
+    pub fn step(&mut self) -> Option<Halt> {
+        if self.halted {
+            return Some(Halt::Break);
+        }
+
+        let instr = *self.mem.instr(self.pc);
+        let taken = match self.execute(&instr) {
+            Ok(taken) => taken,
+            Err(halt) => return Some(halt),
+        };
+        self.steps += 1;
+
+        let next = self.delay.take().unwrap_or(self.pc + 4);
+        self.pc = next & PC_MASK;
+        self.delay = taken.map(|t| t & PC_MASK);
+
+        if self.halted {
+            self.cop0.brk();
+            Some(Halt::Break)
+        } else {
+            None
+        }
+    }
+
+    /// Update the registers and memory with the effect of `instr`, returning
+    /// the target of a taken branch or jump
+    fn execute(&mut self, instr: &Instruction) -> Result<Option<u32>, Halt> {
+        let link = self.pc.wrapping_add(8) & PC_MASK;
+        let regs = &mut self.regs;
+        let mem = &mut self.mem;
+        let mut taken = None;
+        let mut branch = |cond: bool, target: u32| {
+            if cond {
+                taken = Some(target);
+            }
+        };
+
+        match instr.op {
+            RspOpcode::Nop => (),
+            RspOpcode::J(sym) => branch(true, sym.value()),
+            RspOpcode::JAL(sym) => {
+                regs.set(GpReg::RA, link);
+                branch(true, sym.value());
+            }
+            RspOpcode::BEQ(d) => branch(regs.get(d.rs) == regs.get(d.rt), d.target.value()),
+            RspOpcode::BNE(d) => branch(regs.get(d.rs) != regs.get(d.rt), d.target.value()),
+            RspOpcode::BLEZ(d) => branch(regs.get(d.rs) as i32 <= 0, d.target.value()),
+            RspOpcode::BGTZ(d) => branch(regs.get(d.rs) as i32 > 0, d.target.value()),
+            RspOpcode::RegImm(sub) => {
+                let r = sub.get_regs();
+                let value = regs.get(r.rs) as i32;
+                // the link is written whether or not the branch is taken
+                if matches!(sub, RegImm::BLTZAL(_) | RegImm::BGEZAL(_)) {
+                    regs.set(GpReg::RA, link);
+                }
+                match sub {
+                    RegImm::BLTZ(_) | RegImm::BLTZAL(_) => branch(value < 0, r.sym.value()),
+                    RegImm::BGEZ(_) | RegImm::BGEZAL(_) => branch(value >= 0, r.sym.value()),
+                }
+            }
+            // the RSP has no overflow exceptions, so `addi` is `addiu`
+            RspOpcode::ADDI(d) | RspOpcode::ADDIU(d) => {
+                regs.set(d.rt, regs.get(d.rs).wrapping_add(d.value()))
+            }
+            RspOpcode::SLTI(d) => {
+                regs.set(d.rt, ((regs.get(d.rs) as i32) < d.value() as i32) as u32)
+            }
+            RspOpcode::SLTIU(d) => regs.set(d.rt, (regs.get(d.rs) < d.value()) as u32),
+            RspOpcode::ANDI(d) => regs.set(d.rt, regs.get(d.rs) & d.value()),
+            RspOpcode::ORI(d) => regs.set(d.rt, regs.get(d.rs) | d.value()),
+            RspOpcode::XORI(d) => regs.set(d.rt, regs.get(d.rs) ^ d.value()),
+            RspOpcode::LUI(d) => regs.set(d.rt, (d.imm as u32) << 16),
+            RspOpcode::COP0(Cop0Op::MFC0(rt, rd)) => {
+                let value = self.cop0.read(rd, mem);
+                regs.set(rt, value);
+            }
+            RspOpcode::COP0(Cop0Op::MTC0(rt, rd)) => self.cop0.write(rd, regs.get(rt), mem),
+            RspOpcode::COP2(VUOp::Nop) => (),
+            RspOpcode::COP2(VUOp::Compute(c)) => self.vu.compute(&c),
+            RspOpcode::COP2(VUOp::MFC2(m)) => {
+                regs.set(m.rt, self.vu.mfc2(m.vd, m.element) as i16 as u32)
+            }
+            RspOpcode::COP2(VUOp::MTC2(m)) => self.vu.mtc2(m.vd, m.element, regs.get(m.rt) as u16),
+            RspOpcode::COP2(VUOp::CFC2(c)) => regs.set(c.rt, self.vu.ctrl(c.vs) as i16 as u32),
+            RspOpcode::COP2(VUOp::CTC2(c)) => self.vu.set_ctrl(c.vs, regs.get(c.rt) as u16),
+            RspOpcode::LWC2(ls) => self.vu.load(&ls, address(regs, ls.base, ls.offset), mem),
+            RspOpcode::SWC2(ls) => self.vu.store(&ls, address(regs, ls.base, ls.offset), mem),
+            RspOpcode::LB(d) => regs.set(
+                d.dst,
+                mem.load(address(regs, d.base, d.offset), 1) as i8 as u32,
+            ),
+            RspOpcode::LH(d) => regs.set(
+                d.dst,
+                mem.load(address(regs, d.base, d.offset), 2) as i16 as u32,
+            ),
+            // the RSP's registers are 32 bits, so `lwu` is `lw`
+            RspOpcode::LW(d) | RspOpcode::LWU(d) => {
+                regs.set(d.dst, mem.load(address(regs, d.base, d.offset), 4))
+            }
+            RspOpcode::LBU(d) => regs.set(d.dst, mem.load(address(regs, d.base, d.offset), 1)),
+            RspOpcode::LHU(d) => regs.set(d.dst, mem.load(address(regs, d.base, d.offset), 2)),
+            RspOpcode::SB(d) => mem.store(address(regs, d.base, d.offset), 1, regs.get(d.dst)),
+            RspOpcode::SH(d) => mem.store(address(regs, d.base, d.offset), 2, regs.get(d.dst)),
+            RspOpcode::SW(d) => mem.store(address(regs, d.base, d.offset), 4, regs.get(d.dst)),
+            RspOpcode::Special(sub) => {
+                let (rd, rs, rt) = match sub.data {
+                    SpecialData::Break(_) => {
+                        self.halted = true;
+                        return Ok(None);
+                    }
+                    SpecialData::Jr(rs) => {
+                        branch(true, regs.get(rs));
+                        return Ok(taken);
+                    }
+                    SpecialData::JalrReg(d) => {
+                        let target = regs.get(d.rs);
+                        regs.set(d.rd, link);
+                        branch(true, target);
+                        return Ok(taken);
+                    }
+                    SpecialData::ThreeReg(d) | SpecialData::ShiftReg(d) => {
+                        (d.rd, regs.get(d.rs), regs.get(d.rt))
+                    }
+                    SpecialData::ShiftImm(d) => (d.dst, d.by as u32, regs.get(d.src)),
+                };
+                match sub.opcode.eval(rs, rt) {
+                    Some(value) => regs.set(rd, value),
+                    None => return Err(Halt::Unsupported(*instr)),
+                }
+            }
+            RspOpcode::Unsupported(_) => return Err(Halt::Unsupported(*instr)),
+        }
+
+        Ok(taken)
+    }
+}
+
+/// the DMEM address `offset` bytes past the value of `base`
+fn address(regs: &GpRegs, base: GpReg, offset: i16) -> u32 {
+    regs.get(base).wrapping_add(offset as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble::assemble;
+
+    /// a simulator with `src` assembled into IMEM at `pc`, about to run it
+    fn sim(src: &str, pc: u32) -> Sim {
+        let words = assemble(src, 0x0400_1000 | pc).unwrap();
+        let bytes = words
+            .iter()
+            .flat_map(|w| w.to_be_bytes())
+            .collect::<Vec<_>>();
+        let mut sim = Sim::default();
+        sim.mem.write_imem(pc, &bytes);
+        sim.set_pc(pc);
+        sim
+    }
+
+    /// run `sim` until it halts, returning the PC of each instruction it ran
+    fn run(sim: &mut Sim) -> Vec<u32> {
+        let mut pcs = Vec::new();
+        assert_eq!(sim.run_traced(100, |step| pcs.push(step.pc)), Halt::Break);
+        pcs
+    }
+
+    #[test]
+    fn delay_slot_runs_before_branch() {
+        let mut sim = sim(
+            "
+            addiu t0, r0, 1
+            beq t0, t0, skip
+            addiu t1, r0, 2
+            addiu t2, r0, 3
+            skip: break
+            ",
+            0,
+        );
+        assert_eq!(run(&mut sim), [0x000, 0x004, 0x008, 0x010]);
+        assert_eq!(sim.regs.get(GpReg::T1), 2);
+        assert_eq!(sim.regs.get(GpReg::T2), 0);
+    }
+
+    #[test]
+    fn untaken_branch_falls_through() {
+        let mut sim = sim(
+            "
+            bne r0, r0, skip
+            addiu t1, r0, 2
+            addiu t2, r0, 3
+            skip: break
+            ",
+            0,
+        );
+        assert_eq!(run(&mut sim), [0x000, 0x004, 0x008, 0x00C]);
+        assert_eq!(sim.regs.get(GpReg::T2), 3);
+    }
+
+    #[test]
+    fn branch_in_delay_slot() {
+        // the first target runs one instruction, then the second branch is taken
+        let mut sim = sim(
+            "
+            j first
+            j second
+            break
+            first: addiu t0, r0, 1
+            addiu t1, r0, 1
+            second: break
+            ",
+            0,
+        );
+        assert_eq!(run(&mut sim), [0x000, 0x004, 0x00C, 0x014]);
+        assert_eq!(sim.regs.get(GpReg::T0), 1);
+        assert_eq!(sim.regs.get(GpReg::T1), 0);
+    }
+
+    #[test]
+    fn pc_wraps_around_imem() {
+        let mut sim = sim(
+            "
+            jal 0x04001008
+            addiu t0, r0, 1
+            addiu t1, r0, 1
+            break
+            ",
+            0xFFC,
+        );
+        assert_eq!(run(&mut sim), [0xFFC, 0x000, 0x008]);
+        assert_eq!(sim.regs.get(GpReg::T0), 1);
+        assert_eq!(sim.regs.get(GpReg::T1), 0);
+        // the link wraps too
+        assert_eq!(sim.regs.get(GpReg::RA), 0x004);
+    }
+
+    #[test]
+    fn break_halts_until_resumed() {
+        let mut sim = sim("break\naddiu t0, r0, 1\nbreak", 0);
+        assert_eq!(sim.step(), Some(Halt::Break));
+        assert_eq!(
+            sim.cop0.0[Cop0Reg::SpStatus as usize],
+            STATUS_HALT | STATUS_BROKE
+        );
+        assert_eq!(sim.pc(), 0x004);
+
+        // a halted RSP runs nothing
+        assert_eq!(sim.step(), Some(Halt::Break));
+        assert_eq!(sim.steps(), 1);
+        assert_eq!(sim.pc(), 0x004);
+
+        sim.resume();
+        assert_eq!(sim.run(100), Halt::Break);
+        assert_eq!(sim.regs.get(GpReg::T0), 1);
+        assert_eq!(sim.steps(), 3);
+    }
+}
@@ -0,0 +1,356 @@
+//! The parts of the N64 that the RSP reaches through cop0: RDRAM by DMA, the
+//! SP status and semaphore, and the RDP's command buffer.
+
+use super::{Cop0, Memory, MEM_SIZE, STATUS_BROKE, STATUS_HALT};
+use crate::regs::cop0::Cop0Reg;
+
+/// size of RDRAM in an N64 with an expansion pak
+pub const RDRAM_SIZE: usize = 0x800000;
+
+/// `SP_STATUS` bit set by a `break` that should interrupt the CPU
+const STATUS_INTBREAK: u32 = 0x40;
+/// `SP_STATUS` bits read back from the status register
+const STATUS_MASK: u32 = 0x7FE3;
+/// `DPC_STATUS` bit choosing DMEM, not RDRAM, as the source of RDP commands
+const DPC_XBUS: u32 = 0x1;
+/// `DPC_STATUS` bit saying the command buffer is ready
+const DPC_CBUF_READY: u32 = 0x80;
+/// bits of an RDRAM address
+const DRAM_MASK: u32 = 0xFF_FFF8;
+
+/// RDRAM, the SP's DMA engine and registers, and the RDP's command buffer.
+///
+/// DMA finishes as soon as its length is written, so it is never busy or
+/// full. The RDP takes commands as soon as `dpc_end` is written, and keeps
+/// them in [`rdp`](Self::rdp) rather than drawing anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rcp {
+    pub rdram: Vec<u8>,
+    /// 64-bit words of every RDP command sent, in order
+    pub rdp: Vec<u64>,
+    /// times the RSP interrupted the CPU
+    pub interrupts: u32,
+    /// SP address of the next DMA, with bit 12 set for IMEM
+    sp_mem_addr: u32,
+    /// RDRAM address of the next DMA
+    sp_dram_addr: u32,
+    /// length register left by the last DMA
+    sp_len: u32,
+    /// `SP_STATUS` as it reads back
+    sp_status: u32,
+    semaphore: bool,
+    dpc_start: u32,
+    dpc_current: u32,
+    dpc_end: u32,
+    /// `DPC_STATUS` bits set by writes
+    dpc_status: u32,
+}
+
+impl Rcp {
+    /// An RCP connected to `rdram`, with its registers cleared
+    pub fn new(rdram: Vec<u8>) -> Self {
+        Self {
+            rdram,
+            rdp: Vec::new(),
+            interrupts: 0,
+            sp_mem_addr: 0,
+            sp_dram_addr: 0,
+            sp_len: 0,
+            sp_status: 0,
+            semaphore: false,
+            dpc_start: 0,
+            dpc_current: 0,
+            dpc_end: 0,
+            dpc_status: 0,
+        }
+    }
+
+    /// The RDP commands sent, each as its 64-bit words.
+    ///
+    /// A command cut short by the end of the stream is given as far as it goes.
+    pub fn rdp_commands(&self) -> impl Iterator<Item = &[u64]> + '_ {
+        let mut rest = self.rdp.as_slice();
+        std::iter::from_fn(move || {
+            let len = rdp_command_len(*rest.first()?).min(rest.len());
+            let (cmd, tail) = rest.split_at(len);
+            rest = tail;
+            Some(cmd)
+        })
+    }
+
+    /// Copy rows between SP memory and RDRAM as set up by `len`, a
+    /// `sp_rd_len` or `sp_wr_len` value
+    fn dma(&mut self, len: u32, to_rdram: bool, mem: &mut Memory) {
+        let row = ((len & 0xFFF) | 7) + 1;
+        let rows = ((len >> 12) & 0xFF) + 1;
+        let skip = len >> 20;
+        let imem = self.sp_mem_addr & 0x1000 != 0;
+        let mut mem_addr = self.sp_mem_addr & 0xFF8;
+        let mut dram_addr = self.sp_dram_addr & DRAM_MASK;
+
+        for _ in 0..rows {
+            let at = |i| (mem_addr + i) as usize % MEM_SIZE;
+            if to_rdram {
+                let src = if imem { mem.imem() } else { mem.dmem() };
+                for i in 0..row {
+                    if let Some(b) = self.rdram.get_mut((dram_addr + i) as usize) {
+                        *b = src[at(i)];
+                    }
+                }
+            } else {
+                let bytes = (0..row)
+                    .map(|i| *self.rdram.get((dram_addr + i) as usize).unwrap_or(&0))
+                    .collect::<Vec<_>>();
+                if imem {
+                    mem.write_imem(mem_addr, &bytes);
+                } else {
+                    mem.write_dmem(mem_addr, &bytes);
+                }
+            }
+            mem_addr = (mem_addr + row) % MEM_SIZE as u32;
+            dram_addr = (dram_addr + row + skip) & DRAM_MASK;
+        }
+
+        self.sp_mem_addr = (self.sp_mem_addr & 0x1000) | mem_addr;
+        self.sp_dram_addr = dram_addr;
+        // the row count has run down and the last row's length is left
+        self.sp_len = (skip << 20) | 0xFF8;
+    }
+
+    /// Apply a write to `SP_STATUS`, whose bits clear or set each status bit
+    fn write_sp_status(&mut self, value: u32) {
+        let mut update =
+            |clear: u32, set: u32, bit: u32| match (value & 1 << clear != 0, value & 1 << set != 0)
+            {
+                (true, false) => self.sp_status &= !bit,
+                (false, true) => self.sp_status |= bit,
+                _ => (),
+            };
+        update(0, 1, STATUS_HALT);
+        update(5, 6, 0x20);
+        update(7, 8, STATUS_INTBREAK);
+        for signal in 0..8 {
+            update(9 + signal * 2, 10 + signal * 2, 0x80 << signal);
+        }
+        if value & 0x4 != 0 {
+            self.sp_status &= !STATUS_BROKE;
+        }
+        if value & 0x18 == 0x10 {
+            self.interrupts += 1;
+        }
+    }
+
+    /// Send the RDP the commands from `dpc_current` up to `dpc_end`
+    fn run_rdp(&mut self, mem: &Memory) {
+        while self.dpc_current < self.dpc_end {
+            let addr = self.dpc_current;
+            let word = if self.dpc_status & DPC_XBUS != 0 {
+                let hi = mem.load(addr, 4) as u64;
+                let lo = mem.load(addr + 4, 4) as u64;
+                (hi << 32) | lo
+            } else {
+                (0..8).fold(0, |w, i| {
+                    let b = self.rdram.get((addr + i) as usize).copied().unwrap_or(0);
+                    (w << 8) | b as u64
+                })
+            };
+            self.rdp.push(word);
+            self.dpc_current += 8;
+        }
+    }
+}
+
+impl Default for Rcp {
+    /// An RCP with zeroed RDRAM of [`RDRAM_SIZE`]
+    fn default() -> Self {
+        Self::new(vec![0; RDRAM_SIZE])
+    }
+}
+
+impl Cop0 for Rcp {
+    fn read(&mut self, reg: Cop0Reg, _mem: &mut Memory) -> u32 {
+        match reg {
+            Cop0Reg::DmaCache => self.sp_mem_addr,
+            Cop0Reg::DmaRead => self.sp_dram_addr,
+            Cop0Reg::DmaReadLength | Cop0Reg::DmaWriteLength => self.sp_len,
+            Cop0Reg::SpStatus => self.sp_status & STATUS_MASK,
+            Cop0Reg::DmaFull | Cop0Reg::DmaBusy => 0,
+            Cop0Reg::SpReserved => std::mem::replace(&mut self.semaphore, true) as u32,
+            Cop0Reg::CmdStart => self.dpc_start,
+            Cop0Reg::CmdEnd => self.dpc_end,
+            Cop0Reg::CmdCurrent => self.dpc_current,
+            Cop0Reg::CmdStatus => self.dpc_status | DPC_CBUF_READY,
+            Cop0Reg::CmdClock | Cop0Reg::CmdBusy | Cop0Reg::CmdPipeBusy | Cop0Reg::CmdTmemBusy => 0,
+        }
+    }
+
+    fn write(&mut self, reg: Cop0Reg, value: u32, mem: &mut Memory) {
+        match reg {
+            Cop0Reg::DmaCache => self.sp_mem_addr = value & 0x1FF8,
+            Cop0Reg::DmaRead => self.sp_dram_addr = value & DRAM_MASK,
+            Cop0Reg::DmaReadLength => self.dma(value, false, mem),
+            Cop0Reg::DmaWriteLength => self.dma(value, true, mem),
+            Cop0Reg::SpStatus => self.write_sp_status(value),
+            Cop0Reg::SpReserved => self.semaphore = false,
+            Cop0Reg::CmdStart => {
+                self.dpc_start = value & DRAM_MASK;
+                self.dpc_current = self.dpc_start;
+            }
+            Cop0Reg::CmdEnd => {
+                self.dpc_end = value & DRAM_MASK;
+                self.run_rdp(mem);
+            }
+            Cop0Reg::CmdStatus => {
+                // pairs of bits clear or set xbus, freeze and flush
+                for (i, bit) in [DPC_XBUS, 0x2, 0x4].into_iter().enumerate() {
+                    match (value >> (i * 2)) & 0x3 {
+                        0x1 => self.dpc_status &= !bit,
+                        0x2 => self.dpc_status |= bit,
+                        _ => (),
+                    }
+                }
+            }
+            // read only
+            Cop0Reg::DmaFull
+            | Cop0Reg::DmaBusy
+            | Cop0Reg::CmdCurrent
+            | Cop0Reg::CmdClock
+            | Cop0Reg::CmdBusy
+            | Cop0Reg::CmdPipeBusy
+            | Cop0Reg::CmdTmemBusy => (),
+        }
+    }
+
+    fn brk(&mut self) {
+        self.sp_status |= STATUS_HALT | STATUS_BROKE;
+        if self.sp_status & STATUS_INTBREAK != 0 {
+            self.interrupts += 1;
+        }
+    }
+}
+
+/// number of 64-bit words in the RDP command starting with `word`
+fn rdp_command_len(word: u64) -> usize {
+    match (word >> 56) & 0x3F {
+        // triangles, with shade, texture and depth coefficients by flag
+        op @ 0x08..=0x0F => {
+            let shade = if op & 0x4 != 0 { 8 } else { 0 };
+            let texture = if op & 0x2 != 0 { 8 } else { 0 };
+            let depth = if op & 0x1 != 0 { 2 } else { 0 };
+            4 + shade + texture + depth
+        }
+        // texture rectangles, plain and flipped
+        0x24 | 0x25 => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// an RCP whose RDRAM holds the low byte of each address
+    fn rcp() -> Rcp {
+        Rcp::new((0..0x1000).map(|i| i as u8).collect())
+    }
+
+    #[test]
+    fn dma_rows_with_skip() {
+        let (mut rcp, mut mem) = (rcp(), Memory::new());
+        rcp.write(Cop0Reg::DmaCache, 0x100, &mut mem);
+        rcp.write(Cop0Reg::DmaRead, 0x200, &mut mem);
+        // three rows of 16 bytes, skipping 8 bytes of RDRAM after each
+        rcp.write(
+            Cop0Reg::DmaReadLength,
+            (8 << 20) | (2 << 12) | 0xF,
+            &mut mem,
+        );
+
+        let dmem = mem.dmem();
+        assert_eq!(dmem[0x100..0x110], rcp.rdram[0x200..0x210]);
+        assert_eq!(dmem[0x110..0x120], rcp.rdram[0x218..0x228]);
+        assert_eq!(dmem[0x120..0x130], rcp.rdram[0x230..0x240]);
+        assert_eq!(dmem[0x130], 0);
+        assert_eq!(rcp.read(Cop0Reg::DmaCache, &mut mem), 0x130);
+        assert_eq!(rcp.read(Cop0Reg::DmaRead, &mut mem), 0x248);
+        assert_eq!(
+            rcp.read(Cop0Reg::DmaReadLength, &mut mem),
+            (8 << 20) | 0xFF8
+        );
+
+        // and back out, with the length rounded up to 8 bytes
+        rcp.write(Cop0Reg::DmaCache, 0x100, &mut mem);
+        rcp.write(Cop0Reg::DmaRead, 0x800, &mut mem);
+        rcp.write(Cop0Reg::DmaWriteLength, (1 << 12) | 0x3, &mut mem);
+        assert_eq!(rcp.rdram[0x800..0x808], mem.dmem()[0x100..0x108]);
+        assert_eq!(rcp.rdram[0x808..0x810], mem.dmem()[0x108..0x110]);
+        assert_eq!(rcp.rdram[0x810], 0x10);
+    }
+
+    #[test]
+    fn dma_selects_imem_with_bit_12() {
+        let (mut rcp, mut mem) = (rcp(), Memory::new());
+        rcp.rdram[0x20..0x24].copy_from_slice(&0x0000000Du32.to_be_bytes());
+        rcp.write(Cop0Reg::DmaCache, 0x1010, &mut mem);
+        rcp.write(Cop0Reg::DmaRead, 0x20, &mut mem);
+        rcp.write(Cop0Reg::DmaReadLength, 0x7, &mut mem);
+
+        assert_eq!(mem.imem()[0x10..0x18], rcp.rdram[0x20..0x28]);
+        assert!(mem.dmem().iter().all(|&b| b == 0));
+        // the copied words are decoded
+        assert_eq!(mem.instr(0x10).word, 0x0000000D);
+        assert_eq!(rcp.read(Cop0Reg::DmaCache, &mut mem), 0x1018);
+
+        mem.write_dmem(0x10, &[0xAA; 8]);
+        rcp.write(Cop0Reg::DmaCache, 0x1010, &mut mem);
+        rcp.write(Cop0Reg::DmaRead, 0x400, &mut mem);
+        rcp.write(Cop0Reg::DmaWriteLength, 0x7, &mut mem);
+        assert_eq!(rcp.rdram[0x400..0x408], mem.imem()[0x10..0x18]);
+    }
+
+    #[test]
+    fn sp_status_bits() {
+        let (mut rcp, mut mem) = (rcp(), Memory::new());
+        // set halt, interrupt on break and signal 0
+        rcp.write(Cop0Reg::SpStatus, (1 << 1) | (1 << 8) | (1 << 10), &mut mem);
+        let status = rcp.read(Cop0Reg::SpStatus, &mut mem);
+        assert_eq!(status, STATUS_HALT | STATUS_INTBREAK | 0x80);
+
+        rcp.brk();
+        assert_eq!(rcp.interrupts, 1);
+        assert_ne!(rcp.read(Cop0Reg::SpStatus, &mut mem) & STATUS_BROKE, 0);
+
+        // clear halt, broke and signal 0, and raise an interrupt
+        rcp.write(Cop0Reg::SpStatus, 1 | 0x4 | (1 << 9) | 0x10, &mut mem);
+        assert_eq!(rcp.read(Cop0Reg::SpStatus, &mut mem), STATUS_INTBREAK);
+        assert_eq!(rcp.interrupts, 2);
+        // setting and clearing at once changes nothing
+        rcp.write(Cop0Reg::SpStatus, 0x3, &mut mem);
+        assert_eq!(rcp.read(Cop0Reg::SpStatus, &mut mem), STATUS_INTBREAK);
+    }
+
+    #[test]
+    fn rdp_reads_rdram_or_xbus() {
+        let (mut rcp, mut mem) = (rcp(), Memory::new());
+        mem.write_dmem(0x40, &[0xE9, 0, 0, 0, 0, 0, 0, 1]);
+
+        rcp.write(Cop0Reg::CmdStart, 0x40, &mut mem);
+        rcp.write(Cop0Reg::CmdEnd, 0x48, &mut mem);
+        assert_eq!(rcp.rdp, [0x4041_4243_4445_4647]);
+
+        rcp.write(Cop0Reg::CmdStatus, 0x2, &mut mem);
+        assert_eq!(
+            rcp.read(Cop0Reg::CmdStatus, &mut mem),
+            DPC_XBUS | DPC_CBUF_READY
+        );
+        rcp.write(Cop0Reg::CmdStart, 0x40, &mut mem);
+        rcp.write(Cop0Reg::CmdEnd, 0x48, &mut mem);
+        assert_eq!(rcp.rdp[1], 0xE900_0000_0000_0001);
+        assert_eq!(rcp.read(Cop0Reg::CmdCurrent, &mut mem), 0x48);
+
+        rcp.write(Cop0Reg::CmdStatus, 0x1, &mut mem);
+        assert_eq!(rcp.read(Cop0Reg::CmdStatus, &mut mem), DPC_CBUF_READY);
+        let commands = rcp.rdp_commands().collect::<Vec<_>>();
+        assert_eq!(commands.len(), 2);
+    }
+}
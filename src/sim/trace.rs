@@ -0,0 +1,273 @@
+//! Per-instruction traces of a [`Sim`], for diffing against other emulators.
+
+use std::fmt::{self, Write};
+
+use super::{GpRegs, Sim, VuRegs};
+use crate::{
+    json::write_str,
+    print::{Formatter, Print},
+    regs::{
+        su::GpReg,
+        vu::{VUCtrlReg, VUReg},
+    },
+    utils::render,
+    Instruction,
+};
+
+/// lanes of a vector register and of the accumulator
+const LANES: usize = 8;
+
+/// What one instruction changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceStep {
+    /// IMEM address of the instruction
+    pub pc: u32,
+    pub instr: Instruction,
+    /// scalar registers that changed, with their new values
+    pub gp: Vec<(GpReg, u32)>,
+    /// vector registers that changed, with their new lanes
+    pub vu: Vec<(VUReg, [u16; LANES])>,
+    /// accumulator lanes that changed, with their new 48 bits
+    pub acc: Vec<(usize, u64)>,
+    /// vector control registers that changed, with their new values
+    pub ctrl: Vec<(VUCtrlReg, u16)>,
+    /// runs of DMEM bytes that were written, in address order
+    pub dmem: Vec<DmemWrite>,
+}
+
+/// Bytes of DMEM written by an instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DmemWrite {
+    /// DMEM address of the first byte
+    pub addr: u32,
+    /// the new bytes
+    pub bytes: Vec<u8>,
+}
+
+/// The registers of a [`Sim`] before an instruction runs
+pub(super) struct Snapshot {
+    pc: u32,
+    instr: Instruction,
+    regs: GpRegs,
+    vu: VuRegs,
+}
+
+impl Snapshot {
+    pub(super) fn new<C>(sim: &Sim<C>) -> Self {
+        Self {
+            pc: sim.pc,
+            instr: *sim.mem.instr(sim.pc),
+            regs: sim.regs,
+            vu: sim.vu,
+        }
+    }
+
+    /// what changed between this snapshot and `sim`, which has run one
+    /// instruction that wrote the DMEM addresses `written`
+    pub(super) fn diff<C>(&self, sim: &Sim<C>, mut written: Vec<u32>) -> TraceStep {
+        let gp = (0..32u8)
+            .map(|r| GpReg::try_from(r).unwrap())
+            .filter(|&r| self.regs.get(r) != sim.regs.get(r))
+            .map(|r| (r, sim.regs.get(r)))
+            .collect();
+        let vu = (0..32u8)
+            .filter_map(VUReg::new)
+            .filter(|&r| self.vu.reg(r) != sim.vu.reg(r))
+            .map(|r| (r, sim.vu.reg(r)))
+            .collect();
+        let acc = (0..LANES)
+            .filter(|&lane| self.vu.acc(lane) != sim.vu.acc(lane))
+            .map(|lane| (lane, sim.vu.acc(lane)))
+            .collect();
+        let ctrl = [VUCtrlReg::Vco, VUCtrlReg::Vcc, VUCtrlReg::Vce]
+            .into_iter()
+            .filter(|&r| self.vu.ctrl(r) != sim.vu.ctrl(r))
+            .map(|r| (r, sim.vu.ctrl(r)))
+            .collect();
+
+        written.sort_unstable();
+        written.dedup();
+        let mut dmem: Vec<DmemWrite> = Vec::new();
+        for addr in written {
+            let b = sim.mem.dmem()[addr as usize];
+            match dmem.last_mut() {
+                Some(run) if run.addr + run.bytes.len() as u32 == addr => run.bytes.push(b),
+                _ => dmem.push(DmemWrite {
+                    addr,
+                    bytes: vec![b],
+                }),
+            }
+        }
+
+        TraceStep {
+            pc: self.pc,
+            instr: self.instr,
+            gp,
+            vu,
+            acc,
+            ctrl,
+            dmem,
+        }
+    }
+}
+
+impl TraceStep {
+    /// Write this step as text: the instruction, then one indented line per change
+    ///
+    /// ```text
+    /// 010: 4A0210D4  vaddc $v3, $v1, $v2
+    ///     $v3 = 0000 0000 4001 FFFE 0000 0004 0005 0006
+    ///     acc[2] = 000000004001
+    ///     $vco = 0013
+    /// ```
+    pub fn write_text(&self, w: &mut dyn Write, f: &dyn Formatter) -> fmt::Result {
+        write!(w, "{:03X}: {:08X}  ", self.pc, self.instr.word)?;
+        self.instr.op.print(f, w)?;
+        writeln!(w)?;
+
+        for &(reg, value) in &self.gp {
+            write!(w, "    ")?;
+            reg.print(f, w)?;
+            writeln!(w, " = {:08X}", value)?;
+        }
+        for (reg, lanes) in &self.vu {
+            write!(w, "    ")?;
+            reg.print(f, w)?;
+            write!(w, " =")?;
+            for lane in lanes {
+                write!(w, " {:04X}", lane)?;
+            }
+            writeln!(w)?;
+        }
+        for &(lane, value) in &self.acc {
+            writeln!(w, "    acc[{}] = {:012X}", lane, value)?;
+        }
+        for &(reg, value) in &self.ctrl {
+            write!(w, "    ")?;
+            reg.print(f, w)?;
+            writeln!(w, " = {:04X}", value)?;
+        }
+        for run in &self.dmem {
+            write!(w, "    [{:03X}] =", run.addr)?;
+            for b in &run.bytes {
+                write!(w, " {:02X}", b)?;
+            }
+            writeln!(w)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write this step as one line of JSON, without its newline
+    ///
+    /// ```text
+    /// {"pc":16,"word":1241649364,"text":"vaddc $v3, $v1, $v2","gp":[],
+    ///  "vu":[{"index":3,"name":"$v3","lanes":[0,0,16385,65534,0,4,5,6]}],
+    ///  "acc":[{"lane":2,"value":16385}],"ctrl":[{"index":0,"name":"$vco","value":19}],
+    ///  "dmem":[]}
+    /// ```
+    pub fn write_json(&self, w: &mut dyn Write, f: &dyn Formatter) -> fmt::Result {
+        write!(
+            w,
+            "{{\"pc\":{},\"word\":{},\"text\":",
+            self.pc, self.instr.word
+        )?;
+        write_str(w, &render(|s| self.instr.op.print(f, s)))?;
+
+        w.write_str(",\"gp\":[")?;
+        for (i, &(reg, value)) in self.gp.iter().enumerate() {
+            let name = render(|s| reg.print(f, s));
+            write_reg(w, i, reg as u32, &name)?;
+            write!(w, ",\"value\":{}}}", value)?;
+        }
+
+        w.write_str("],\"vu\":[")?;
+        for (i, (reg, lanes)) in self.vu.iter().enumerate() {
+            let name = render(|s| reg.print(f, s));
+            write_reg(w, i, reg.index() as u32, &name)?;
+            w.write_str(",\"lanes\":[")?;
+            for (j, lane) in lanes.iter().enumerate() {
+                if j != 0 {
+                    w.write_str(",")?;
+                }
+                write!(w, "{}", lane)?;
+            }
+            w.write_str("]}")?;
+        }
+
+        w.write_str("],\"acc\":[")?;
+        for (i, &(lane, value)) in self.acc.iter().enumerate() {
+            if i != 0 {
+                w.write_str(",")?;
+            }
+            write!(w, "{{\"lane\":{},\"value\":{}}}", lane, value)?;
+        }
+
+        w.write_str("],\"ctrl\":[")?;
+        for (i, &(reg, value)) in self.ctrl.iter().enumerate() {
+            let name = render(|s| reg.print(f, s));
+            write_reg(w, i, reg as u32, &name)?;
+            write!(w, ",\"value\":{}}}", value)?;
+        }
+
+        w.write_str("],\"dmem\":[")?;
+        for (i, run) in self.dmem.iter().enumerate() {
+            if i != 0 {
+                w.write_str(",")?;
+            }
+            write!(w, "{{\"addr\":{},\"bytes\":[", run.addr)?;
+            for (j, b) in run.bytes.iter().enumerate() {
+                if j != 0 {
+                    w.write_str(",")?;
+                }
+                write!(w, "{}", b)?;
+            }
+            w.write_str("]}")?;
+        }
+        w.write_str("]}")
+    }
+}
+
+/// start the `i`th object of a list of registers, up to its `value`
+fn write_reg(w: &mut dyn Write, i: usize, index: u32, name: &str) -> fmt::Result {
+    if i != 0 {
+        w.write_str(",")?;
+    }
+    write!(w, "{{\"index\":{},\"name\":", index)?;
+    write_str(w, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::Cop0Regs;
+
+    /// trace the instruction `word`, run with `t0` holding `0x1234`
+    fn trace(word: u32) -> TraceStep {
+        let mut sim = Sim::<Cop0Regs>::default();
+        sim.mem.write_imem(0, &word.to_be_bytes());
+        sim.regs.set(GpReg::T0, 0x1234);
+        let mut step = None;
+        sim.step_traced(|s| step = Some(s));
+        step.unwrap()
+    }
+
+    #[test]
+    fn dmem_writes() {
+        let write = |addr, bytes: &[u8]| DmemWrite {
+            addr,
+            bytes: bytes.to_vec(),
+        };
+
+        // `sw t0, 0xFFE(r0)` wraps around to the start of DMEM
+        let step = trace(0xAC080FFE);
+        assert_eq!(
+            step.dmem,
+            [write(0x000, &[0x12, 0x34]), write(0xFFE, &[0, 0])]
+        );
+        // `sb r0, 0x20(r0)` is written even though the byte is unchanged
+        assert_eq!(trace(0xA0000020).dmem, [write(0x020, &[0])]);
+        // `addiu t0, r0, 1` writes nothing
+        assert_eq!(trace(0x24080001).dmem, []);
+    }
+}
@@ -0,0 +1,1145 @@
+//! The vector unit: its registers, accumulator and flags, and the effect of
+//! each vector op and vector load and store on them.
+
+use super::{Memory, MEM_SIZE};
+use crate::{
+    ops::{
+        vu::{RegEl, VUCompute, VUOpcode},
+        Cop2LoadStore, RspAddressMode,
+    },
+    regs::vu::{Element, VUCtrlReg, VUReg},
+};
+
+/// lanes of a vector register
+const LANES: usize = 8;
+
+/// the RSP's 512-entry reciprocal ROM, used by `vrcp`
+const RECIPROCALS: [u16; 512] = reciprocals();
+/// the RSP's 512-entry inverse square root ROM, used by `vrsq`
+const INVERSE_SQUARE_ROOTS: [u16; 512] = inverse_square_roots();
+
+const fn reciprocals() -> [u16; 512] {
+    let mut table = [0xFFFF; 512];
+    let mut i = 1;
+    while i < 512 {
+        let b = (1u64 << 34) / (i as u64 + 512);
+        table[i] = ((b + 1) >> 8) as u16;
+        i += 1;
+    }
+    table
+}
+
+const fn inverse_square_roots() -> [u16; 512] {
+    let mut table = [0; 512];
+    let mut i = 0;
+    while i < 512 {
+        // odd entries are for odd powers of two
+        let a = (i as u64 + 512) >> (i % 2);
+        // the least b >= 1 << 17 with a * (b + 1)^2 >= 1 << 44
+        let (mut lo, mut hi) = (1u64 << 17, 1u64 << 19);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if a * (mid + 1) * (mid + 1) < 1 << 44 {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        table[i] = (lo >> 1) as u16;
+        i += 1;
+    }
+    table
+}
+
+/// The vector unit's state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VuRegs {
+    regs: [[u16; LANES]; 32],
+    /// 48-bit accumulator of each lane, sign extended
+    acc: [i64; LANES],
+    /// carry of each lane in the low byte, not equal in the high byte
+    vco: u16,
+    /// compare of each lane in the low byte, clip compare in the high byte
+    vcc: u16,
+    /// compare extension of each lane
+    vce: u8,
+    /// high half of the input to a double precision `vrcpl` or `vrsql`
+    div_in: u16,
+    /// high half of the result of the last `vrcp` or `vrsq`
+    div_out: u16,
+    /// whether the next `vrcpl` or `vrsql` is double precision
+    div_dp: bool,
+}
+
+impl Default for VuRegs {
+    fn default() -> Self {
+        Self {
+            regs: [[0; LANES]; 32],
+            acc: [0; LANES],
+            vco: 0,
+            vcc: 0,
+            vce: 0,
+            div_in: 0,
+            div_out: 0,
+            div_dp: false,
+        }
+    }
+}
+
+impl VuRegs {
+    /// lanes of `reg`, from lane 0 at the lowest address in memory
+    pub fn reg(&self, reg: VUReg) -> [u16; LANES] {
+        self.regs[reg.index() as usize]
+    }
+
+    pub fn set_reg(&mut self, reg: VUReg, lanes: [u16; LANES]) {
+        self.regs[reg.index() as usize] = lanes;
+    }
+
+    /// the 48 bits of the accumulator of `lane`
+    pub fn acc(&self, lane: usize) -> u64 {
+        self.acc[lane] as u64 & 0xFFFF_FFFF_FFFF
+    }
+
+    /// set the accumulator of `lane` to the low 48 bits of `value`
+    pub fn set_acc(&mut self, lane: usize, value: u64) {
+        self.acc[lane] = ((value as i64) << 16) >> 16;
+    }
+
+    pub fn ctrl(&self, reg: VUCtrlReg) -> u16 {
+        match reg {
+            VUCtrlReg::Vco => self.vco,
+            VUCtrlReg::Vcc => self.vcc,
+            VUCtrlReg::Vce => self.vce as u16,
+        }
+    }
+
+    pub fn set_ctrl(&mut self, reg: VUCtrlReg, value: u16) {
+        match reg {
+            VUCtrlReg::Vco => self.vco = value,
+            VUCtrlReg::Vcc => self.vcc = value,
+            VUCtrlReg::Vce => self.vce = value as u8,
+        }
+    }
+
+    /// byte `i` of register `r`, counting from its lowest address in memory
+    fn byte(&self, r: usize, i: usize) -> u8 {
+        lane_byte(&self.regs[r], i)
+    }
+
+    fn set_byte(&mut self, r: usize, i: usize, value: u8) {
+        let lane = &mut self.regs[r][(i & 15) / 2];
+        *lane = if i.is_multiple_of(2) {
+            (*lane & 0x00FF) | (value as u16) << 8
+        } else {
+            (*lane & 0xFF00) | value as u16
+        };
+    }
+
+    /// `mfc2`: the 16 bits starting at byte `e` of `reg`, wrapping around
+    pub(super) fn mfc2(&self, reg: VUReg, e: u8) -> u16 {
+        let (r, e) = (reg.index() as usize, e as usize);
+        (self.byte(r, e) as u16) << 8 | self.byte(r, (e + 1) & 15) as u16
+    }
+
+    /// `mtc2`: write `value` from byte `e` of `reg`, up to its last byte
+    pub(super) fn mtc2(&mut self, reg: VUReg, e: u8, value: u16) {
+        let (r, e) = (reg.index() as usize, e as usize);
+        self.set_byte(r, e, (value >> 8) as u8);
+        if e != 15 {
+            self.set_byte(r, e + 1, value as u8);
+        }
+    }
+
+    fn carry(&self, n: usize) -> bool {
+        self.vco & (1 << n) != 0
+    }
+
+    fn not_equal(&self, n: usize) -> bool {
+        self.vco & (0x100 << n) != 0
+    }
+
+    fn compare(&self, n: usize) -> bool {
+        self.vcc & (1 << n) != 0
+    }
+
+    fn clip(&self, n: usize) -> bool {
+        self.vcc & (0x100 << n) != 0
+    }
+
+    fn set_compare(&mut self, n: usize, value: bool) -> bool {
+        self.vcc = (self.vcc & !(1 << n)) | ((value as u16) << n);
+        value
+    }
+
+    fn set_clip(&mut self, n: usize, value: bool) -> bool {
+        self.vcc = (self.vcc & !(0x100 << n)) | ((value as u16) << (8 + n));
+        value
+    }
+
+    fn set_acc_low(&mut self, n: usize, value: u16) {
+        self.acc[n] = (self.acc[n] & !0xFFFF) | value as i64;
+    }
+
+    fn acc_parts(&self, n: usize) -> (u16, u16, u16) {
+        let acc = self.acc[n];
+        ((acc >> 32) as u16, (acc >> 16) as u16, acc as u16)
+    }
+
+    /// high and middle of the accumulator, clamped to a signed 16-bit value
+    fn clamp_signed(&self, n: usize) -> u16 {
+        (self.acc[n] >> 16).clamp(i16::MIN as i64, i16::MAX as i64) as u16
+    }
+
+    /// high and middle of the accumulator, clamped to `0..=0x7FFF`, or `0xFFFF` above it
+    fn clamp_unsigned(&self, n: usize) -> u16 {
+        match self.acc[n] >> 16 {
+            v if v < 0 => 0,
+            v if v > i16::MAX as i64 => 0xFFFF,
+            v => v as u16,
+        }
+    }
+
+    /// the low accumulator, or `0` or `0xFFFF` if the high and middle overflow a signed 16-bit value
+    fn clamp_low(&self, n: usize) -> u16 {
+        match self.acc[n] >> 16 {
+            v if v < i16::MIN as i64 => 0,
+            v if v > i16::MAX as i64 => 0xFFFF,
+            _ => self.acc[n] as u16,
+        }
+    }
+
+    /// the lanes of `reg` selected by the element `e`
+    fn broadcast(&self, reg: VUReg, e: Element) -> [u16; LANES] {
+        let lanes = self.reg(reg);
+        let select = |n: usize| match e {
+            Element::Vector => n,
+            Element::Quarter(x) => (n & !1) | x as usize,
+            Element::Half(x) => (n & !3) | x as usize,
+            Element::Whole(x) => x as usize,
+        };
+        std::array::from_fn(|n| lanes[select(n)])
+    }
+
+    /// run the vector computation `c`
+    pub(super) fn compute(&mut self, c: &VUCompute) {
+        use VUOpcode::*;
+
+        let vt = self.broadcast(c.vt, c.element);
+        let e = c.element.encode();
+        let vd = c.vd.index() as usize;
+        let vs = match c.vs {
+            RegEl::Reg(vs) => vs.index(),
+            RegEl::Element(de) => de,
+        };
+        let s = self.regs[vs as usize];
+        let signed = |v: u16| v as i16 as i64;
+        let mut out = [0; LANES];
+
+        match c.op {
+            VMULF | VMULU | VMACF | VMACU => {
+                for n in 0..LANES {
+                    let product = signed(s[n]) * signed(vt[n]) * 2;
+                    let acc = match c.op {
+                        VMULF | VMULU => product + 0x8000,
+                        _ => self.acc[n] + product,
+                    };
+                    self.set_acc(n, acc as u64);
+                    out[n] = match c.op {
+                        VMULF | VMACF => self.clamp_signed(n),
+                        _ => self.clamp_unsigned(n),
+                    };
+                }
+            }
+            VRNDP | VRNDN => {
+                for n in 0..LANES {
+                    // the `vs` field selects a shifted product
+                    let product = signed(vt[n]) << if vs & 1 != 0 { 16 } else { 0 };
+                    let acc = self.acc[n];
+                    if (c.op == VRNDP) == (acc >= 0) {
+                        self.set_acc(n, (acc + product) as u64);
+                    }
+                    out[n] = self.clamp_signed(n);
+                }
+            }
+            VMULQ => {
+                for n in 0..LANES {
+                    let mut product = (signed(s[n]) * signed(vt[n])) as i32;
+                    // round towards zero
+                    if product < 0 {
+                        product += 31;
+                    }
+                    self.acc[n] = (product as i64) << 16;
+                    out[n] = clamp_i16((product >> 1) as i64) & !0xF;
+                }
+            }
+            VMACQ => {
+                for (n, out) in out.iter_mut().enumerate() {
+                    let mut product = (self.acc[n] >> 16) as i32;
+                    if product & 0x20 == 0 {
+                        if product < 0 {
+                            product += 32;
+                        } else if product >= 32 {
+                            product -= 32;
+                        }
+                    }
+                    self.acc[n] = ((product as i64) << 16) | (self.acc[n] & 0xFFFF);
+                    *out = clamp_i16((product >> 1) as i64) & !0xF;
+                }
+            }
+            VMUDL | VMUDM | VMUDN | VMUDH | VMADL | VMADM | VMADN | VMADH => {
+                for n in 0..LANES {
+                    let (a, b) = (s[n] as i64, vt[n] as i64);
+                    let product = match c.op {
+                        VMUDL | VMADL => (a * b) >> 16,
+                        VMUDM | VMADM => signed(s[n]) * b,
+                        VMUDN | VMADN => a * signed(vt[n]),
+                        _ => (signed(s[n]) * signed(vt[n])) << 16,
+                    };
+                    let acc = match c.op {
+                        VMUDL | VMUDM | VMUDN | VMUDH => product,
+                        _ => self.acc[n] + product,
+                    };
+                    self.set_acc(n, acc as u64);
+                    out[n] = match c.op {
+                        VMUDL | VMUDN | VMADL | VMADN => self.clamp_low(n),
+                        _ => self.clamp_signed(n),
+                    };
+                }
+            }
+            VADD | VSUB => {
+                for n in 0..LANES {
+                    let carry = self.carry(n) as i64;
+                    let result = match c.op {
+                        VADD => signed(s[n]) + signed(vt[n]) + carry,
+                        _ => signed(s[n]) - signed(vt[n]) - carry,
+                    };
+                    self.set_acc_low(n, result as u16);
+                    out[n] = clamp_i16(result);
+                }
+                self.vco = 0;
+            }
+            VABS => {
+                for n in 0..LANES {
+                    let (low, result) = match s[n] as i16 {
+                        0 => (0, 0),
+                        v if v > 0 => (vt[n], vt[n]),
+                        _ if vt[n] == 0x8000 => (0x8000, 0x7FFF),
+                        _ => {
+                            let neg = vt[n].wrapping_neg();
+                            (neg, neg)
+                        }
+                    };
+                    self.set_acc_low(n, low);
+                    out[n] = result;
+                }
+            }
+            VADDC | VSUBC => {
+                let mut vco = 0;
+                for n in 0..LANES {
+                    let (a, b) = (s[n] as i32, vt[n] as i32);
+                    let result = match c.op {
+                        VADDC => a + b,
+                        _ => a - b,
+                    };
+                    let (carry, ne) = match c.op {
+                        VADDC => (result > 0xFFFF, false),
+                        _ => (result < 0, result != 0),
+                    };
+                    vco |= (carry as u16) << n | (ne as u16) << (8 + n);
+                    self.set_acc_low(n, result as u16);
+                    out[n] = result as u16;
+                }
+                self.vco = vco;
+            }
+            VSAR => {
+                for (n, lane) in out.iter_mut().enumerate() {
+                    let (hi, md, lo) = self.acc_parts(n);
+                    *lane = match e {
+                        8 => hi,
+                        9 => md,
+                        10 => lo,
+                        _ => 0,
+                    };
+                }
+            }
+            VLT | VEQ | VNE | VGE => {
+                for n in 0..LANES {
+                    let (a, b) = (s[n] as i16, vt[n] as i16);
+                    let (ne, carry) = (self.not_equal(n), self.carry(n));
+                    let cond = match c.op {
+                        VLT => a < b || (a == b && ne && carry),
+                        VEQ => a == b && !ne,
+                        VNE => a != b || ne,
+                        _ => a > b || (a == b && !(ne && carry)),
+                    };
+                    self.set_compare(n, cond);
+                    self.set_clip(n, false);
+                    let result = if cond { s[n] } else { vt[n] };
+                    self.set_acc_low(n, result);
+                    out[n] = result;
+                }
+                self.vco = 0;
+            }
+            VCL => {
+                for n in 0..LANES {
+                    let (a, b) = (s[n], vt[n]);
+                    let result = match (self.carry(n), self.not_equal(n)) {
+                        (true, true) => {
+                            if self.compare(n) {
+                                b.wrapping_neg()
+                            } else {
+                                a
+                            }
+                        }
+                        (true, false) => {
+                            let sum = a as u32 + b as u32;
+                            let (zero, carry) = (sum & 0xFFFF == 0, sum > 0xFFFF);
+                            let le = if self.vce & (1 << n) != 0 {
+                                zero || !carry
+                            } else {
+                                zero && !carry
+                            };
+                            if self.set_compare(n, le) {
+                                b.wrapping_neg()
+                            } else {
+                                a
+                            }
+                        }
+                        (false, true) => {
+                            if self.clip(n) {
+                                b
+                            } else {
+                                a
+                            }
+                        }
+                        (false, false) => {
+                            if self.set_clip(n, a >= b) {
+                                b
+                            } else {
+                                a
+                            }
+                        }
+                    };
+                    self.set_acc_low(n, result);
+                    out[n] = result;
+                }
+                self.vco = 0;
+                self.vce = 0;
+            }
+            VCH => {
+                let (mut vco, mut vce) = (0, 0);
+                for n in 0..LANES {
+                    let (a, b) = (s[n] as i16 as i32, vt[n] as i16 as i32);
+                    let differ = (a ^ b) < 0;
+                    let result = if differ { a + b } else { a - b };
+                    let ne = result != 0 && s[n] != !vt[n];
+                    let value = if differ {
+                        let le = self.set_compare(n, result <= 0);
+                        self.set_clip(n, b < 0);
+                        vce |= ((result == -1) as u8) << n;
+                        if le {
+                            vt[n].wrapping_neg()
+                        } else {
+                            s[n]
+                        }
+                    } else {
+                        self.set_compare(n, b < 0);
+                        if self.set_clip(n, result >= 0) {
+                            vt[n]
+                        } else {
+                            s[n]
+                        }
+                    };
+                    vco |= (differ as u16) << n | (ne as u16) << (8 + n);
+                    self.set_acc_low(n, value);
+                    out[n] = value;
+                }
+                self.vco = vco;
+                self.vce = vce;
+            }
+            VCR => {
+                for n in 0..LANES {
+                    let (a, b) = (s[n] as i16 as i32, vt[n] as i16 as i32);
+                    let value = if (a ^ b) < 0 {
+                        self.set_clip(n, b < 0);
+                        if self.set_compare(n, a + b < 0) {
+                            !vt[n]
+                        } else {
+                            s[n]
+                        }
+                    } else {
+                        self.set_compare(n, b < 0);
+                        if self.set_clip(n, a - b >= 0) {
+                            vt[n]
+                        } else {
+                            s[n]
+                        }
+                    };
+                    self.set_acc_low(n, value);
+                    out[n] = value;
+                }
+                self.vco = 0;
+                self.vce = 0;
+            }
+            VMRG => {
+                for n in 0..LANES {
+                    let result = if self.compare(n) { s[n] } else { vt[n] };
+                    self.set_acc_low(n, result);
+                    out[n] = result;
+                }
+                self.vco = 0;
+            }
+            VAND | VNAND | VOR | VNOR | VXOR | VNXOR => {
+                for n in 0..LANES {
+                    let (a, b) = (s[n], vt[n]);
+                    let result = match c.op {
+                        VAND => a & b,
+                        VNAND => !(a & b),
+                        VOR => a | b,
+                        VNOR => !(a | b),
+                        VXOR => a ^ b,
+                        _ => !(a ^ b),
+                    };
+                    self.set_acc_low(n, result);
+                    out[n] = result;
+                }
+            }
+            VRCP | VRCPL | VRSQ | VRSQL | VRCPH | VRSQH | VMOV => {
+                // `vs` is the lane of `vd` to write
+                let de = vs as usize & 7;
+                let input = self.regs[c.vt.index() as usize][e as usize & 7];
+                let value = match c.op {
+                    VRCPH | VRSQH => {
+                        self.div_dp = true;
+                        self.div_in = input;
+                        self.div_out
+                    }
+                    VMOV => vt[de],
+                    _ => {
+                        let double = matches!(c.op, VRCPL | VRSQL) && self.div_dp;
+                        let result = self.divide(input, double, matches!(c.op, VRSQ | VRSQL));
+                        self.div_dp = false;
+                        self.div_out = (result >> 16) as u16;
+                        result as u16
+                    }
+                };
+                for (n, &lane) in vt.iter().enumerate() {
+                    self.set_acc_low(n, lane);
+                }
+                self.regs[vd][de] = value;
+                return;
+            }
+            VNOP => return,
+        }
+
+        self.regs[vd] = out;
+    }
+
+    /// The reciprocal, or inverse square root, of `input` as a 32-bit fixed
+    /// point value, looked up the way the RSP does
+    fn divide(&self, input: u16, double: bool, sqrt: bool) -> u32 {
+        let input = if double {
+            ((self.div_in as i32) << 16) | input as i32
+        } else {
+            input as i16 as i32
+        };
+        let mask = input >> 31;
+        let mut data = input ^ mask;
+        if input > i16::MIN as i32 {
+            data -= mask;
+        }
+
+        if data == 0 {
+            0x7FFF_FFFF
+        } else if input == i16::MIN as i32 {
+            0xFFFF_0000
+        } else {
+            let shift = data.leading_zeros();
+            let index = (((data as u64) << shift) & 0x7FC0_0000) >> 22;
+            let (value, shift) = if sqrt {
+                let index = (index as u32 & 0x1FE) | (shift & 1);
+                (INVERSE_SQUARE_ROOTS[index as usize], (31 - shift) >> 1)
+            } else {
+                (RECIPROCALS[index as usize], 31 - shift)
+            };
+            (((0x10000 | value as u32) << 14) >> shift) ^ mask as u32
+        }
+    }
+
+    /// run the vector load `ls` from DMEM address `addr`
+    pub(super) fn load(&mut self, ls: &Cop2LoadStore, addr: u32, mem: &Memory) {
+        // DMEM wraps around, so only the low bits of the address matter
+        let addr = addr % MEM_SIZE as u32;
+        let read = |addr: u32| mem.load(addr, 1) as u8;
+        let vt = ls.vt.index() as usize;
+        let e = ls.element as u32;
+
+        match ls.opcode {
+            RspAddressMode::Byte
+            | RspAddressMode::Short
+            | RspAddressMode::Word
+            | RspAddressMode::Double => {
+                let end = (e + ls.item_size()).min(16);
+                for (i, b) in (e..end).enumerate() {
+                    self.set_byte(vt, b as usize, read(addr + i as u32));
+                }
+            }
+            RspAddressMode::Quad => {
+                let end = (16 + e - (addr & 15)).min(16);
+                for (i, b) in (e..end).enumerate() {
+                    self.set_byte(vt, b as usize, read(addr + i as u32));
+                }
+            }
+            RspAddressMode::Rest => {
+                let start = e + 16 - (addr & 15);
+                let base = addr & !15;
+                for (i, b) in (start..16).enumerate() {
+                    self.set_byte(vt, b as usize, read(base + i as u32));
+                }
+            }
+            RspAddressMode::Pack | RspAddressMode::UPack => {
+                let index = (addr & 7).wrapping_sub(e);
+                let base = addr & !7;
+                let shift = if ls.opcode == RspAddressMode::Pack {
+                    8
+                } else {
+                    7
+                };
+                for n in 0..LANES as u32 {
+                    let byte = read(base + (index.wrapping_add(n) & 15));
+                    self.regs[vt][n as usize] = (byte as u16) << shift;
+                }
+            }
+            RspAddressMode::HalfPack => {
+                let index = (addr & 7).wrapping_sub(e);
+                let base = addr & !7;
+                for n in 0..LANES as u32 {
+                    let byte = read(base + (index.wrapping_add(n * 2) & 15));
+                    self.regs[vt][n as usize] = (byte as u16) << 7;
+                }
+            }
+            RspAddressMode::FourthPack => {
+                let index = (addr & 7).wrapping_sub(e);
+                let base = addr & !7;
+                let mut tmp = [0; LANES];
+                for n in 0..4 {
+                    let at = |off: u32| read(base + (index.wrapping_add(n * 4 + off) & 15));
+                    tmp[n as usize] = (at(0) as u16) << 7;
+                    tmp[n as usize + 4] = (at(8) as u16) << 7;
+                }
+                for b in e as usize..(e as usize + 8).min(16) {
+                    self.set_byte(vt, b, lane_byte(&tmp, b));
+                }
+            }
+            RspAddressMode::Wrap => {
+                for (i, b) in (16 - e..e + 16).enumerate() {
+                    self.set_byte(vt, b as usize, read(addr + i as u32 * 4));
+                }
+            }
+            RspAddressMode::Transpose => {
+                let begin = addr & !7;
+                let mut addr = begin + ((e + (addr & 8)) & 15);
+                let mut next = || {
+                    let byte = read(addr);
+                    addr += 1;
+                    if addr == begin + 16 {
+                        addr = begin;
+                    }
+                    byte
+                };
+                let base = vt & !7;
+                let mut reg = e as usize >> 1;
+                for i in 0..LANES {
+                    self.set_byte(base + reg, i * 2, next());
+                    self.set_byte(base + reg, i * 2 + 1, next());
+                    reg = (reg + 1) & 7;
+                }
+            }
+        }
+    }
+
+    /// run the vector store `ls` to DMEM address `addr`
+    pub(super) fn store(&self, ls: &Cop2LoadStore, addr: u32, mem: &mut Memory) {
+        let addr = addr % MEM_SIZE as u32;
+        let mut write = |addr: u32, value: u8| mem.store(addr, 1, value as u32);
+        let vt = ls.vt.index() as usize;
+        let e = ls.element as u32;
+        let byte = |b: u32| self.byte(vt, b as usize & 15);
+        let lane = |n: u32| self.regs[vt][n as usize & 7];
+
+        match ls.opcode {
+            RspAddressMode::Byte
+            | RspAddressMode::Short
+            | RspAddressMode::Word
+            | RspAddressMode::Double => {
+                for i in 0..ls.item_size() {
+                    write(addr + i, byte(e + i));
+                }
+            }
+            RspAddressMode::Quad => {
+                for (i, b) in (e..e + 16 - (addr & 15)).enumerate() {
+                    write(addr + i as u32, byte(b));
+                }
+            }
+            RspAddressMode::Rest => {
+                let shift = 16 - (addr & 15);
+                let base = addr & !15;
+                for (i, b) in (e..e + (addr & 15)).enumerate() {
+                    write(base + i as u32, byte(b + shift));
+                }
+            }
+            RspAddressMode::Pack | RspAddressMode::UPack => {
+                // packed lanes are written from their high byte, unpacked from bit 7
+                let packed_first = ls.opcode == RspAddressMode::Pack;
+                for (i, b) in (e..e + 8).enumerate() {
+                    let value = if ((b & 15) < 8) == packed_first {
+                        byte((b & 7) << 1)
+                    } else {
+                        (lane(b) >> 7) as u8
+                    };
+                    write(addr + i as u32, value);
+                }
+            }
+            RspAddressMode::HalfPack => {
+                let index = addr & 7;
+                let base = addr & !7;
+                for n in 0..LANES as u32 {
+                    let b = e + n * 2;
+                    let value = byte(b) << 1 | byte(b + 1) >> 7;
+                    write(base + ((index + n * 2) & 15), value);
+                }
+            }
+            RspAddressMode::FourthPack => {
+                let index = addr & 7;
+                let base = addr & !7;
+                let lanes = match e {
+                    0 | 15 => Some([0, 1, 2, 3]),
+                    1 => Some([6, 7, 4, 5]),
+                    4 => Some([1, 2, 3, 0]),
+                    5 => Some([7, 4, 5, 6]),
+                    8 => Some([4, 5, 6, 7]),
+                    11 => Some([3, 0, 1, 2]),
+                    12 => Some([5, 6, 7, 4]),
+                    _ => None,
+                };
+                for i in 0..4 {
+                    let value = lanes.map_or(0, |l| (lane(l[i as usize]) >> 7) as u8);
+                    write(base + ((index + i * 4) & 15), value);
+                }
+            }
+            RspAddressMode::Wrap => {
+                let index = addr & 7;
+                let base = addr & !7;
+                for (i, b) in (e..e + 16).enumerate() {
+                    write(base + ((index + i as u32) & 15), byte(b));
+                }
+            }
+            RspAddressMode::Transpose => {
+                let start = vt & !7;
+                let mut b = 16 - (e & !1);
+                let mut index = (addr & 7).wrapping_sub(e & !1);
+                let base = addr & !7;
+                for reg in start..start + 8 {
+                    for _ in 0..2 {
+                        write(base + (index & 15), self.byte(reg, b as usize & 15));
+                        index = index.wrapping_add(1);
+                        b += 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// byte `i` of `lanes`, which are big-endian in memory
+fn lane_byte(lanes: &[u16; LANES], i: usize) -> u8 {
+    let lane = lanes[(i & 15) / 2];
+    if i.is_multiple_of(2) {
+        (lane >> 8) as u8
+    } else {
+        lane as u8
+    }
+}
+
+/// `value` clamped to a signed 16-bit value
+fn clamp_i16(value: i64) -> u16 {
+    value.clamp(i16::MIN as i64, i16::MAX as i64) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regs::su::GpReg;
+
+    const VS: u8 = 1;
+    const VT: u8 = 2;
+    const VD: u8 = 3;
+
+    fn reg(index: u8) -> VUReg {
+        VUReg::new(index).unwrap()
+    }
+
+    /// run `op vd, vs, vt` on lanes `s` and `t`, returning `vd`
+    fn run(vu: &mut VuRegs, op: VUOpcode, s: [u16; LANES], t: [u16; LANES]) -> [u16; LANES] {
+        vu.set_reg(reg(VS), s);
+        vu.set_reg(reg(VT), t);
+        vu.compute(&VUCompute {
+            op,
+            vt: reg(VT),
+            vs: RegEl::Reg(reg(VS)),
+            vd: reg(VD),
+            element: Element::Vector,
+        });
+        vu.reg(reg(VD))
+    }
+
+    /// run the scalar op `op vd[de], vt[e]` on `input`, returning lane `de` of `vd`
+    fn run_scalar(vu: &mut VuRegs, op: VUOpcode, input: u16) -> u16 {
+        vu.set_reg(reg(VT), [input; LANES]);
+        vu.compute(&VUCompute {
+            op,
+            vt: reg(VT),
+            vs: RegEl::Element(5),
+            vd: reg(VD),
+            element: Element::Whole(2),
+        });
+        vu.reg(reg(VD))[5]
+    }
+
+    fn ls(opcode: RspAddressMode, vt: u8, element: u8) -> Cop2LoadStore {
+        Cop2LoadStore {
+            opcode,
+            vt: reg(vt),
+            element,
+            base: GpReg::R0,
+            offset: 0,
+            data: None,
+        }
+    }
+
+    /// DMEM where each byte holds the low byte of its address
+    fn counting_dmem() -> Memory {
+        let mut mem = Memory::new();
+        let bytes = (0..MEM_SIZE).map(|i| i as u8).collect::<Vec<_>>();
+        mem.write_dmem(0, &bytes);
+        mem
+    }
+
+    #[test]
+    fn vmulf_rounds_and_clamps() {
+        let mut vu = VuRegs::default();
+        let s = [0x4000, 0x8000, 0xFFFF, 0x8000, 0, 0, 0, 0];
+        let t = [0x4000, 0x8000, 0x0001, 0x7FFF, 0, 0, 0, 0];
+        let out = run(&mut vu, VUOpcode::VMULF, s, t);
+        assert_eq!(out, [0x2000, 0x7FFF, 0, 0x8001, 0, 0, 0, 0]);
+        assert_eq!(vu.acc(0), 0x0000_2000_8000);
+        assert_eq!(vu.acc(1), 0x0000_8000_8000);
+        assert_eq!(vu.acc(2), 0x0000_0000_7FFE);
+        assert_eq!(vu.acc(3), 0xFFFF_8001_8000);
+        assert_eq!(vu.acc(4), 0x0000_0000_8000);
+    }
+
+    #[test]
+    fn vmulu_clamps_unsigned() {
+        let mut vu = VuRegs::default();
+        let s = [0x8000, 0x7FFF, 0x8000, 0, 0, 0, 0, 0];
+        let t = [0x4000, 0x7FFF, 0x8000, 0, 0, 0, 0, 0];
+        let out = run(&mut vu, VUOpcode::VMULU, s, t);
+        assert_eq!(out, [0, 0x7FFE, 0xFFFF, 0, 0, 0, 0, 0]);
+        assert_eq!(vu.acc(1), 0x0000_7FFE_8002);
+    }
+
+    #[test]
+    fn vmacf_accumulates_and_clamps() {
+        let mut vu = VuRegs::default();
+        let s = [0x7FFF, 0x0002, 0, 0, 0, 0, 0, 0];
+        let t = [0x7FFF, 0x4000, 0, 0, 0, 0, 0, 0];
+        vu.set_acc(1, 0x7FFF_FFFF_0000);
+
+        let out = run(&mut vu, VUOpcode::VMACF, s, t);
+        assert_eq!(out[0], 0x7FFE);
+        assert_eq!(vu.acc(0), 0x0000_7FFE_0002);
+        // the accumulator is 48 bits, and wraps around to negative
+        assert_eq!(out[1], 0x8000);
+        assert_eq!(vu.acc(1), 0x8000_0000_0000);
+
+        let out = run(&mut vu, VUOpcode::VMACF, s, t);
+        assert_eq!(out[0], 0x7FFF);
+        assert_eq!(vu.acc(0), 0x0000_FFFC_0004);
+    }
+
+    #[test]
+    fn vadd_uses_and_clears_carry() {
+        let mut vu = VuRegs::default();
+        vu.set_ctrl(VUCtrlReg::Vco, 0xFF05);
+        let s = [0x7FFF, 0x8000, 0x0001, 0, 0, 0, 0, 0];
+        let t = [0x0001, 0xFFFF, 0x0002, 0, 0, 0, 0, 0];
+        let out = run(&mut vu, VUOpcode::VADD, s, t);
+        assert_eq!(out, [0x7FFF, 0x8000, 0x0004, 0, 0, 0, 0, 0]);
+        // the low accumulator keeps the unclamped sum
+        assert_eq!(vu.acc(0) & 0xFFFF, 0x8001);
+        assert_eq!(vu.acc(1) & 0xFFFF, 0x7FFF);
+        assert_eq!(vu.ctrl(VUCtrlReg::Vco), 0);
+    }
+
+    #[test]
+    fn vaddc_and_vsubc_set_vco() {
+        let mut vu = VuRegs::default();
+        let s = [0xFFFF, 0x1234, 0, 0, 0, 0, 0, 0];
+        let t = [0x0001, 0x1111, 0, 0, 0, 0, 0, 0];
+        let out = run(&mut vu, VUOpcode::VADDC, s, t);
+        assert_eq!(out, [0x0000, 0x2345, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(vu.ctrl(VUCtrlReg::Vco), 0x0001);
+
+        let s = [0x0001, 0x0005, 0x0005, 0, 0, 0, 0, 0];
+        let t = [0x0002, 0x0005, 0x0003, 0, 0, 0, 0, 0];
+        let out = run(&mut vu, VUOpcode::VSUBC, s, t);
+        assert_eq!(out, [0xFFFF, 0, 2, 0, 0, 0, 0, 0]);
+        // borrow in the low byte, not equal in the high byte
+        assert_eq!(vu.ctrl(VUCtrlReg::Vco), 0x0501);
+    }
+
+    #[test]
+    fn selects_compare_with_vco() {
+        let s = [1, 2, 3, 3, 3, 0x8000, 0x7FFF, 5];
+        let t = [2, 1, 3, 3, 3, 0x7FFF, 0x8000, 5];
+        // lane 3 carries and is not equal, lane 4 is not equal and lane 7 carries
+        let vco = 0x1888;
+        let cases = [
+            (VUOpcode::VLT, 0x29, [1, 1, 3, 3, 3, 0x8000, 0x8000, 5]),
+            (VUOpcode::VGE, 0xD6, [2, 2, 3, 3, 3, 0x7FFF, 0x7FFF, 5]),
+            (VUOpcode::VEQ, 0x84, t),
+            (VUOpcode::VNE, 0x7B, s),
+        ];
+        for (op, vcc, expected) in cases {
+            let mut vu = VuRegs::default();
+            vu.set_ctrl(VUCtrlReg::Vco, vco);
+            vu.set_ctrl(VUCtrlReg::Vcc, 0xFF00);
+            assert_eq!(run(&mut vu, op, s, t), expected, "{:?}", op);
+            assert_eq!(vu.ctrl(VUCtrlReg::Vcc), vcc, "{:?}", op);
+            assert_eq!(vu.ctrl(VUCtrlReg::Vco), 0, "{:?}", op);
+        }
+    }
+
+    #[test]
+    fn vch_sets_vcc_vco_and_vce() {
+        let mut vu = VuRegs::default();
+        let s = [5, 5, 2, 0xFFFB, 0, 0xFFFB, 3, 0x8000];
+        let t = [3, 0xFFFD, 0xFFFD, 3, 0, 0xFFFD, 0xFFFD, 0x7FFF];
+        let out = run(&mut vu, VUOpcode::VCH, s, t);
+        assert_eq!(out, [3, 5, 3, 0xFFFD, 0, 0xFFFB, 3, 0x8001]);
+        assert_eq!(vu.ctrl(VUCtrlReg::Vcc), 0x57EC);
+        assert_eq!(vu.ctrl(VUCtrlReg::Vco), 0x2BCE);
+        assert_eq!(vu.ctrl(VUCtrlReg::Vce), 0x84);
+    }
+
+    #[test]
+    fn vcl_uses_flags_from_vch() {
+        let mut vu = VuRegs::default();
+        // lanes 3 to 6 carry, lanes 2 and 3 are not equal
+        vu.set_ctrl(VUCtrlReg::Vco, 0x0C78);
+        // lane 2 clips, lane 3 compares
+        vu.set_ctrl(VUCtrlReg::Vcc, 0x0408);
+        vu.set_ctrl(VUCtrlReg::Vce, 0x20);
+        let s = [5, 3, 7, 7, 0xFFFF, 2, 0x1000, 0x8000];
+        let t = [3, 5, 9, 9, 1, 3, 0xF000, 0x7FFF];
+        let out = run(&mut vu, VUOpcode::VCL, s, t);
+        assert_eq!(out, [3, 3, 9, 0xFFF7, 0xFFFF, 0xFFFD, 0x1000, 0x7FFF]);
+        assert_eq!(vu.ctrl(VUCtrlReg::Vcc), 0x8528);
+        assert_eq!(vu.ctrl(VUCtrlReg::Vco), 0);
+        assert_eq!(vu.ctrl(VUCtrlReg::Vce), 0);
+    }
+
+    #[test]
+    fn vcr_clips_ones_complement() {
+        let mut vu = VuRegs::default();
+        vu.set_ctrl(VUCtrlReg::Vco, 0xFFFF);
+        vu.set_ctrl(VUCtrlReg::Vce, 0xFF);
+        let s = [5, 2, 0xFFFB, 5, 0xFFFB, 3, 0, 0xFFFD];
+        let t = [0xFFFD, 0xFFFD, 3, 3, 0xFFFD, 0xFFFD, 0, 0xFFFD];
+        let out = run(&mut vu, VUOpcode::VCR, s, t);
+        assert_eq!(out, [5, 2, 0xFFFC, 3, 0xFFFB, 3, 0, 0xFFFD]);
+        assert_eq!(vu.ctrl(VUCtrlReg::Vcc), 0xEB96);
+        assert_eq!(vu.ctrl(VUCtrlReg::Vco), 0);
+        assert_eq!(vu.ctrl(VUCtrlReg::Vce), 0);
+    }
+
+    #[test]
+    fn division_tables() {
+        assert_eq!(RECIPROCALS[..4], [0xFFFF, 0xFF00, 0xFE01, 0xFD04]);
+        assert_eq!(RECIPROCALS[511], 0x0040);
+        assert_eq!(INVERSE_SQUARE_ROOTS[..4], [0x6A09, 0xFFFF, 0x6955, 0xFF00]);
+    }
+
+    #[test]
+    fn vrcp_single_precision() {
+        let mut vu = VuRegs::default();
+        assert_eq!(run_scalar(&mut vu, VUOpcode::VRCP, 0x0001), 0xC000);
+        assert_eq!(vu.div_out, 0x7FFF);
+        assert_eq!(run_scalar(&mut vu, VUOpcode::VRCP, 0x0002), 0xE000);
+        assert_eq!(vu.div_out, 0x3FFF);
+        // negative results are the one's complement
+        assert_eq!(run_scalar(&mut vu, VUOpcode::VRCP, 0xFFFE), 0x1FFF);
+        assert_eq!(vu.div_out, 0xC000);
+        assert_eq!(run_scalar(&mut vu, VUOpcode::VRCP, 0), 0xFFFF);
+        assert_eq!(vu.div_out, 0x7FFF);
+        assert_eq!(run_scalar(&mut vu, VUOpcode::VRCP, 0x8000), 0);
+        assert_eq!(vu.div_out, 0xFFFF);
+        // the accumulator gets the broadcast `vt`
+        assert!((0..LANES).all(|n| vu.acc(n) & 0xFFFF == 0x8000));
+    }
+
+    #[test]
+    fn vrcph_and_vrcpl_double_precision() {
+        let mut vu = VuRegs::default();
+        run_scalar(&mut vu, VUOpcode::VRCP, 0x0002);
+        // `vrcph` returns the high half of the last result
+        assert_eq!(run_scalar(&mut vu, VUOpcode::VRCPH, 0x0001), 0x3FFF);
+        assert!(vu.div_dp);
+        // 1 / 0x00010000
+        assert_eq!(run_scalar(&mut vu, VUOpcode::VRCPL, 0x0000), 0x7FFF);
+        assert!(!vu.div_dp);
+        assert_eq!(run_scalar(&mut vu, VUOpcode::VRCPH, 0), 0);
+        // without a `vrcph` first, `vrcpl` is single precision
+        vu.div_dp = false;
+        assert_eq!(run_scalar(&mut vu, VUOpcode::VRCPL, 0x0000), 0xFFFF);
+        assert_eq!(vu.div_out, 0x7FFF);
+    }
+
+    #[test]
+    fn vrsq_and_vrsqh() {
+        let mut vu = VuRegs::default();
+        assert_eq!(run_scalar(&mut vu, VUOpcode::VRSQ, 1), 0xC000);
+        assert_eq!(vu.div_out, 0x7FFF);
+        assert_eq!(run_scalar(&mut vu, VUOpcode::VRSQ, 2), 0x4000);
+        assert_eq!(vu.div_out, 0x5A82);
+        assert_eq!(run_scalar(&mut vu, VUOpcode::VRSQ, 4), 0xE000);
+        assert_eq!(vu.div_out, 0x3FFF);
+        assert_eq!(run_scalar(&mut vu, VUOpcode::VRSQH, 0x0004), 0x3FFF);
+        // 1 / sqrt(0x00040000)
+        assert_eq!(run_scalar(&mut vu, VUOpcode::VRSQL, 0), 0xFFE0);
+        assert_eq!(vu.div_out, 0x003F);
+    }
+
+    #[test]
+    fn ltv_transposes_into_eight_registers() {
+        let mem = counting_dmem();
+        let mut vu = VuRegs::default();
+        vu.load(&ls(RspAddressMode::Transpose, 8, 0), 0x20, &mem);
+        for i in 0..8 {
+            let mut lanes = [0; LANES];
+            lanes[i] = (0x2021 + 0x202 * i) as u16;
+            assert_eq!(vu.reg(reg(8 + i as u8)), lanes);
+        }
+
+        // the element picks the first register, and the address wraps in its 16 bytes
+        let mut vu = VuRegs::default();
+        vu.load(&ls(RspAddressMode::Transpose, 10, 2), 0x20, &mem);
+        assert_eq!(vu.reg(reg(9))[0], 0x2223);
+        assert_eq!(vu.reg(reg(15))[6], 0x2E2F);
+        assert_eq!(vu.reg(reg(8))[7], 0x2021);
+    }
+
+    #[test]
+    fn stv_transposes_out_of_eight_registers() {
+        let mut mem = Memory::new();
+        let mut vu = VuRegs::default();
+        for r in 0..8 {
+            let lanes = std::array::from_fn(|n| (r << 12 | n << 8 | r << 4 | n) as u16);
+            vu.set_reg(reg(16 + r as u8), lanes);
+        }
+        vu.store(&ls(RspAddressMode::Transpose, 16, 0), 0x40, &mut mem);
+        let expected = (0..8)
+            .flat_map(|r| [(r << 4 | r) as u8, (r << 4 | r) as u8])
+            .collect::<Vec<_>>();
+        assert_eq!(&mem.dmem()[0x40..0x50], &expected[..]);
+
+        // and back again
+        let mut loaded = VuRegs::default();
+        loaded.load(&ls(RspAddressMode::Transpose, 16, 0), 0x40, &mem);
+        for r in 0..8 {
+            assert_eq!(
+                loaded.reg(reg(16 + r))[r as usize],
+                vu.reg(reg(16 + r))[r as usize]
+            );
+        }
+    }
+
+    #[test]
+    fn swv_rotates_within_eight_bytes() {
+        let mut mem = Memory::new();
+        let mut vu = VuRegs::default();
+        vu.set_reg(reg(1), std::array::from_fn(|n| 0xA0A1 + 0x202 * n as u16));
+        vu.store(&ls(RspAddressMode::Wrap, 1, 0), 0x53, &mut mem);
+        let mut expected = vec![0xAD, 0xAE, 0xAF];
+        expected.extend(0xA0..=0xAC);
+        assert_eq!(&mem.dmem()[0x50..0x60], &expected[..]);
+
+        // DMEM wraps around at its end
+        vu.store(&ls(RspAddressMode::Wrap, 1, 0), 0xFFC, &mut mem);
+        assert_eq!(
+            &mem.dmem()[0xFF8..],
+            [0xAC, 0xAD, 0xAE, 0xAF, 0xA0, 0xA1, 0xA2, 0xA3]
+        );
+        assert_eq!(
+            &mem.dmem()[..8],
+            [0xA4, 0xA5, 0xA6, 0xA7, 0xA8, 0xA9, 0xAA, 0xAB]
+        );
+    }
+
+    #[test]
+    fn lrv_loads_the_bytes_before_the_address() {
+        let mem = counting_dmem();
+        let mut vu = VuRegs::default();
+        vu.load(&ls(RspAddressMode::Rest, 1, 0), 0x18, &mem);
+        let expected = [0, 0, 0, 0, 0x1011, 0x1213, 0x1415, 0x1617];
+        assert_eq!(vu.reg(reg(1)), expected);
+
+        // nothing comes before an aligned address
+        vu.load(&ls(RspAddressMode::Rest, 1, 0), 0x20, &mem);
+        assert_eq!(vu.reg(reg(1)), expected);
+    }
+
+    #[test]
+    fn lpv_and_luv_unpack_bytes() {
+        let mem = counting_dmem();
+        let mut vu = VuRegs::default();
+        vu.load(&ls(RspAddressMode::Pack, 1, 0), 0x10, &mem);
+        assert_eq!(
+            vu.reg(reg(1)),
+            std::array::from_fn(|n| 0x1000 + 0x100 * n as u16)
+        );
+        vu.load(&ls(RspAddressMode::Pack, 1, 0), 0x13, &mem);
+        assert_eq!(
+            vu.reg(reg(1)),
+            std::array::from_fn(|n| 0x1300 + 0x100 * n as u16)
+        );
+        // the element moves the start back, wrapping within the 16 bytes
+        vu.load(&ls(RspAddressMode::Pack, 1, 1), 0x10, &mem);
+        let expected = [
+            0x1F00, 0x1000, 0x1100, 0x1200, 0x1300, 0x1400, 0x1500, 0x1600,
+        ];
+        assert_eq!(vu.reg(reg(1)), expected);
+
+        vu.load(&ls(RspAddressMode::UPack, 1, 0), 0x10, &mem);
+        assert_eq!(
+            vu.reg(reg(1)),
+            std::array::from_fn(|n| 0x0800 + 0x80 * n as u16)
+        );
+    }
+
+    #[test]
+    fn lfv_loads_every_fourth_byte_into_half_a_register() {
+        let mem = counting_dmem();
+        let mut vu = VuRegs::default();
+        vu.set_reg(reg(1), [0xFFFF; LANES]);
+        vu.load(&ls(RspAddressMode::FourthPack, 1, 0), 0x10, &mem);
+        let expected = [0x800, 0xA00, 0xC00, 0xE00, 0xFFFF, 0xFFFF, 0xFFFF, 0xFFFF];
+        assert_eq!(vu.reg(reg(1)), expected);
+
+        vu.load(&ls(RspAddressMode::FourthPack, 1, 8), 0x10, &mem);
+        let expected = [0x800, 0xA00, 0xC00, 0xE00, 0x800, 0xA00, 0xC00, 0xE00];
+        assert_eq!(vu.reg(reg(1)), expected);
+    }
+}
@@ -1,10 +1,16 @@
-use crate::print::{Print, PrintOpts};
+use crate::print::{Formatter, Print, PrintOpts};
 use std::fmt::{self, Write};
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Sym {
+    /// jump target or subroutine (`subr_XXXXXXXX`)
     Global(u32),
+    /// branch target, local to its function
     Static(u32),
+    /// handler reached through a jump table (`handler_XXXXXXXX`)
+    Handler(u32),
+    /// DMEM data accessed by a load or store (`data_0xXXXX`)
+    Data(u32),
 }
 
 impl Sym {
@@ -16,31 +22,36 @@ impl Sym {
         let target = (vaddr + 4) as i32 + ((imm as i32) * 4);
         Self::Static(target as u32)
     }
+    /// the 26-bit instruction index field of a jump to this symbol
+    pub(crate) const fn jmp_field(&self) -> u32 {
+        (self.value() >> 2) & 0x03FFFFFF
+    }
+    /// the 16-bit offset field of a branch at `vaddr` to this symbol
+    pub(crate) const fn branch_field(&self, vaddr: u32) -> u32 {
+        let delta = self.value().wrapping_sub(vaddr.wrapping_add(4)) as i32;
+        ((delta >> 2) as u32) & 0xFFFF
+    }
     pub const fn value(&self) -> u32 {
         match self {
             Self::Global(v) => *v,
             Self::Static(v) => *v,
+            Self::Handler(v) => *v,
+            Self::Data(v) => *v,
         }
     }
     pub const fn is_global(&self) -> bool {
-        match self {
-            Self::Global(_) => true,
-            _ => false,
-        }
+        matches!(self, Self::Global(_) | Self::Handler(_) | Self::Data(_))
     }
 }
 
 impl Print for Sym {
-    fn print(&self, _opts: PrintOpts, w: &mut impl Write) -> fmt::Result {
-        match self {
-            Self::Global(addr) => write!(w, "subr_{:08X}", addr),
-            Self::Static(addr) => write!(w, "@L{:08X}", addr),
-        }
+    fn print(&self, f: &dyn Formatter, w: &mut dyn Write) -> fmt::Result {
+        f.label(*self, w)
     }
 }
 
 impl fmt::Display for Sym {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.print(PrintOpts::default(), f)
+        self.print(&PrintOpts::default(), f)
     }
 }
@@ -0,0 +1,664 @@
+//! User-supplied names for code and data.
+//!
+//! A symbol file has one symbol per line:
+//!
+//! ```text
+//! # name = address [size] [type]
+//! main_loop = 0x04001080
+//! gfx_dispatch = 0x0400 0x40 half
+//! tmp = 0x0F00 16
+//! ```
+//!
+//! The type is one of `code`, `data`, `byte`, `half` or `word`. Without one,
+//! addresses in `0x04000000..0x04001000` and below `0x1000` name DMEM and any
+//! other address names IMEM. Code is matched on its full address, as the
+//! microcode's jumps see it, so name a routine by the address it is listed at.
+//! Data is only matched on its low 12 bits, as the RSP addresses DMEM, so a
+//! variable can be named by its RDRAM or DMEM address. `#`, `//` and `;` start
+//! comments.
+//!
+//! Symbols can also be imported from the linker map or splat `symbol_addrs.txt`
+//! of a whole program, keeping those that fall in the RSP's memory.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write},
+};
+
+use crate::{
+    print::{Disp, Formatter},
+    regs::{
+        cop0::Cop0Reg,
+        su::GpReg,
+        vu::{Element, VUCtrlReg, VUReg},
+    },
+    Sym,
+};
+
+/// mask of an address within IMEM or DMEM
+const MEM_MASK: u32 = 0xFFF;
+
+/// What a symbol names, which decides its address space
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolType {
+    /// an IMEM label
+    Code,
+    /// untyped DMEM data
+    Data,
+    Byte,
+    Half,
+    Word,
+}
+
+impl SymbolType {
+    /// the type of an untyped symbol at `addr`
+    pub const fn infer(addr: u32) -> Self {
+        match addr {
+            0..=0x0FFF | 0x0400_0000..=0x0400_0FFF => Self::Data,
+            _ => Self::Code,
+        }
+    }
+
+    pub const fn is_code(&self) -> bool {
+        matches!(self, Self::Code)
+    }
+
+    /// size of each item of data of this type, if it has one
+    pub const fn item_size(&self) -> Option<u32> {
+        match self {
+            Self::Byte => Some(1),
+            Self::Half => Some(2),
+            Self::Word => Some(4),
+            Self::Code | Self::Data => None,
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        let ty = match s {
+            "code" => Self::Code,
+            "data" => Self::Data,
+            "byte" => Self::Byte,
+            "half" => Self::Half,
+            "word" => Self::Word,
+            _ => return None,
+        };
+
+        Some(ty)
+    }
+}
+
+/// A user-supplied name for an address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub addr: u32,
+    /// bytes covered, if known
+    pub size: Option<u32>,
+    pub ty: SymbolType,
+}
+
+impl Symbol {
+    /// an untyped symbol, placed in IMEM or DMEM by its address
+    pub fn new(name: impl Into<String>, addr: u32) -> Self {
+        Self {
+            name: name.into(),
+            addr,
+            size: None,
+            ty: SymbolType::infer(addr),
+        }
+    }
+
+    /// address within IMEM or DMEM
+    pub const fn offset(&self) -> u32 {
+        self.addr & MEM_MASK
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolError {
+    /// one-based line of the error
+    pub line: usize,
+    pub kind: SymbolErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SymbolErrorKind {
+    /// the line is not `name = address`
+    Syntax,
+    BadName(String),
+    BadNumber(String),
+    UnknownType(String),
+    DuplicateName(String),
+    /// a symbol was given a size of zero
+    ZeroSize(String),
+}
+
+impl fmt::Display for SymbolErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Syntax => write!(f, "expected `name = address [size] [type]`"),
+            Self::BadName(n) => write!(f, "`{}` is not a valid label name", n),
+            Self::BadNumber(n) => write!(f, "could not parse number `{}`", n),
+            Self::UnknownType(t) => write!(f, "unknown symbol type `{}`", t),
+            Self::DuplicateName(n) => write!(f, "symbol `{}` defined more than once", n),
+            Self::ZeroSize(n) => write!(f, "symbol `{}` has a size of zero", n),
+        }
+    }
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl std::error::Error for SymbolError {}
+
+/// User-supplied names that replace generated labels
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Symbols {
+    /// by address
+    code: BTreeMap<u32, Symbol>,
+    /// by DMEM address
+    data: BTreeMap<u32, Symbol>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a symbol file, as described in the [module docs](self)
+    pub fn parse(src: &str) -> Result<Self, SymbolError> {
+        let mut symbols = Self::new();
+        for (i, line) in src.lines().enumerate() {
+            let at_line = |kind| SymbolError { line: i + 1, kind };
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let sym = parse_line(line).map_err(at_line)?;
+            if symbols.iter().any(|s| s.name == sym.name) {
+                return Err(at_line(SymbolErrorKind::DuplicateName(sym.name)));
+            }
+            symbols.insert(sym);
+        }
+
+        Ok(symbols)
+    }
+
+    /// add `sym`, replacing any symbol already at its address
+    pub fn insert(&mut self, sym: Symbol) {
+        if sym.ty.is_code() {
+            self.code.insert(sym.addr, sym);
+        } else {
+            self.data.insert(sym.offset(), sym);
+        }
+    }
+
+    /// name the IMEM label at `addr`
+    pub fn insert_code(&mut self, addr: u32, name: impl Into<String>) {
+        self.insert(Symbol {
+            ty: SymbolType::Code,
+            ..Symbol::new(name, addr)
+        });
+    }
+
+    /// name the DMEM variable at `addr`
+    pub fn insert_data(&mut self, addr: u32, name: impl Into<String>) {
+        self.insert(Symbol {
+            ty: SymbolType::Data,
+            ..Symbol::new(name, addr)
+        });
+    }
+
+    /// the user's name for `sym`, if there is one
+    pub fn name(&self, sym: Sym) -> Option<&str> {
+        let found = match sym {
+            Sym::Data(addr) => self.data.get(&(addr & MEM_MASK)),
+            Sym::Global(addr) | Sym::Static(addr) | Sym::Handler(addr) => self.code.get(&addr),
+        };
+        found.map(|s| s.name.as_str())
+    }
+
+    /// every IMEM symbol, by address
+    pub fn code(&self) -> impl Iterator<Item = &Symbol> {
+        self.code.values()
+    }
+
+    /// every DMEM symbol, by address
+    pub fn data(&self) -> impl Iterator<Item = &Symbol> {
+        self.data.values()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.code().chain(self.data())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty() && self.data.is_empty()
+    }
+
+    /// Read the RSP's symbols from a splat `symbol_addrs.txt`, as laid out by `layout`.
+    ///
+    /// Lines are `name = 0xADDR; // type:u16 size:0x10`, where the attributes are
+    /// optional and any others are ignored.
+    pub fn parse_splat(src: &str, layout: Layout) -> Result<Self, SymbolError> {
+        let mut symbols = Self::new();
+        for (i, line) in src.lines().enumerate() {
+            let at_line = |kind| SymbolError { line: i + 1, kind };
+            let (def, attrs) = line.split_once("//").unwrap_or((line, ""));
+            let def = def.trim().trim_end_matches(';').trim_end();
+            if def.is_empty() {
+                continue;
+            }
+
+            let (name, addr) = def
+                .split_once('=')
+                .ok_or(at_line(SymbolErrorKind::Syntax))?;
+            let name = parse_name(name).map_err(at_line)?;
+            let addr = parse_number(addr.trim()).map_err(at_line)?;
+            let mut sym = match layout.place(addr) {
+                Some((addr, ty)) => Symbol {
+                    ty,
+                    ..Symbol::new(name, addr)
+                },
+                None => continue,
+            };
+            for (key, value) in attrs.split_whitespace().filter_map(|a| a.split_once(':')) {
+                match key {
+                    "size" => sym.size = Some(parse_size(name, value).map_err(at_line)?),
+                    "type" if !sym.ty.is_code() => sym.ty = splat_type(value),
+                    _ => (),
+                }
+            }
+            symbols.insert(sym);
+        }
+
+        Ok(symbols)
+    }
+
+    /// Read the RSP's symbols from a GNU ld map file, as laid out by `layout`.
+    ///
+    /// Only the `0xADDR name` lines that list symbols are read; section and
+    /// input file lines, and symbols outside the RSP, are skipped.
+    pub fn parse_ld_map(src: &str, layout: Layout) -> Self {
+        let mut symbols = Self::new();
+        for line in src.lines() {
+            let mut fields = line.split_whitespace();
+            let (addr, name) = match (fields.next(), fields.next(), fields.next()) {
+                // `0xADDR name`, or an assignment `0xADDR name = expr`
+                (Some(addr), Some(name), None | Some("=")) => (addr, name),
+                _ => continue,
+            };
+            let addr = match addr.strip_prefix("0x").map(|a| u64::from_str_radix(a, 16)) {
+                Some(Ok(addr)) => addr as u32,
+                _ => continue,
+            };
+            let (name, (addr, ty)) = match (parse_name(name), layout.place(addr)) {
+                (Ok(name), Some(placed)) => (name, placed),
+                _ => continue,
+            };
+            symbols.insert(Symbol {
+                ty,
+                ..Symbol::new(name, addr)
+            });
+        }
+
+        symbols
+    }
+}
+
+/// Where microcode was linked, to pick the RSP's symbols out of a whole program's
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    /// address the code was linked at
+    pub vram: u32,
+    /// size of the code
+    pub size: u32,
+}
+
+impl Layout {
+    /// The address of `addr` in the disassembly, and whether it is in IMEM or
+    /// DMEM, or `None` if it is not an RSP address.
+    ///
+    /// DMEM is at `0x04000000`, and IMEM at `vram` and `0x04001000`, which is
+    /// moved to `vram`.
+    pub const fn place(&self, addr: u32) -> Option<(u32, SymbolType)> {
+        match addr {
+            0x0400_0000..=0x0400_0FFF => Some((addr, SymbolType::Data)),
+            0x0400_1000..=0x0400_1FFF => {
+                Some((self.vram.wrapping_add(addr & MEM_MASK), SymbolType::Code))
+            }
+            _ if addr.wrapping_sub(self.vram) < self.size => Some((addr, SymbolType::Code)),
+            _ => None,
+        }
+    }
+}
+
+/// the type of a DMEM symbol with splat type `ty`
+fn splat_type(ty: &str) -> SymbolType {
+    match ty {
+        "u8" | "s8" | "char" => SymbolType::Byte,
+        "u16" | "s16" => SymbolType::Half,
+        "u32" | "s32" | "f32" | "ptr" => SymbolType::Word,
+        _ => SymbolType::Data,
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    ["#", "//", ";"]
+        .iter()
+        .filter_map(|c| line.find(c))
+        .min()
+        .map_or(line, |i| &line[..i])
+}
+
+/// `name = address [size] [type]`
+fn parse_line(line: &str) -> Result<Symbol, SymbolErrorKind> {
+    let (name, rest) = line.split_once('=').ok_or(SymbolErrorKind::Syntax)?;
+    let name = parse_name(name)?;
+    let mut fields = rest
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|f| !f.is_empty());
+    let addr = fields.next().ok_or(SymbolErrorKind::Syntax)?;
+    let mut sym = Symbol::new(name, parse_number(addr)?);
+    for field in fields {
+        match SymbolType::parse(field) {
+            Some(ty) => sym.ty = ty,
+            None if field.starts_with(|c: char| c.is_ascii_digit()) => {
+                sym.size = Some(parse_size(&sym.name, field)?)
+            }
+            None => return Err(SymbolErrorKind::UnknownType(field.to_string())),
+        }
+    }
+
+    Ok(sym)
+}
+
+/// the size `s` of the symbol `name`, which cannot be empty
+fn parse_size(name: &str, s: &str) -> Result<u32, SymbolErrorKind> {
+    match parse_number(s)? {
+        0 => Err(SymbolErrorKind::ZeroSize(name.to_string())),
+        size => Ok(size),
+    }
+}
+
+/// `name`, if it is usable as a label: an identifier, which may also use `.`
+/// and `@` but not only them, so that the location counter `.` is not a name
+pub(crate) fn parse_name(name: &str) -> Result<&str, SymbolErrorKind> {
+    let name = name.trim();
+    let word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let valid = |c: char| word(c) || matches!(c, '.' | '@');
+    if !name.chars().any(word)
+        || name.starts_with(|c: char| c.is_ascii_digit())
+        || !name.chars().all(valid)
+    {
+        return Err(SymbolErrorKind::BadName(name.to_string()));
+    }
+
+    Ok(name)
+}
+
+pub(crate) fn parse_number(s: &str) -> Result<u32, SymbolErrorKind> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| SymbolErrorKind::BadNumber(s.to_string()))
+}
+
+/// A [`Formatter`] that names labels from [`Symbols`], leaving everything else to `inner`
+pub(crate) struct Named<'a> {
+    inner: &'a dyn Formatter,
+    symbols: Option<&'a Symbols>,
+}
+
+impl<'a> Named<'a> {
+    pub(crate) fn new(inner: &'a dyn Formatter, symbols: Option<&'a Symbols>) -> Self {
+        Self { inner, symbols }
+    }
+}
+
+impl Formatter for Named<'_> {
+    fn mnemonic(&self, mnemonic: &str, w: &mut dyn Write) -> fmt::Result {
+        self.inner.mnemonic(mnemonic, w)
+    }
+
+    fn separator(&self, w: &mut dyn Write) -> fmt::Result {
+        self.inner.separator(w)
+    }
+
+    fn gp_reg(&self, reg: GpReg, w: &mut dyn Write) -> fmt::Result {
+        self.inner.gp_reg(reg, w)
+    }
+
+    fn cop0_reg(&self, reg: Cop0Reg, w: &mut dyn Write) -> fmt::Result {
+        self.inner.cop0_reg(reg, w)
+    }
+
+    fn vu_reg(&self, reg: VUReg, w: &mut dyn Write) -> fmt::Result {
+        self.inner.vu_reg(reg, w)
+    }
+
+    fn vu_ctrl_reg(&self, reg: VUCtrlReg, w: &mut dyn Write) -> fmt::Result {
+        self.inner.vu_ctrl_reg(reg, w)
+    }
+
+    fn element(&self, e: Element, w: &mut dyn Write) -> fmt::Result {
+        self.inner.element(e, w)
+    }
+
+    fn element_index(&self, index: u8, w: &mut dyn Write) -> fmt::Result {
+        self.inner.element_index(index, w)
+    }
+
+    fn imm(&self, imm: i32, w: &mut dyn Write) -> fmt::Result {
+        self.inner.imm(imm, w)
+    }
+
+    fn hex_imm(&self, imm: u16, w: &mut dyn Write) -> fmt::Result {
+        self.inner.hex_imm(imm, w)
+    }
+
+    fn offset(&self, disp: Disp, base: GpReg, w: &mut dyn Write) -> fmt::Result {
+        self.inner.offset(disp, base, w)
+    }
+
+    fn vector_offset(&self, disp: Disp, size: u8, base: GpReg, w: &mut dyn Write) -> fmt::Result {
+        self.inner.vector_offset(disp, size, base, w)
+    }
+
+    fn label(&self, sym: Sym, w: &mut dyn Write) -> fmt::Result {
+        match self.symbols.and_then(|s| s.name(sym)) {
+            Some(name) => w.write_str(name),
+            None => self.inner.label(sym, w),
+        }
+    }
+
+    fn func_start(&self, name: &str, w: &mut dyn Write) -> fmt::Result {
+        self.inner.func_start(name, w)
+    }
+
+    fn func_end(&self, name: &str, w: &mut dyn Write) -> fmt::Result {
+        self.inner.func_end(name, w)
+    }
+
+    fn comment(&self, text: &str, w: &mut dyn Write) -> fmt::Result {
+        self.inner.comment(text, w)
+    }
+
+    fn word(&self, value: u32, w: &mut dyn Write) -> fmt::Result {
+        self.inner.word(value, w)
+    }
+
+    fn data(&self, size: u32, values: &[u32], w: &mut dyn Write) -> fmt::Result {
+        self.inner.data(size, values, w)
+    }
+
+    fn line(&self, addr: u32, word: u32, instr: &str, w: &mut dyn Write) -> fmt::Result {
+        self.inner.line(addr, word, instr, w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUT: Layout = Layout {
+        vram: 0x8400_0000,
+        size: 0x100,
+    };
+
+    fn names(symbols: &Symbols) -> Vec<(&str, u32, SymbolType)> {
+        symbols
+            .iter()
+            .map(|s| (s.name.as_str(), s.addr, s.ty))
+            .collect()
+    }
+
+    #[test]
+    fn splat_imem_moves_to_vram() {
+        let src = "\
+            loop = 0x04001010;\n\
+            main = 0x84000020; // size:0x8\n\
+            buf = 0x04000040; // type:u16\n\
+            far = 0x80001000;\n";
+        let symbols = Symbols::parse_splat(src, LAYOUT).unwrap();
+        assert_eq!(
+            names(&symbols),
+            [
+                ("loop", 0x8400_0010, SymbolType::Code),
+                ("main", 0x8400_0020, SymbolType::Code),
+                ("buf", 0x0400_0040, SymbolType::Half),
+            ]
+        );
+        assert_eq!(symbols.name(Sym::Static(0x8400_0010)), Some("loop"));
+        assert_eq!(symbols.name(Sym::Data(0x40)), Some("buf"));
+    }
+
+    #[test]
+    fn ld_map_imem_moves_to_vram() {
+        let src = "\
+ .text          0x0000000084000000      0x100 rsp.o\n\
+                0x0000000004001010                loop\n\
+                0x0000000084000020                main\n\
+                0x0000000004000040                buf = .\n";
+        let layout = Layout {
+            vram: 0xA400_1000,
+            ..LAYOUT
+        };
+        let symbols = Symbols::parse_ld_map(src, layout);
+        assert_eq!(
+            names(&symbols),
+            [
+                ("loop", 0xA400_1010, SymbolType::Code),
+                ("buf", 0x0400_0040, SymbolType::Data),
+            ]
+        );
+        assert_eq!(symbols.name(Sym::Global(0xA400_1010)), Some("loop"));
+    }
+
+    #[test]
+    fn ld_map_skips_location_counter() {
+        let src = "\
+                0x0000000084000000                . = ALIGN (0x8)\n\
+                0x0000000084000008                .L_loop\n\
+                0x0000000084000010                . = 0x84000010\n\
+                0x0000000084000018                *fill*\n\
+                0x0000000084000020                @@\n\
+                0x0000000084000028                _start\n";
+        let symbols = Symbols::parse_ld_map(src, LAYOUT);
+        assert_eq!(
+            names(&symbols),
+            [
+                (".L_loop", 0x8400_0008, SymbolType::Code),
+                ("_start", 0x8400_0028, SymbolType::Code),
+            ]
+        );
+    }
+
+    #[test]
+    fn label_names() {
+        for good in ["a", "_x1", ".L0", "@L84000000", "data.buf"] {
+            assert_eq!(parse_name(good), Ok(good));
+        }
+        for bad in ["", ".", "..", "@", "1abc", "a-b", "*fill*"] {
+            assert_eq!(
+                parse_name(bad),
+                Err(SymbolErrorKind::BadName(bad.to_string()))
+            );
+        }
+        let err = Symbols::parse(". = 0x84000000").unwrap_err();
+        assert_eq!(err.kind, SymbolErrorKind::BadName(".".to_string()));
+    }
+
+    #[test]
+    fn parse_symbol_file() {
+        let src = "\
+            # microcode entry\n\
+            main_loop = 0x04001080\n\
+            gfx_dispatch = 0x0400 0x40 half // jump table\n\
+            \n\
+            tmp = 0x04000F00, 16 ; scratch\n\
+            done = 0x84000100 code\n";
+        let symbols = Symbols::parse(src).unwrap();
+        assert_eq!(
+            names(&symbols),
+            [
+                ("main_loop", 0x0400_1080, SymbolType::Code),
+                ("done", 0x8400_0100, SymbolType::Code),
+                ("gfx_dispatch", 0x400, SymbolType::Half),
+                ("tmp", 0x0400_0F00, SymbolType::Data),
+            ]
+        );
+        let sizes = symbols.data().map(|s| s.size).collect::<Vec<_>>();
+        assert_eq!(sizes, [Some(0x40), Some(16)]);
+    }
+
+    #[test]
+    fn code_matches_full_address_and_data_low_bits() {
+        let symbols = Symbols::parse("main = 0x04001080\nbuf = 0x04000040").unwrap();
+        assert_eq!(symbols.name(Sym::Global(0x0400_1080)), Some("main"));
+        assert_eq!(symbols.name(Sym::Static(0x0400_1080)), Some("main"));
+        assert_eq!(symbols.name(Sym::Global(0x080)), None);
+        assert_eq!(symbols.name(Sym::Global(0x8400_1080)), None);
+        assert_eq!(symbols.name(Sym::Data(0x040)), Some("buf"));
+        assert_eq!(symbols.name(Sym::Data(0x0400_0040)), Some("buf"));
+        assert_eq!(symbols.name(Sym::Data(0x0400_0080)), None);
+    }
+
+    #[test]
+    fn symbol_file_errors() {
+        let err = |src: &str| Symbols::parse(src).unwrap_err();
+        assert_eq!(
+            err("a = 0x10\nb = 0x20 0"),
+            SymbolError {
+                line: 2,
+                kind: SymbolErrorKind::ZeroSize("b".to_string()),
+            }
+        );
+        assert_eq!(
+            err("a = 0x10\n\na = 0x20").kind,
+            SymbolErrorKind::DuplicateName("a".to_string())
+        );
+        assert_eq!(
+            err("a = 0x20 float").kind,
+            SymbolErrorKind::UnknownType("float".to_string())
+        );
+        assert_eq!(
+            err("a = 0xZZ").kind,
+            SymbolErrorKind::BadNumber("0xZZ".to_string())
+        );
+        assert_eq!(err("a 0x10").kind, SymbolErrorKind::Syntax);
+        assert_eq!(err("a =").kind, SymbolErrorKind::Syntax);
+    }
+
+    #[test]
+    fn splat_rejects_zero_size() {
+        let err = Symbols::parse_splat("\nbuf = 0x04000040; // size:0", LAYOUT).unwrap_err();
+        assert_eq!(err.line, 2);
+        assert_eq!(err.kind, SymbolErrorKind::ZeroSize("buf".to_string()));
+    }
+}
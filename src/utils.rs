@@ -1,4 +1,4 @@
-use std::fmt;
+use std::{fmt, io};
 
 /// extract a u8 of `size` bits from bit `b` from `src`
 pub(crate) fn u8_at(b: u8, size: u8, src: u32) -> u8 {
@@ -11,6 +11,13 @@ pub(crate) fn i8_at(b: u8, size: u8, src: u32) -> i8 {
     ((base << (8 - size)) as i8) >> (8 - size)
 }
 
+/// collect the output of a printer into a new string
+pub(crate) fn render(print: impl FnOnce(&mut String) -> fmt::Result) -> String {
+    let mut s = String::new();
+    print(&mut s).unwrap();
+    s
+}
+
 /// convience wrapper for pretty printing load/store offsets
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) struct Offset(pub(crate) i16);
@@ -21,9 +28,52 @@ impl fmt::Display for Offset {
         if v < 0x10 && v > -0x10 {
             write!(f, "{}", v)
         } else if v.is_negative() {
-            write!(f, "-{:#x}", -v)
+            write!(f, "-{:#x}", v.unsigned_abs())
         } else {
             write!(f, "{:#x}", v)
         }
     }
 }
+
+/// adapts an `io::Write` for the `fmt::Write` based printers, keeping the io error
+pub(crate) struct IoWriter<'a, W: io::Write> {
+    inner: &'a mut W,
+    error: Option<io::Error>,
+}
+
+impl<'a, W: io::Write> IoWriter<'a, W> {
+    pub(crate) fn new(inner: &'a mut W) -> Self {
+        Self { inner, error: None }
+    }
+
+    /// the error that made the last write fail
+    pub(crate) fn into_error(self) -> io::Error {
+        self.error
+            .unwrap_or_else(|| io::Error::other("formatter error"))
+    }
+}
+
+impl<W: io::Write> fmt::Write for IoWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets() {
+        let text = |v| Offset(v).to_string();
+        assert_eq!(text(0), "0");
+        assert_eq!(text(-0xF), "-15");
+        assert_eq!(text(0x10), "0x10");
+        assert_eq!(text(-0x10), "-0x10");
+        assert_eq!(text(i16::MAX), "0x7fff");
+        assert_eq!(text(i16::MIN), "-0x8000");
+    }
+}
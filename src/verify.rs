@@ -0,0 +1,129 @@
+use std::fmt;
+
+use crate::{
+    assemble::{self, AsmErrorKind},
+    decode_bytes,
+    print::Print,
+    PrintOpts, RspDisasmError, Syntax,
+};
+
+/// An instruction whose printed form does not assemble back to the original word
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub addr: u32,
+    /// original instruction word
+    pub word: u32,
+    /// the disassembled text of `word`
+    pub text: String,
+    /// the word assembled from `text`, or why it could not be assembled
+    pub reencoded: Result<u32, AsmErrorKind>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08X}: {:08X} -> ", self.addr, self.word)?;
+        match &self.reencoded {
+            Ok(w) => write!(f, "{:08X}", w)?,
+            Err(e) => write!(f, "({})", e)?,
+        }
+        write!(f, "\t{}", self.text)
+    }
+}
+
+/// Disassemble `data` with `opts`, then assemble every line again and report
+/// each word that does not reproduce the original bytes.
+///
+/// The built-in assembler reads armips and bass syntax, so GNU syntax is checked as armips.
+pub fn verify_bytes(
+    data: &[u8],
+    vaddr: u32,
+    opts: PrintOpts,
+) -> Result<Vec<Mismatch>, RspDisasmError> {
+    let syntax = match opts.syntax {
+        Syntax::Bass => Syntax::Bass,
+        Syntax::Armips | Syntax::Gnu => Syntax::Armips,
+    };
+    let opts = PrintOpts { syntax, ..opts };
+    let mismatches = decode_bytes(data, vaddr)?
+        .into_iter()
+        .filter_map(|instr| {
+            let mut text = String::new();
+            instr.op.print(&opts, &mut text).unwrap();
+            let reencoded = assemble::assemble_instruction_as(&text, instr.addr, syntax);
+
+            (reencoded != Ok(instr.word)).then_some(Mismatch {
+                addr: instr.addr,
+                word: instr.word,
+                text,
+                reencoded,
+            })
+        })
+        .collect();
+
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble::assemble_instruction_as;
+
+    const VADDR: u32 = 0x0400_1000;
+
+    /// every LWC2 and SWC2 word with base `a0` and vt `$v1`
+    fn vector_loads_and_stores() -> Vec<u8> {
+        let mut data = Vec::new();
+        for op in [0x32, 0x3A] {
+            for mode in 0..12 {
+                for e in 0..16 {
+                    for offset in 0..0x80 {
+                        let word =
+                            (op << 26) | (4 << 21) | (1 << 16) | (mode << 11) | (e << 7) | offset;
+                        data.extend(u32::to_be_bytes(word));
+                    }
+                }
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn vector_loads_and_stores_reassemble() {
+        let data = vector_loads_and_stores();
+        for syntax in [Syntax::Armips, Syntax::Bass] {
+            let opts = PrintOpts {
+                syntax,
+                ..PrintOpts::default()
+            };
+            assert_eq!(
+                verify_bytes(&data, VADDR, opts).unwrap(),
+                [],
+                "{:?}",
+                syntax
+            );
+        }
+    }
+
+    #[test]
+    fn bass_vector_offsets_count_items() {
+        let opts = PrintOpts {
+            syntax: Syntax::Bass,
+            ..PrintOpts::default()
+        };
+        let text = |word: u32| {
+            let instr = decode_bytes(&word.to_be_bytes(), VADDR).unwrap();
+            let mut text = String::new();
+            instr[0].op.print(&opts, &mut text).unwrap();
+            text
+        };
+        assert_eq!(text(0xC8812001), "lqv v1[e0], 1(a0)");
+        assert_eq!(text(0xE88119FF), "sdv v1[e3], -1(a0)");
+
+        let lqv = |src| assemble_instruction_as(src, VADDR, Syntax::Bass);
+        assert_eq!(lqv("lqv v1[e0], (data_0x0110+0x10)/16(a0)"), Ok(0xC8812012));
+        assert_eq!(
+            lqv("lqv v1[e0], (data_0x0118)/16(a0)"),
+            Err(AsmErrorKind::OutOfRange(0x118))
+        );
+    }
+}